@@ -0,0 +1,116 @@
+//! Builds the upstream FreeRTOS kernel with its POSIX port and generates the bindings.
+//!
+//! The kernel sources are not vendored, point `FREERTOS_KERNEL_PATH` to a checkout of
+//! [FreeRTOS-Kernel](https://github.com/FreeRTOS/FreeRTOS-Kernel) (V11.1.0 or newer).
+//! A different `FreeRTOSConfig.h` can be used by setting `FREERTOS_CONFIG_PATH` to the
+//! directory containing it.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+const KERNEL_SOURCES: &[&str] = &[
+    "tasks.c",
+    "queue.c",
+    "list.c",
+    "timers.c",
+    "event_groups.c",
+    "stream_buffer.c",
+    "portable/MemMang/heap_3.c",
+    "portable/ThirdParty/GCC/Posix/port.c",
+    "portable/ThirdParty/GCC/Posix/utils/wait_for_event.c",
+];
+
+const PORT_INCLUDES: &[&str] = &[
+    "include",
+    "portable/ThirdParty/GCC/Posix",
+    "portable/ThirdParty/GCC/Posix/utils",
+];
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    println!("cargo:rerun-if-env-changed=FREERTOS_KERNEL_PATH");
+    println!("cargo:rerun-if-env-changed=FREERTOS_CONFIG_PATH");
+
+    let kernel = match env::var_os("FREERTOS_KERNEL_PATH") {
+        Some(path) => PathBuf::from(path),
+        None => panic!(
+            "FREERTOS_KERNEL_PATH is not set, it must point to a checkout of the FreeRTOS-Kernel repository"
+        ),
+    };
+    if !kernel.join("tasks.c").is_file() {
+        panic!(
+            "FREERTOS_KERNEL_PATH ({}) does not contain the FreeRTOS kernel sources",
+            kernel.display()
+        );
+    }
+    let config = env::var_os("FREERTOS_CONFIG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| manifest_dir.join("config"));
+
+    let includes: Vec<PathBuf> = PORT_INCLUDES
+        .iter()
+        .map(|dir| kernel.join(dir))
+        .chain([config.clone(), manifest_dir.join("src")].iter().cloned())
+        .collect();
+
+    build_kernel(&kernel, &includes, &manifest_dir);
+    generate_bindings(&kernel, &config, &includes, &manifest_dir, &out_dir);
+
+    println!("cargo:rerun-if-changed={}", config.display());
+    println!("cargo:rerun-if-changed=src/shim.c");
+    println!("cargo:rerun-if-changed=src/shim.h");
+    println!("cargo:rustc-link-lib=pthread");
+    println!("cargo:include={}", config.display());
+}
+
+fn build_kernel(kernel: &Path, includes: &[PathBuf], manifest_dir: &Path) {
+    let mut build = cc::Build::new();
+    build
+        .files(KERNEL_SOURCES.iter().map(|file| kernel.join(file)))
+        .file(manifest_dir.join("src/shim.c"))
+        .includes(includes)
+        .flag("-pthread")
+        .warnings(false);
+    if env::var_os("CARGO_FEATURE_TRACE").is_some() {
+        build.define("FREERTOS_RS_TRACE", None);
+    }
+
+    build.compile("freertos");
+}
+
+fn generate_bindings(
+    kernel: &Path,
+    config: &Path,
+    includes: &[PathBuf],
+    manifest_dir: &Path,
+    out_dir: &Path,
+) {
+    bindgen::Builder::default()
+        .header(manifest_dir.join("src/shim.h").to_string_lossy())
+        .clang_args(includes.iter().map(|dir| format!("-I{}", dir.display())))
+        .use_core()
+        .ctypes_prefix("core::ffi")
+        .layout_tests(false)
+        .allowlist_file(format!("{}.*", regex_escape(kernel)))
+        .allowlist_file(format!("{}.*", regex_escape(config)))
+        .allowlist_file(format!("{}.*", regex_escape(&manifest_dir.join("src"))))
+        .allowlist_function("strlen")
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        .generate()
+        .expect("failed to generate the FreeRTOS bindings")
+        .write_to_file(out_dir.join("bindings.rs"))
+        .expect("failed to write the FreeRTOS bindings");
+}
+
+fn regex_escape(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.display().to_string().chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
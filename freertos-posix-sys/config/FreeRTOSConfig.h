@@ -0,0 +1,87 @@
+/*
+ * Kernel configuration for running the `freertos` crate on the POSIX simulator port.
+ *
+ * Values that end up in the generated bindings (e.g. `configTICK_RATE_HZ`) must be
+ * plain integer literals, bindgen skips macros containing casts.
+ */
+
+#ifndef FREERTOS_CONFIG_H
+#define FREERTOS_CONFIG_H
+
+#define configUSE_PREEMPTION                       1
+#define configUSE_TIME_SLICING                     1
+#define configUSE_PORT_OPTIMISED_TASK_SELECTION    0
+#define configTICK_RATE_HZ                         1000
+#define configMAX_PRIORITIES                       25
+#define configMINIMAL_STACK_SIZE                   4096
+#define configMAX_TASK_NAME_LEN                    16
+#define configSTACK_DEPTH_TYPE                     uint16_t
+#define configTICK_TYPE_WIDTH_IN_BITS              TICK_TYPE_WIDTH_32_BITS
+#define configIDLE_SHOULD_YIELD                    1
+
+#define configSUPPORT_DYNAMIC_ALLOCATION           1
+#define configSUPPORT_STATIC_ALLOCATION            1
+/* Idle and timer task memory comes from the kernel, no application callbacks needed. */
+#define configKERNEL_PROVIDED_STATIC_MEMORY        1
+
+#define configNUM_THREAD_LOCAL_STORAGE_POINTERS    4
+#define configUSE_TASK_NOTIFICATIONS               1
+#define configTASK_NOTIFICATION_ARRAY_ENTRIES      4
+#define configUSE_MUTEXES                          1
+#define configUSE_RECURSIVE_MUTEXES                1
+#define configUSE_COUNTING_SEMAPHORES              1
+#define configUSE_QUEUE_SETS                       1
+#define configQUEUE_REGISTRY_SIZE                  0
+
+#define configUSE_TIMERS                           1
+#define configTIMER_TASK_PRIORITY                  ( configMAX_PRIORITIES - 1 )
+#define configTIMER_QUEUE_LENGTH                   20
+#define configTIMER_TASK_STACK_DEPTH               ( configMINIMAL_STACK_SIZE * 2 )
+
+#define configUSE_IDLE_HOOK                        0
+#define configUSE_TICK_HOOK                        0
+#define configUSE_MALLOC_FAILED_HOOK               0
+#define configUSE_DAEMON_TASK_STARTUP_HOOK         0
+#define configCHECK_FOR_STACK_OVERFLOW             0
+
+/* Required by `uxTaskGetSystemState`, the run time counter is provided by the port. */
+#define configUSE_TRACE_FACILITY                   1
+#define configGENERATE_RUN_TIME_STATS              1
+
+#define INCLUDE_vTaskDelete                        1
+#define INCLUDE_vTaskDelay                         1
+#define INCLUDE_xTaskDelayUntil                    1
+#define INCLUDE_vTaskSuspend                       1
+#define INCLUDE_xTaskResumeFromISR                 1
+#define INCLUDE_xTaskAbortDelay                    1
+#define INCLUDE_uxTaskPriorityGet                  1
+#define INCLUDE_vTaskPrioritySet                   1
+#define INCLUDE_eTaskGetState                      1
+#define INCLUDE_xTaskGetCurrentTaskHandle          1
+#define INCLUDE_xTaskGetIdleTaskHandle             1
+#define INCLUDE_uxTaskGetStackHighWaterMark        1
+#define INCLUDE_xTaskGetSchedulerState             1
+#define INCLUDE_xTimerPendFunctionCall             1
+#define INCLUDE_xSemaphoreGetMutexHolder           1
+
+extern void freertos_rs_assert_failed( const char * pcFile,
+                                       unsigned long ulLine );
+#define configASSERT( x )    if( ( x ) == 0 ) freertos_rs_assert_failed( __FILE__, __LINE__ )
+
+/* Defined with the `trace` feature, the events are recorded by the `freertos` crate. */
+#ifdef FREERTOS_RS_TRACE
+    extern void freertos_rs_trace( unsigned char ucEvent,
+                                   void * pvObject );
+    #define traceTASK_SWITCHED_IN()                      freertos_rs_trace( 0, pxCurrentTCB )
+    #define traceTASK_SWITCHED_OUT()                     freertos_rs_trace( 1, pxCurrentTCB )
+    #define traceQUEUE_SEND( pxQueue )                   freertos_rs_trace( 2, pxQueue )
+    #define traceQUEUE_SEND_FROM_ISR( pxQueue )          freertos_rs_trace( 3, pxQueue )
+    #define traceQUEUE_RECEIVE( pxQueue )                freertos_rs_trace( 4, pxQueue )
+    #define traceQUEUE_RECEIVE_FROM_ISR( pxQueue )       freertos_rs_trace( 5, pxQueue )
+    #define traceBLOCKING_ON_QUEUE_SEND( pxQueue )       freertos_rs_trace( 6, pxQueue )
+    #define traceBLOCKING_ON_QUEUE_RECEIVE( pxQueue )    freertos_rs_trace( 7, pxQueue )
+    #define traceTASK_DELAY()                            freertos_rs_trace( 8, pxCurrentTCB )
+    #define traceTASK_DELAY_UNTIL( xTimeToWake )         freertos_rs_trace( 9, pxCurrentTCB )
+#endif
+
+#endif /* FREERTOS_CONFIG_H */
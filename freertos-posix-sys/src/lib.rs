@@ -0,0 +1,58 @@
+//! Raw bindings to the FreeRTOS kernel built for the POSIX/Linux simulator port.
+//!
+//! Exposes the same `sys` surface as the target specific bindings, so the `freertos`
+//! crate can be built and tested on a regular Linux host with its `posix` feature.
+
+#![no_std]
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+#![allow(clippy::all)]
+
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+// Macros containing casts aren't picked up by bindgen.
+pub const pdFALSE: BaseType_t = 0;
+pub const pdTRUE: BaseType_t = 1;
+pub const pdFAIL: BaseType_t = pdFALSE;
+pub const pdPASS: BaseType_t = pdTRUE;
+pub const portMAX_DELAY: TickType_t = TickType_t::MAX;
+
+pub const queueQUEUE_TYPE_BASE: u8 = 0;
+pub const queueQUEUE_TYPE_SET: u8 = 0;
+pub const queueQUEUE_TYPE_MUTEX: u8 = 1;
+pub const queueQUEUE_TYPE_COUNTING_SEMAPHORE: u8 = 2;
+pub const queueQUEUE_TYPE_BINARY_SEMAPHORE: u8 = 3;
+pub const queueQUEUE_TYPE_RECURSIVE_MUTEX: u8 = 4;
+
+pub const tmrCOMMAND_START: BaseType_t = 1;
+pub const tmrCOMMAND_RESET: BaseType_t = 2;
+pub const tmrCOMMAND_STOP: BaseType_t = 3;
+pub const tmrCOMMAND_CHANGE_PERIOD: BaseType_t = 4;
+pub const tmrCOMMAND_DELETE: BaseType_t = 5;
+
+#[inline(always)]
+pub unsafe fn vTaskEnterCritical() {
+    freertos_rs_enter_critical()
+}
+#[inline(always)]
+pub unsafe fn vTaskExitCritical() {
+    freertos_rs_exit_critical()
+}
+#[inline(always)]
+pub unsafe fn ulTaskEnterCriticalFromISR() -> UBaseType_t {
+    freertos_rs_enter_critical_from_isr()
+}
+#[inline(always)]
+pub unsafe fn vTaskExitCriticalFromISR(uxSavedInterruptStatus: UBaseType_t) {
+    freertos_rs_exit_critical_from_isr(uxSavedInterruptStatus)
+}
+#[inline(always)]
+pub unsafe fn vPortYieldFromISR() {
+    freertos_rs_yield_from_isr()
+}
+/// The simulator has no interrupts, the tick runs on a thread of its own.
+#[inline(always)]
+pub unsafe fn xPortInIsrContext() -> BaseType_t {
+    pdFALSE
+}
@@ -0,0 +1,36 @@
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "shim.h"
+
+void freertos_rs_enter_critical( void )
+{
+    taskENTER_CRITICAL();
+}
+
+void freertos_rs_exit_critical( void )
+{
+    taskEXIT_CRITICAL();
+}
+
+UBaseType_t freertos_rs_enter_critical_from_isr( void )
+{
+    return taskENTER_CRITICAL_FROM_ISR();
+}
+
+void freertos_rs_exit_critical_from_isr( UBaseType_t uxSavedInterruptStatus )
+{
+    taskEXIT_CRITICAL_FROM_ISR( uxSavedInterruptStatus );
+}
+
+void freertos_rs_yield_from_isr( void )
+{
+    portYIELD_FROM_ISR( pdTRUE );
+}
+
+void freertos_rs_assert_failed( const char * pcFile,
+                                unsigned long ulLine )
+{
+    fprintf( stderr, "FreeRTOS assertion failed at %s:%lu\n", pcFile, ulLine );
+    abort();
+}
@@ -0,0 +1,26 @@
+/*
+ * Everything the Rust bindings are generated from. Kernel macros that can't be
+ * expressed through bindgen are wrapped by the `freertos_rs_` functions in shim.c.
+ */
+
+#ifndef FREERTOS_RS_SHIM_H
+#define FREERTOS_RS_SHIM_H
+
+#include <string.h>
+
+#include "FreeRTOS.h"
+#include "task.h"
+#include "queue.h"
+#include "semphr.h"
+#include "timers.h"
+#include "event_groups.h"
+#include "stream_buffer.h"
+#include "message_buffer.h"
+
+void freertos_rs_enter_critical( void );
+void freertos_rs_exit_critical( void );
+UBaseType_t freertos_rs_enter_critical_from_isr( void );
+void freertos_rs_exit_critical_from_isr( UBaseType_t uxSavedInterruptStatus );
+void freertos_rs_yield_from_isr( void );
+
+#endif /* FREERTOS_RS_SHIM_H */
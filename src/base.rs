@@ -10,15 +10,38 @@ pub enum FreeRtosError {
     StringConversionError,
     TaskNotFound,
     InvalidQueueSize,
+    /// The stack doesn't fit the kernel's `configSTACK_DEPTH_TYPE`.
+    InvalidStackSize,
     ProcessorHasShutDown,
+    /// The joined task panicked before it returned.
+    TaskPanicked,
+    /// The joined task was deleted before it returned.
+    TaskDeleted,
+    /// Every notification index is reserved already, see
+    /// [`NotificationIndex::reserve()`](crate::NotificationIndex::reserve).
+    NoNotificationIndex,
+    /// [`select!`](crate::select) has no arm for the member of the queue set that is ready,
+    /// for example one added by C code.
+    UnhandledSetMember,
+    /// The lock is held already, by the caller or on SMP by another core. Returned instead
+    /// of handing out a second mutable reference.
+    AlreadyLocked,
+    /// Locking a mutex timed out while it was held by a task. Only returned with the
+    /// `mutex-diagnostics` feature, instead of `MutexTimeout`. Any crate in the build can
+    /// turn that on, so code handling `MutexTimeout` should handle this too.
+    MutexHeld(crate::mutex::MutexHolder),
 }
 
 use core::ptr;
 
+#[cfg(feature = "mock")]
+use crate::mock::sys;
+
 pub use chlorine::{c_char, c_void};
 
 pub use sys::BaseType_t as BaseType;
 pub use sys::StackType_t as StackType;
+pub use sys::EventBits_t as EventBits;
 pub use sys::TickType_t as TickType;
 pub use sys::UBaseType_t as UBaseType;
 
@@ -28,10 +51,19 @@ pub type QueueHandle = ptr::NonNull<c_void>;
 pub type MaybeQueueHandle = Option<QueueHandle>;
 pub type TimerHandle = ptr::NonNull<c_void>;
 pub type MaybeTimerHandle = Option<TimerHandle>;
+pub type EventGroupHandle = ptr::NonNull<c_void>;
+pub type MaybeEventGroupHandle = Option<EventGroupHandle>;
+pub type StreamBufferHandle = ptr::NonNull<c_void>;
+pub type MaybeStreamBufferHandle = Option<StreamBufferHandle>;
 
 pub use sys::TaskStatus_t as TaskStatusFfi;
+pub use sys::StaticEventGroup_t as StaticEventGroupFfi;
+pub use sys::StaticQueue_t as StaticQueueFfi;
+pub use sys::StaticTask_t as StaticTaskFfi;
+pub use sys::StaticTimer_t as StaticTimerFfi;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[repr(u8)]
 pub enum TaskState {
     /// A task is querying the state of itself, so must be running.
@@ -45,3 +77,15 @@ pub enum TaskState {
     /// The task being queried has been deleted, but its TCB has not yet been freed.
     Deleted = 4,
 }
+
+impl TaskState {
+    pub(crate) fn from_freertos(state: sys::eTaskState) -> TaskState {
+        match state as u8 {
+            0 => TaskState::Running,
+            1 => TaskState::Ready,
+            2 => TaskState::Blocked,
+            3 => TaskState::Suspended,
+            _ => TaskState::Deleted,
+        }
+    }
+}
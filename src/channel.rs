@@ -0,0 +1,220 @@
+use core::marker::PhantomData;
+use core::mem;
+use core::mem::MaybeUninit;
+
+use crate::base::*;
+use crate::glue;
+use crate::isr::*;
+use crate::queue_set::QueueSetMember;
+use crate::units::*;
+
+unsafe impl<T: Send> Send for Channel<T> {}
+unsafe impl<T: Send> Sync for Channel<T> {}
+
+/// A queue that moves its items instead of copying them, for types that aren't `Copy`
+/// like `Vec`, `String` or `Box<dyn Trait>`.
+///
+/// The kernel copies the bytes of an item into the queue and out of it again, ownership
+/// goes along with them. Items that were never received are dropped with the channel.
+/// An item that couldn't be sent is handed back together with the error.
+#[derive(Debug)]
+pub struct Channel<T: Send> {
+    queue: QueueHandle,
+    item_type: PhantomData<T>,
+}
+
+impl<T: Send> Channel<T> {
+    pub fn new(max_size: usize) -> Result<Channel<T>, FreeRtosError> {
+        match unsafe { glue::queue_create(max_size as UBaseType, mem::size_of::<T>() as UBaseType) }
+        {
+            Some(queue) => Ok(Channel {
+                queue,
+                item_type: PhantomData,
+            }),
+            None => Err(FreeRtosError::OutOfMemory),
+        }
+    }
+
+    /// Send an item to the end of the channel. Wait for the channel to have empty space
+    /// for it.
+    pub fn send(&self, item: T, max_wait: impl Into<Ticks>) -> Result<(), (T, FreeRtosError)> {
+        let item = MaybeUninit::new(item);
+        unsafe {
+            if glue::queue_send(self.queue, item.as_ptr() as *const _, max_wait.into().ticks) {
+                Ok(())
+            } else {
+                Err((item.assume_init(), FreeRtosError::QueueSendTimeout))
+            }
+        }
+    }
+
+    /// Send an item to the front of the channel. Wait for the channel to have empty space
+    /// for it.
+    pub fn send_to_front(
+        &self,
+        item: T,
+        max_wait: impl Into<Ticks>,
+    ) -> Result<(), (T, FreeRtosError)> {
+        let item = MaybeUninit::new(item);
+        unsafe {
+            if glue::queue_send_to_front(
+                self.queue,
+                item.as_ptr() as *const _,
+                max_wait.into().ticks,
+            ) {
+                Ok(())
+            } else {
+                Err((item.assume_init(), FreeRtosError::QueueSendTimeout))
+            }
+        }
+    }
+
+    /// Send an item to the end of the channel, from an interrupt.
+    pub fn send_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        item: T,
+    ) -> Result<(), (T, FreeRtosError)> {
+        let item = MaybeUninit::new(item);
+        unsafe {
+            if glue::queue_send_isr(
+                self.queue,
+                item.as_ptr() as *const _,
+                context.get_task_field_mut(),
+            ) {
+                Ok(())
+            } else {
+                Err((item.assume_init(), FreeRtosError::QueueFull))
+            }
+        }
+    }
+
+    /// Wait for an item to be available on the channel.
+    pub fn receive(&self, max_wait: impl Into<Ticks>) -> Result<T, FreeRtosError> {
+        let mut item = MaybeUninit::<T>::uninit();
+        unsafe {
+            if glue::queue_receive(
+                self.queue,
+                item.as_mut_ptr() as *mut _,
+                max_wait.into().ticks,
+            ) {
+                Ok(item.assume_init())
+            } else {
+                Err(FreeRtosError::QueueReceiveTimeout)
+            }
+        }
+    }
+
+    /// Receive an item from an interrupt, if there is any.
+    pub fn receive_from_isr(&self, context: &mut InterruptContext) -> Result<T, FreeRtosError> {
+        let mut item = MaybeUninit::<T>::uninit();
+        unsafe {
+            if glue::queue_receive_isr(
+                self.queue,
+                item.as_mut_ptr() as *mut _,
+                context.get_task_field_mut(),
+            ) {
+                Ok(item.assume_init())
+            } else {
+                Err(FreeRtosError::QueueReceiveTimeout)
+            }
+        }
+    }
+
+    /// The number of items in the channel.
+    pub fn messages_waiting(&self) -> usize {
+        unsafe { glue::queue_messages_waiting(self.queue) as usize }
+    }
+
+    /// The number of items that can be sent before the channel is full.
+    pub fn spaces_available(&self) -> usize {
+        unsafe { glue::queue_spaces_available(self.queue) as usize }
+    }
+}
+
+impl<'a, T: Send> QueueSetMember<'a> for Channel<T> {
+    type Ready = T;
+
+    fn queue_handle(&self) -> QueueHandle {
+        self.queue
+    }
+
+    fn take_ready(&'a self) -> Result<T, FreeRtosError> {
+        self.receive(Ticks::zero())
+    }
+}
+
+impl<T: Send> Drop for Channel<T> {
+    fn drop(&mut self) {
+        // Drop the items that were never received.
+        while self.receive(Ticks::zero()).is_ok() {}
+
+        unsafe {
+            glue::queue_delete(self.queue);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::prelude::*;
+    use crate::queue::Queue;
+    use crate::task::*;
+
+    /// Counts how many times it was dropped.
+    struct Counted(String, Arc<AtomicU32>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn items_are_moved_to_the_receiver() {
+        let kernel = Kernel::new();
+        let drops = Arc::new(AtomicU32::new(0));
+        let channel = Arc::new(Channel::new(2).unwrap());
+        let out = Arc::new(Queue::new(1).unwrap());
+
+        let (c, o) = (channel.clone(), out.clone());
+        Task::new()
+            .start(move || {
+                let item: Counted = c.receive(Ticks::infinite()).unwrap();
+                o.send(item.0.len(), Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.run();
+        assert!(channel.send(Counted("hello".into(), drops.clone()), Ticks::zero()).is_ok());
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(5));
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn full_channel_gives_the_item_back() {
+        let _kernel = Kernel::new();
+        let drops = Arc::new(AtomicU32::new(0));
+        let channel = Channel::new(2).unwrap();
+
+        assert!(channel.send(Counted("a".into(), drops.clone()), Ticks::zero()).is_ok());
+        assert!(channel.send_to_front(Counted("b".into(), drops.clone()), Ticks::zero()).is_ok());
+        let (item, error) = channel
+            .send(Counted("c".into(), drops.clone()), Ticks::zero())
+            .err()
+            .unwrap();
+        assert_eq!((item.0.as_str(), error), ("c", FreeRtosError::QueueSendTimeout));
+        drop(item);
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+        assert_eq!(channel.receive(Ticks::zero()).map(|item| item.0.clone()), Ok("b".into()));
+        assert_eq!(drops.load(Ordering::SeqCst), 2);
+        drop(channel);
+        assert_eq!(drops.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,292 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use core::fmt;
+
+use crate::base::*;
+use crate::glue;
+use crate::prelude::*;
+use crate::task::*;
+use crate::units::*;
+
+const CORES: usize = glue::NUMBER_OF_CORES;
+
+/// Measures the CPU load of every task over a sliding window, from the run time counters
+/// the kernel keeps when `configGENERATE_RUN_TIME_STATS` is enabled.
+///
+/// [`sample()`](Self::sample) has to be called periodically, more often than the run time
+/// counter wraps. The load of the idle tasks, found with `xTaskGetIdleTaskHandle`, tells
+/// how busy the cores are, so `INCLUDE_xTaskGetIdleTaskHandle` must be enabled too.
+///
+/// ```ignore
+/// let mut monitor = CpuMonitor::new(Ticks::seconds(10));
+/// loop {
+///     monitor.sample();
+///     println!("{}", monitor.load());
+///     CurrentTask::delay(Ticks::seconds(1));
+/// }
+/// ```
+pub struct CpuMonitor {
+    window: TickType,
+    previous: Option<Sample>,
+    intervals: VecDeque<Interval>,
+    /// Every task in the latest sample or one of the intervals, by task number.
+    tasks: BTreeMap<UBaseType, (Task, String)>,
+}
+
+/// The run time counters read by one call of [`CpuMonitor::sample()`].
+struct Sample {
+    tick: TickType,
+    total: u32,
+    counters: BTreeMap<UBaseType, u32>,
+}
+
+/// What ran between two samples.
+struct Interval {
+    start: TickType,
+    end: TickType,
+    total: u32,
+    tasks: Vec<(UBaseType, u32)>,
+    idle: [u32; CORES],
+}
+
+impl CpuMonitor {
+    /// Report the load over the last `window`, the first sample only serves as the start
+    /// of the first interval.
+    pub fn new(window: impl Into<Ticks>) -> CpuMonitor {
+        CpuMonitor {
+            window: window.into().ticks,
+            previous: None,
+            intervals: VecDeque::new(),
+            tasks: BTreeMap::new(),
+        }
+    }
+
+    /// Read the run time counters of all tasks and drop the intervals that left the window.
+    pub fn sample(&mut self) {
+        let state = get_all_tasks(None);
+        let sample = Sample {
+            tick: get_tick_count(),
+            total: state.total_run_time,
+            counters: state
+                .tasks
+                .iter()
+                .map(|t| (t.task_number, t.run_time_counter))
+                .collect(),
+        };
+
+        if let Some(previous) = &self.previous {
+            let tasks = sample
+                .counters
+                .iter()
+                .map(|(&number, &counter)| {
+                    // A task created since the previous sample ran for all of its counter.
+                    let run_time = match previous.counters.get(&number) {
+                        Some(&before) => counter.wrapping_sub(before),
+                        None => counter,
+                    };
+                    (number, run_time)
+                })
+                .collect::<Vec<_>>();
+
+            let mut idle = [0; CORES];
+            for (core, idle) in idle.iter_mut().enumerate() {
+                let handle = unsafe { glue::get_idle_task_handle(core as UBaseType) };
+                let number = state
+                    .tasks
+                    .iter()
+                    .find(|t| Some(t.task.clone().into_raw()) == handle)
+                    .map(|t| t.task_number);
+                if let Some(&(_, run_time)) = tasks.iter().find(|&&(n, _)| Some(n) == number) {
+                    *idle = run_time;
+                }
+            }
+
+            self.intervals.push_back(Interval {
+                start: previous.tick,
+                end: sample.tick,
+                total: sample.total.wrapping_sub(previous.total),
+                tasks,
+                idle,
+            });
+        }
+
+        while let Some(oldest) = self.intervals.front() {
+            if sample.tick.wrapping_sub(oldest.end) < self.window {
+                break;
+            }
+            self.intervals.pop_front();
+        }
+
+        for task in state.tasks {
+            self.tasks.insert(task.task_number, (task.task, task.name));
+        }
+        let intervals = &self.intervals;
+        self.tasks.retain(|number, _| {
+            sample.counters.contains_key(number)
+                || intervals
+                    .iter()
+                    .any(|i| i.tasks.iter().any(|&(n, _)| n == *number))
+        });
+
+        self.previous = Some(sample);
+    }
+
+    /// The load over the intervals in the window, all zero until two samples were taken.
+    /// Tasks deleted within the window are included, up to their last sample.
+    pub fn load(&self) -> CpuLoad {
+        let mut run_times = BTreeMap::<UBaseType, u64>::new();
+        let mut idle = [0u64; CORES];
+        let mut total_run_time = 0u64;
+        for interval in &self.intervals {
+            total_run_time += interval.total as u64;
+            for &(number, run_time) in &interval.tasks {
+                *run_times.entry(number).or_default() += run_time as u64;
+            }
+            for (sum, &run_time) in idle.iter_mut().zip(interval.idle.iter()) {
+                *sum += run_time as u64;
+            }
+        }
+
+        let tasks = self
+            .tasks
+            .iter()
+            .map(|(&number, (task, name))| {
+                let run_time = run_times.get(&number).copied().unwrap_or(0);
+                TaskLoad {
+                    task: task.clone(),
+                    name: name.clone(),
+                    task_number: number,
+                    run_time,
+                    percent: percent(run_time, total_run_time),
+                }
+            })
+            .collect();
+
+        let busy = |idle: u64, capacity: u64| {
+            if capacity == 0 {
+                0.0
+            } else {
+                100.0 - percent(idle.min(capacity), capacity)
+            }
+        };
+
+        CpuLoad {
+            ticks: match (self.intervals.front(), self.intervals.back()) {
+                (Some(first), Some(last)) => last.end.wrapping_sub(first.start),
+                _ => 0,
+            },
+            total_run_time,
+            percent: busy(idle.iter().sum(), total_run_time * CORES as u64),
+            #[cfg(feature = "smp")]
+            cores: idle.iter().map(|&idle| busy(idle, total_run_time)).collect(),
+            tasks,
+        }
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f32 {
+    if whole == 0 {
+        0.0
+    } else {
+        (part as f64 * 100.0 / whole as f64) as f32
+    }
+}
+
+/// The load measured by a [`CpuMonitor`].
+#[derive(Debug, Clone)]
+pub struct CpuLoad {
+    /// The ticks the load was measured over.
+    pub ticks: TickType,
+    /// The elapsed run time, in the unit of the run time counter.
+    pub total_run_time: u64,
+    /// How busy all cores were together, in percent.
+    pub percent: f32,
+    /// How busy each core was, in percent, by the run time of its idle task.
+    #[cfg(feature = "smp")]
+    pub cores: Vec<f32>,
+    /// Ordered by task number.
+    pub tasks: Vec<TaskLoad>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskLoad {
+    pub task: Task,
+    pub name: String,
+    pub task_number: UBaseType,
+    /// The run time within the window, in the unit of the run time counter.
+    pub run_time: u64,
+    /// The share of one core, in percent.
+    pub percent: f32,
+}
+
+impl fmt::Display for CpuLoad {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "CPU load over {} ticks\r\n", self.ticks)?;
+
+        write!(
+            fmt,
+            "{id: <6} | {name: <16} | {cpu_abs: >10} | {cpu_rel: >6}\r\n",
+            id = "ID",
+            name = "Name",
+            cpu_abs = "CPU",
+            cpu_rel = "%"
+        )?;
+
+        for task in &self.tasks {
+            write!(
+                fmt,
+                "{id: <6} | {name: <16} | {cpu_abs: >10} | {cpu_rel: >5.1}%\r\n",
+                id = task.task_number,
+                name = task.name,
+                cpu_abs = task.run_time,
+                cpu_rel = task.percent
+            )?;
+        }
+
+        write!(fmt, "Total: {:.1}%\r\n", self.percent)?;
+        #[cfg(feature = "smp")]
+        for (core, percent) in self.cores.iter().enumerate() {
+            write!(fmt, "Core {}: {:.1}%\r\n", core, percent)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+
+    fn names(load: &CpuLoad) -> Vec<&str> {
+        load.tasks.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    #[test]
+    fn deleted_tasks_leave_with_the_window() {
+        let kernel = Kernel::new();
+        let mut monitor = CpuMonitor::new(Ticks::new(10));
+        let task = Task::new()
+            .name("worker")
+            .start(|| CurrentTask::delay(Ticks::infinite()))
+            .unwrap();
+
+        monitor.sample();
+        assert_eq!(monitor.load().ticks, 0);
+        kernel.advance_ticks(4);
+        monitor.sample();
+        let load = monitor.load();
+        assert_eq!(load.ticks, 4);
+        assert_eq!(names(&load), ["worker"]);
+
+        unsafe { task.delete() };
+        kernel.advance_ticks(4);
+        monitor.sample();
+        assert_eq!(monitor.load().ticks, 8);
+        assert_eq!(names(&monitor.load()), ["worker"]);
+
+        kernel.advance_ticks(10);
+        monitor.sample();
+        assert_eq!(monitor.load().ticks, 10);
+        assert!(monitor.load().tasks.is_empty());
+    }
+}
@@ -1,61 +1,178 @@
-use core::cell::UnsafeCell;
+use core::cell::{Cell, UnsafeCell};
+use core::marker::PhantomData;
+use core::mem;
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use crate::base::*;
 use crate::glue;
+use crate::isr::InterruptContext;
 
-pub struct CriticalSection(UBaseType);
+/// Disables interrupts, and on SMP the other cores, until dropped. For tasks, can be
+/// nested.
+pub struct CriticalSection(PhantomData<*mut ()>);
 impl CriticalSection {
     pub fn enter() -> Self {
-        let previous_state = unsafe { glue::enter_critical() };
+        unsafe { glue::enter_critical() };
 
-        CriticalSection(previous_state)
+        CriticalSection(PhantomData)
     }
 }
 
 impl Drop for CriticalSection {
     fn drop(&mut self) {
         unsafe {
-            glue::exit_critical(self.0);
+            glue::exit_critical();
         }
     }
 }
 
-unsafe impl<T: Sync + Send> Send for ExclusiveData<T> {}
-unsafe impl<T: Sync + Send> Sync for ExclusiveData<T> {}
+/// Masks interrupts up to the kernel's priority until dropped. For interrupts, can be
+/// nested.
+pub struct IsrCriticalSection(UBaseType, PhantomData<*mut ()>);
+impl IsrCriticalSection {
+    pub fn enter(_context: &mut InterruptContext) -> Self {
+        let previous_state = unsafe { glue::enter_critical_isr() };
+
+        IsrCriticalSection(previous_state, PhantomData)
+    }
+}
+
+impl Drop for IsrCriticalSection {
+    fn drop(&mut self) {
+        unsafe {
+            glue::exit_critical_isr(self.0);
+        }
+    }
+}
+
+/// The implementation of the `critical-section` crate. Tasks take the task critical
+/// section, which the kernel nests and which keeps the other cores out on SMP, only
+/// interrupts take the variant for interrupts.
+#[cfg(feature = "critical-section")]
+mod critical_section_impl {
+    use critical_section::RawRestoreState;
+
+    use crate::glue;
+
+    struct FreeRtosCriticalSection;
+    critical_section::set_impl!(FreeRtosCriticalSection);
+
+    // `release()` runs in the same context as the matching `acquire()`, so it can look
+    // at the context again instead of storing it in the restore state.
+    unsafe impl critical_section::Impl for FreeRtosCriticalSection {
+        unsafe fn acquire() -> RawRestoreState {
+            if glue::is_inside_interrupt() {
+                glue::enter_critical_isr() as RawRestoreState
+            } else {
+                glue::enter_critical();
+                0
+            }
+        }
+
+        unsafe fn release(previous_state: RawRestoreState) {
+            if glue::is_inside_interrupt() {
+                glue::exit_critical_isr(previous_state as _)
+            } else {
+                glue::exit_critical()
+            }
+        }
+    }
+}
+
+/// Suspends the scheduler until dropped, interrupts stay enabled. No other task runs in
+/// the meantime, but the current task must not block. Can be nested.
+pub struct SchedulerLock(PhantomData<*mut ()>);
+impl SchedulerLock {
+    pub fn lock() -> Self {
+        unsafe { glue::task_suspend_all() };
+
+        SchedulerLock(PhantomData)
+    }
+
+    /// Resume the scheduler, returns whether that already switched to another task.
+    pub fn resume(self) -> bool {
+        mem::forget(self);
+        unsafe { glue::task_resume_all() }
+    }
+}
+
+impl Drop for SchedulerLock {
+    fn drop(&mut self) {
+        unsafe {
+            glue::task_resume_all();
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for ExclusiveData<T> {}
+unsafe impl<T: Send> Sync for ExclusiveData<T> {}
 
 /// Data protected with a critical region. Lightweight version of a mutex,
 /// intended for simple data structures.
+///
+/// Both tasks and interrupts mask interrupts while holding the lock and take the port's
+/// spinlock, so it is safe to share between a task and an interrupt on another core. On
+/// ESP-IDF every `ExclusiveData` has a `portMUX_TYPE` of its own, elsewhere the kernel's
+/// critical sections are the spinlock. Locking it again while holding it fails with
+/// [`FreeRtosError::AlreadyLocked`].
 pub struct ExclusiveData<T: ?Sized> {
+    lock: glue::Spinlock,
+    /// Whether a guard exists, only touched while holding the spinlock.
+    held: Cell<bool>,
     data: UnsafeCell<T>,
 }
 
 impl<T> ExclusiveData<T> {
     pub fn new(data: T) -> Self {
         ExclusiveData {
+            lock: glue::Spinlock::new(),
+            held: Cell::new(false),
             data: UnsafeCell::new(data),
         }
     }
 
     pub fn lock(&self) -> Result<ExclusiveDataGuard<T>, FreeRtosError> {
+        unsafe { self.lock.enter() };
+        if self.held.replace(true) {
+            unsafe { self.lock.exit() };
+            return Err(FreeRtosError::AlreadyLocked);
+        }
+
         Ok(ExclusiveDataGuard {
+            lock: &self.lock,
+            held: &self.held,
             data: &self.data,
-            _lock: CriticalSection::enter(),
+            _not_send: PhantomData,
         })
     }
 
     pub fn lock_from_isr(
         &self,
-        _context: &mut crate::isr::InterruptContext,
+        _context: &mut InterruptContext,
     ) -> Result<ExclusiveDataGuardIsr<T>, FreeRtosError> {
-        Ok(ExclusiveDataGuardIsr { data: &self.data })
+        let previous_state = unsafe { self.lock.enter_isr() };
+        if self.held.replace(true) {
+            unsafe { self.lock.exit_isr(previous_state) };
+            return Err(FreeRtosError::AlreadyLocked);
+        }
+
+        Ok(ExclusiveDataGuardIsr {
+            lock: &self.lock,
+            held: &self.held,
+            data: &self.data,
+            previous_state,
+            _not_send: PhantomData,
+        })
     }
 }
 
 /// Holds the mutex until we are dropped
 pub struct ExclusiveDataGuard<'a, T: ?Sized + 'a> {
+    lock: &'a glue::Spinlock,
+    held: &'a Cell<bool>,
     data: &'a UnsafeCell<T>,
-    _lock: CriticalSection,
+    _not_send: PhantomData<*mut ()>,
 }
 
 impl<'mutex, T: ?Sized> Deref for ExclusiveDataGuard<'mutex, T> {
@@ -72,8 +189,19 @@ impl<'mutex, T: ?Sized> DerefMut for ExclusiveDataGuard<'mutex, T> {
     }
 }
 
+impl<'a, T: ?Sized> Drop for ExclusiveDataGuard<'a, T> {
+    fn drop(&mut self) {
+        self.held.set(false);
+        unsafe { self.lock.exit() }
+    }
+}
+
 pub struct ExclusiveDataGuardIsr<'a, T: ?Sized + 'a> {
+    lock: &'a glue::Spinlock,
+    held: &'a Cell<bool>,
     data: &'a UnsafeCell<T>,
+    previous_state: UBaseType,
+    _not_send: PhantomData<*mut ()>,
 }
 
 impl<'mutex, T: ?Sized> Deref for ExclusiveDataGuardIsr<'mutex, T> {
@@ -89,3 +217,163 @@ impl<'mutex, T: ?Sized> DerefMut for ExclusiveDataGuardIsr<'mutex, T> {
         unsafe { &mut *self.data.get() }
     }
 }
+
+impl<'a, T: ?Sized> Drop for ExclusiveDataGuardIsr<'a, T> {
+    fn drop(&mut self) {
+        self.held.set(false);
+        unsafe { self.lock.exit_isr(self.previous_state) }
+    }
+}
+
+unsafe impl<T: Send> Send for SchedulerExclusiveData<T> {}
+unsafe impl<T: Send> Sync for SchedulerExclusiveData<T> {}
+
+/// Data protected by suspending the scheduler instead of disabling interrupts. Only for
+/// tasks, interrupts can't access it.
+///
+/// Suspending the scheduler nests and on SMP leaves the tasks running on the other cores
+/// alone, so `lock()` fails with [`FreeRtosError::AlreadyLocked`] while a guard exists.
+pub struct SchedulerExclusiveData<T: ?Sized> {
+    held: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+impl<T> SchedulerExclusiveData<T> {
+    pub fn new(data: T) -> Self {
+        SchedulerExclusiveData {
+            held: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> Result<SchedulerExclusiveDataGuard<'_, T>, FreeRtosError> {
+        let lock = SchedulerLock::lock();
+        if self.held.swap(true, Ordering::Acquire) {
+            return Err(FreeRtosError::AlreadyLocked);
+        }
+
+        Ok(SchedulerExclusiveDataGuard {
+            held: &self.held,
+            data: &self.data,
+            _lock: lock,
+        })
+    }
+}
+
+/// Keeps the scheduler suspended until we are dropped
+pub struct SchedulerExclusiveDataGuard<'a, T: ?Sized + 'a> {
+    held: &'a AtomicBool,
+    data: &'a UnsafeCell<T>,
+    _lock: SchedulerLock,
+}
+
+impl<'mutex, T: ?Sized> Deref for SchedulerExclusiveDataGuard<'mutex, T> {
+    type Target = T;
+
+    fn deref<'a>(&'a self) -> &'a T {
+        unsafe { &*self.data.get() }
+    }
+}
+
+impl<'mutex, T: ?Sized> DerefMut for SchedulerExclusiveDataGuard<'mutex, T> {
+    fn deref_mut<'a>(&'a mut self) -> &'a mut T {
+        unsafe { &mut *self.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SchedulerExclusiveDataGuard<'a, T> {
+    fn drop(&mut self) {
+        self.held.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::prelude::*;
+    use crate::queue::Queue;
+    use crate::semaphore::Semaphore;
+    use crate::task::*;
+    use crate::units::Ticks;
+
+    #[test]
+    fn scheduler_lock_defers_the_switch() {
+        let kernel = Kernel::new();
+        let semaphore = Arc::new(Semaphore::new_binary().unwrap());
+        let data = Arc::new(SchedulerExclusiveData::new(Vec::new()));
+        let out = Arc::new(Queue::new(1).unwrap());
+
+        let (s, d) = (semaphore.clone(), data.clone());
+        Task::new()
+            .priority(TaskPriority(3))
+            .start(move || {
+                s.take(Ticks::infinite()).unwrap();
+                d.lock().unwrap().push(2);
+            })
+            .unwrap();
+        let (s, d, o) = (semaphore.clone(), data.clone(), out.clone());
+        Task::new()
+            .start(move || {
+                let lock = SchedulerLock::lock();
+                s.give().unwrap();
+                let mut guard = d.lock().unwrap();
+                guard.push(1);
+                assert_eq!(d.lock().err(), Some(FreeRtosError::AlreadyLocked));
+                drop(guard);
+                o.send(lock.resume(), Ticks::zero()).unwrap();
+                let _critical = CriticalSection::enter();
+                d.lock().unwrap().push(3);
+            })
+            .unwrap();
+
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(true));
+        assert_eq!(*data.lock().unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn exclusive_data_from_tasks_and_interrupts() {
+        let kernel = Kernel::new();
+        let data = Arc::new(ExclusiveData::new(0));
+
+        let d = data.clone();
+        Task::new()
+            .start(move || {
+                let mut guard = d.lock().unwrap();
+                *guard += 1;
+                assert_eq!(d.lock().err(), Some(FreeRtosError::AlreadyLocked));
+                drop(guard);
+                *d.lock().unwrap() += 1;
+            })
+            .unwrap();
+
+        kernel.run();
+        let mut context = InterruptContext::new();
+        let guard = data.lock_from_isr(&mut context).unwrap();
+        assert!(data.lock_from_isr(&mut context).is_err());
+        drop(guard);
+        *data.lock_from_isr(&mut context).unwrap() += 1;
+        drop(context);
+        assert_eq!(*data.lock().unwrap(), 3);
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[test]
+    fn critical_section_from_tasks_and_interrupts() {
+        let kernel = Kernel::new();
+        let out = Arc::new(Queue::new(1).unwrap());
+
+        let o = out.clone();
+        Task::new()
+            .start(move || {
+                let nested = critical_section::with(|_| critical_section::with(|_| 5));
+                o.send(nested, Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(5));
+        assert_eq!(critical_section::with(|_| 6), 6);
+    }
+}
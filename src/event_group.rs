@@ -0,0 +1,227 @@
+use core::mem::MaybeUninit;
+
+use crate::base::*;
+use crate::glue;
+use crate::isr::*;
+use crate::units::*;
+
+unsafe impl Send for EventGroup {}
+unsafe impl Sync for EventGroup {}
+
+/// Storage for the kernel's bookkeeping of a statically allocated event group.
+pub struct StaticEventGroupBuffer(MaybeUninit<StaticEventGroupFfi>);
+
+impl StaticEventGroupBuffer {
+    pub const fn new() -> StaticEventGroupBuffer {
+        StaticEventGroupBuffer(MaybeUninit::uninit())
+    }
+}
+
+/// A set of event flags tasks can wait on.
+///
+/// Only the lower 24 bits are usable with 32 bit ticks and the lower 56 bits with
+/// 64 bit ticks, the kernel uses the upper byte for itself.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct EventGroup {
+    handle: EventGroupHandle,
+}
+
+impl EventGroup {
+    /// Create a new event group with all bits cleared.
+    pub fn new() -> Result<EventGroup, FreeRtosError> {
+        match unsafe { glue::event_group_create() } {
+            Some(handle) => Ok(EventGroup { handle }),
+            None => Err(FreeRtosError::OutOfMemory),
+        }
+    }
+
+    /// Create a new event group with all bits cleared, without allocating.
+    pub fn new_static(
+        buffer: &'static mut StaticEventGroupBuffer,
+    ) -> Result<EventGroup, FreeRtosError> {
+        match unsafe { glue::event_group_create_static(buffer.0.as_mut_ptr()) } {
+            Some(handle) => Ok(EventGroup { handle }),
+            None => Err(FreeRtosError::OutOfMemory),
+        }
+    }
+
+    /// Set bits, unblocking the tasks waiting for them. Returns the bits at the time this
+    /// call returns, bits cleared by the unblocked tasks are already gone.
+    pub fn set_bits(&self, bits: EventBits) -> EventBits {
+        unsafe { glue::event_group_set_bits(self.handle, bits) }
+    }
+
+    /// Clear bits. Returns the bits from before they were cleared.
+    pub fn clear_bits(&self, bits: EventBits) -> EventBits {
+        unsafe { glue::event_group_clear_bits(self.handle, bits) }
+    }
+
+    /// Get the current bits.
+    pub fn get_bits(&self) -> EventBits {
+        unsafe { glue::event_group_clear_bits(self.handle, 0) }
+    }
+
+    /// Wait for any of the bits in `mask` to be set, or all of them if `all` is true.
+    /// With `clear_on_exit` the bits in `mask` are cleared again once the wait succeeded.
+    ///
+    /// Returns the bits from before they were cleared.
+    pub fn wait_bits(
+        &self,
+        mask: EventBits,
+        all: bool,
+        clear_on_exit: bool,
+        max_wait: impl Into<Ticks>,
+    ) -> Result<EventBits, FreeRtosError> {
+        let bits = unsafe {
+            glue::event_group_wait_bits(self.handle, mask, clear_on_exit, all, max_wait.into().ticks)
+        };
+
+        let satisfied = if all {
+            bits & mask == mask
+        } else {
+            bits & mask != 0
+        };
+        if satisfied {
+            Ok(bits)
+        } else {
+            Err(FreeRtosError::Timeout)
+        }
+    }
+
+    /// Set `bits` and wait for all of `wait_for` to be set, as a rendezvous point for
+    /// several tasks. The bits in `wait_for` are cleared once every task arrived.
+    ///
+    /// Returns the bits from before they were cleared.
+    pub fn sync(
+        &self,
+        bits: EventBits,
+        wait_for: EventBits,
+        max_wait: impl Into<Ticks>,
+    ) -> Result<EventBits, FreeRtosError> {
+        let bits =
+            unsafe { glue::event_group_sync(self.handle, bits, wait_for, max_wait.into().ticks) };
+
+        if bits & wait_for == wait_for {
+            Ok(bits)
+        } else {
+            Err(FreeRtosError::Timeout)
+        }
+    }
+
+    /// Set bits from an interrupt. The bits are set by the timer daemon task, fails if
+    /// its command queue is full.
+    pub fn set_bits_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        bits: EventBits,
+    ) -> Result<(), FreeRtosError> {
+        unsafe {
+            if glue::event_group_set_bits_from_isr(
+                self.handle,
+                bits,
+                context.get_task_field_mut(),
+            ) {
+                Ok(())
+            } else {
+                Err(FreeRtosError::QueueFull)
+            }
+        }
+    }
+
+    /// Clear bits from an interrupt. The bits are cleared by the timer daemon task, fails
+    /// if its command queue is full.
+    pub fn clear_bits_from_isr(
+        &self,
+        _context: &mut InterruptContext,
+        bits: EventBits,
+    ) -> Result<(), FreeRtosError> {
+        unsafe {
+            if glue::event_group_clear_bits_from_isr(self.handle, bits) {
+                Ok(())
+            } else {
+                Err(FreeRtosError::QueueFull)
+            }
+        }
+    }
+
+    /// Get the current bits from an interrupt.
+    pub fn get_bits_from_isr(&self, _context: &mut InterruptContext) -> EventBits {
+        unsafe { glue::event_group_get_bits_from_isr(self.handle) }
+    }
+}
+
+impl Drop for EventGroup {
+    fn drop(&mut self) {
+        unsafe {
+            glue::event_group_delete(self.handle);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::prelude::*;
+    use crate::queue::Queue;
+    use crate::task::*;
+
+    #[test]
+    fn sync_meets_all_tasks() {
+        let kernel = Kernel::new();
+        let group = Arc::new(EventGroup::new().unwrap());
+        let out = Arc::new(Queue::new(3).unwrap());
+
+        for i in 0..3 {
+            let (g, o) = (group.clone(), out.clone());
+            Task::new()
+                .start(move || {
+                    CurrentTask::delay(Ticks::new(i + 1));
+                    let bits = g.sync(1 << i, 0b111, Ticks::infinite()).unwrap();
+                    o.send((bits & 0b111, get_tick_count()), Ticks::zero()).unwrap();
+                })
+                .unwrap();
+        }
+
+        kernel.advance_ticks(2);
+        assert!(out.receive(Ticks::zero()).is_err());
+        assert_eq!(group.get_bits(), 0b11);
+        kernel.advance_ticks(1);
+        for _ in 0..3 {
+            assert_eq!(out.receive(Ticks::zero()), Ok((0b111, 3)));
+        }
+        assert_eq!(group.get_bits(), 0);
+    }
+
+    #[test]
+    fn wait_bits_any_all_and_timeout() {
+        let kernel = Kernel::new();
+        let group = Arc::new(EventGroup::new().unwrap());
+        let out = Arc::new(Queue::new(3).unwrap());
+
+        let (g, o) = (group.clone(), out.clone());
+        Task::new()
+            .start(move || {
+                o.send(g.wait_bits(0b11, true, false, Ticks::new(2)), Ticks::zero()).unwrap();
+                o.send(g.wait_bits(0b11, true, true, Ticks::infinite()), Ticks::zero()).unwrap();
+                o.send(g.wait_bits(0b1100, false, false, Ticks::infinite()), Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        group.set_bits(0b01);
+        kernel.advance_ticks(2);
+        assert_eq!(out.receive(Ticks::zero()), Ok(Err(FreeRtosError::Timeout)));
+        group.set_bits(0b10);
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(Ok(0b11)));
+        assert_eq!(group.get_bits(), 0);
+
+        let mut context = InterruptContext::new();
+        group.set_bits_from_isr(&mut context, 0b100).unwrap();
+        drop(context);
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(Ok(0b100)));
+        assert_eq!(group.clear_bits(0b100), 0b100);
+    }
+}
@@ -0,0 +1,176 @@
+//! Machine readable formats of the task status, written to any [`fmt::Write`] so they work
+//! without `std`.
+
+use core::fmt::{self, Write};
+
+use crate::task::*;
+
+/// The CSV columns, the JSON keys have the same names.
+const CSV_HEADER: &str = "task_number,name,state,current_priority,base_priority,\
+                          run_time_counter,stack_high_water_mark,total_run_time\n";
+
+impl SchedulerState {
+    /// Write one JSON object per task, each on its own line and ending with `\n`. Every
+    /// object holds the fields of the task and the `total_run_time` of the scheduler.
+    pub fn write_json_lines<W: Write>(&self, out: &mut W) -> fmt::Result {
+        write_json_lines(out, &self.tasks, self.total_run_time)
+    }
+
+    /// Write one CSV row per task, after a header row if `header` is set. The columns are
+    /// the keys of [`write_json_lines()`](Self::write_json_lines), rows end with `\n` too.
+    pub fn write_csv<W: Write>(&self, out: &mut W, header: bool) -> fmt::Result {
+        write_csv(out, &self.tasks, self.total_run_time, header)
+    }
+}
+
+impl<const N: usize> TaskSnapshot<N> {
+    /// Same as [`SchedulerState::write_json_lines()`].
+    pub fn write_json_lines<W: Write>(&self, out: &mut W) -> fmt::Result {
+        write_json_lines(out, self.tasks(), self.total_run_time())
+    }
+
+    /// Same as [`SchedulerState::write_csv()`].
+    pub fn write_csv<W: Write>(&self, out: &mut W, header: bool) -> fmt::Result {
+        write_csv(out, self.tasks(), self.total_run_time(), header)
+    }
+}
+
+impl<Name: AsRef<str>> TaskStatus<Name> {
+    /// Write the task as a single JSON object, without a line ending.
+    pub fn write_json<W: Write>(&self, out: &mut W) -> fmt::Result {
+        self.write_json_fields(out)?;
+        out.write_char('}')
+    }
+
+    /// Everything but the closing brace.
+    fn write_json_fields<W: Write>(&self, out: &mut W) -> fmt::Result {
+        write!(out, "{{\"task_number\":{},\"name\":", self.task_number)?;
+        write_json_string(out, self.name.as_ref())?;
+        write!(
+            out,
+            ",\"state\":\"{:?}\",\"current_priority\":{},\"base_priority\":{},\
+             \"run_time_counter\":{},\"stack_high_water_mark\":{}",
+            self.task_state,
+            self.current_priority.0,
+            self.base_priority.0,
+            self.run_time_counter,
+            self.stack_high_water_mark
+        )
+    }
+}
+
+fn write_json_lines<W: Write, Name: AsRef<str>>(
+    out: &mut W,
+    tasks: &[TaskStatus<Name>],
+    total_run_time: u32,
+) -> fmt::Result {
+    for task in tasks {
+        task.write_json_fields(out)?;
+        writeln!(out, ",\"total_run_time\":{}}}", total_run_time)?;
+    }
+    Ok(())
+}
+
+fn write_csv<W: Write, Name: AsRef<str>>(
+    out: &mut W,
+    tasks: &[TaskStatus<Name>],
+    total_run_time: u32,
+    header: bool,
+) -> fmt::Result {
+    if header {
+        out.write_str(CSV_HEADER)?;
+    }
+    for task in tasks {
+        write!(out, "{},", task.task_number)?;
+        write_csv_field(out, task.name.as_ref())?;
+        writeln!(
+            out,
+            ",{:?},{},{},{},{},{}",
+            task.task_state,
+            task.current_priority.0,
+            task.base_priority.0,
+            task.run_time_counter,
+            task.stack_high_water_mark,
+            total_run_time
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_json_string<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// Quote the field if it contains anything that would break the row.
+fn write_csv_field<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    if !s.contains([',', '"', '\r', '\n']) {
+        return out.write_str(s);
+    }
+
+    out.write_char('"')?;
+    for c in s.chars() {
+        if c == '"' {
+            out.write_char('"')?;
+        }
+        out.write_char(c)?;
+    }
+    out.write_char('"')
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::prelude::*;
+    use crate::units::*;
+    use alloc::format;
+
+    #[test]
+    fn lines_end_with_newline_and_names_are_escaped() {
+        let kernel = Kernel::new();
+        Task::new()
+            .name("a,\"b\"")
+            .priority(TaskPriority(3))
+            .stack_size(256)
+            .start(|| CurrentTask::delay(Ticks::infinite()))
+            .unwrap();
+        kernel.run();
+
+        let state = get_all_tasks(None);
+        let number = state.tasks[0].task_number;
+
+        let mut json = String::new();
+        state.write_json_lines(&mut json).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                "{{\"task_number\":{},\"name\":\"a,\\\"b\\\"\",\"state\":\"Suspended\",\
+                 \"current_priority\":3,\"base_priority\":3,\"run_time_counter\":0,\
+                 \"stack_high_water_mark\":256,\"total_run_time\":0}}\n",
+                number
+            )
+        );
+
+        let mut csv = String::new();
+        state.write_csv(&mut csv, true).unwrap();
+        assert_eq!(
+            csv,
+            format!(
+                "{}{},\"a,\"\"b\"\"\",Suspended,3,3,0,256,0\n",
+                CSV_HEADER, number
+            )
+        );
+    }
+}
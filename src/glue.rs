@@ -2,10 +2,16 @@ use core::mem;
 use core::ptr;
 
 use crate::base::*;
+use crate::port;
 
 pub use sys::configTICK_RATE_HZ as TICK_RATE_HZ;
 pub use sys::portMAX_DELAY as MAX_DELAY;
 pub const TICK_PERIOD_MS: u32 = 1000 / TICK_RATE_HZ;
+pub const MAX_TASK_NAME_LEN: usize = sys::configMAX_TASK_NAME_LEN as usize;
+pub const NUM_TLS_POINTERS: BaseType = sys::configNUM_THREAD_LOCAL_STORAGE_POINTERS as _;
+pub use crate::port::NOTIFICATION_ARRAY_ENTRIES;
+pub use crate::port::NUMBER_OF_CORES;
+pub use crate::port::Spinlock;
 
 #[cfg(feature = "use-platform-strlen")]
 #[inline(always)]
@@ -46,7 +52,7 @@ pub unsafe fn task_delay_until(
     pxPreviousWakeTime: *mut TickType,
     xTimeIncrement: TickType,
 ) -> bool {
-    sys::xTaskDelayUntil(pxPreviousWakeTime, xTimeIncrement) == sys::pdTRUE
+    port::task_delay_until(pxPreviousWakeTime, xTimeIncrement)
 }
 #[inline(always)]
 pub unsafe fn task_delay(xTicksToDelay: TickType) {
@@ -70,11 +76,22 @@ pub unsafe fn task_get_tick_count_from_isr() -> TickType {
 
 #[inline(always)]
 pub unsafe fn create_recursive_mutex() -> MaybeQueueHandle {
-    mem::transmute(sys::xQueueCreateMutex(sys::queueQUEUE_TYPE_RECURSIVE_MUTEX))
+    mem::transmute(sys::xQueueCreateMutex(port::QUEUE_TYPE_RECURSIVE_MUTEX))
 }
 #[inline(always)]
 pub unsafe fn create_mutex() -> MaybeQueueHandle {
-    mem::transmute(sys::xQueueCreateMutex(sys::queueQUEUE_TYPE_MUTEX))
+    mem::transmute(sys::xQueueCreateMutex(port::QUEUE_TYPE_MUTEX))
+}
+#[inline(always)]
+pub unsafe fn create_recursive_mutex_static(buffer: *mut StaticQueueFfi) -> MaybeQueueHandle {
+    mem::transmute(sys::xQueueCreateMutexStatic(
+        port::QUEUE_TYPE_RECURSIVE_MUTEX,
+        buffer,
+    ))
+}
+#[inline(always)]
+pub unsafe fn create_mutex_static(buffer: *mut StaticQueueFfi) -> MaybeQueueHandle {
+    mem::transmute(sys::xQueueCreateMutexStatic(port::QUEUE_TYPE_MUTEX, buffer))
 }
 
 #[inline(always)]
@@ -90,6 +107,21 @@ pub unsafe fn give_mutex_isr(mutex: QueueHandle, xHigherPriorityTaskWoken: *mut
     sys::xQueueGiveFromISR(mutex.as_ptr() as _, xHigherPriorityTaskWoken) == sys::pdTRUE
 }
 #[inline(always)]
+pub unsafe fn take_semaphore_isr(
+    semaphore: QueueHandle,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    sys::xQueueReceiveFromISR(
+        semaphore.as_ptr() as *mut _,
+        ptr::null_mut(),
+        xHigherPriorityTaskWoken,
+    ) == sys::pdTRUE
+}
+#[inline(always)]
+pub unsafe fn get_semaphore_count(semaphore: QueueHandle) -> UBaseType {
+    sys::uxQueueMessagesWaiting(semaphore.as_ptr() as *mut _)
+}
+#[inline(always)]
 pub unsafe fn give_recursive_mutex(mutex: QueueHandle) -> bool {
     sys::xQueueGiveMutexRecursive(mutex.as_ptr() as *mut _) == sys::pdTRUE
 }
@@ -97,6 +129,10 @@ pub unsafe fn give_recursive_mutex(mutex: QueueHandle) -> bool {
 pub unsafe fn take_recursive_mutex(mutex: QueueHandle, max: TickType) -> bool {
     sys::xQueueTakeMutexRecursive(mutex.as_ptr() as *mut _, max) == sys::pdTRUE
 }
+#[inline(always)]
+pub unsafe fn mutex_get_holder(mutex: QueueHandle) -> MaybeTaskHandle {
+    mem::transmute(sys::xQueueGetMutexHolder(mutex.as_ptr() as *mut _))
+}
 
 #[inline(always)]
 pub unsafe fn delete_semaphore(mutex: QueueHandle) {
@@ -108,34 +144,111 @@ pub unsafe fn create_binary_semaphore() -> MaybeQueueHandle {
     mem::transmute(sys::xQueueGenericCreate(
         1,
         0,
-        sys::queueQUEUE_TYPE_BINARY_SEMAPHORE,
+        port::QUEUE_TYPE_BINARY_SEMAPHORE,
     ))
 }
 #[inline(always)]
 pub unsafe fn create_counting_semaphore(max: UBaseType, initial: UBaseType) -> MaybeQueueHandle {
     mem::transmute(sys::xQueueCreateCountingSemaphore(max, initial))
 }
+#[inline(always)]
+pub unsafe fn create_binary_semaphore_static(buffer: *mut StaticQueueFfi) -> MaybeQueueHandle {
+    mem::transmute(sys::xQueueGenericCreateStatic(
+        1,
+        0,
+        ptr::null_mut(),
+        buffer,
+        port::QUEUE_TYPE_BINARY_SEMAPHORE,
+    ))
+}
+#[inline(always)]
+pub unsafe fn create_counting_semaphore_static(
+    max: UBaseType,
+    initial: UBaseType,
+    buffer: *mut StaticQueueFfi,
+) -> MaybeQueueHandle {
+    mem::transmute(sys::xQueueCreateCountingSemaphoreStatic(
+        max, initial, buffer,
+    ))
+}
 
 #[inline(always)]
 pub unsafe fn queue_create(length: UBaseType, item_size: UBaseType) -> MaybeQueueHandle {
     mem::transmute(sys::xQueueGenericCreate(
         length,
         item_size,
-        sys::queueQUEUE_TYPE_BASE,
+        port::QUEUE_TYPE_BASE,
+    ))
+}
+#[inline(always)]
+pub unsafe fn queue_create_static(
+    length: UBaseType,
+    item_size: UBaseType,
+    storage: *mut u8,
+    buffer: *mut StaticQueueFfi,
+) -> MaybeQueueHandle {
+    mem::transmute(sys::xQueueGenericCreateStatic(
+        length,
+        item_size,
+        storage,
+        buffer,
+        port::QUEUE_TYPE_BASE,
     ))
 }
 #[inline(always)]
 pub unsafe fn queue_delete(queue: QueueHandle) {
     sys::vQueueDelete(queue.as_ptr() as *mut _)
 }
+// The `queueSEND_TO_*` and `queueOVERWRITE` macros contain casts and aren't picked up by
+// bindgen.
+const QUEUE_SEND_TO_BACK: BaseType = 0;
+const QUEUE_SEND_TO_FRONT: BaseType = 1;
+const QUEUE_OVERWRITE: BaseType = 2;
+
 #[inline(always)]
 pub unsafe fn queue_send(queue: QueueHandle, item: *const c_void, max_wait: TickType) -> bool {
-    sys::xQueueGenericSend(queue.as_ptr() as *mut _, item, max_wait, 0) == sys::pdTRUE
+    sys::xQueueGenericSend(queue.as_ptr() as *mut _, item, max_wait, QUEUE_SEND_TO_BACK)
+        == sys::pdTRUE
 }
+#[inline(always)]
+pub unsafe fn queue_send_to_front(
+    queue: QueueHandle,
+    item: *const c_void,
+    max_wait: TickType,
+) -> bool {
+    sys::xQueueGenericSend(
+        queue.as_ptr() as *mut _,
+        item,
+        max_wait,
+        QUEUE_SEND_TO_FRONT,
+    ) == sys::pdTRUE
+}
+#[inline(always)]
+pub unsafe fn queue_overwrite(queue: QueueHandle, item: *const c_void) -> bool {
+    sys::xQueueGenericSend(queue.as_ptr() as *mut _, item, 0, QUEUE_OVERWRITE) == sys::pdTRUE
+}
+
 #[inline(always)]
 pub unsafe fn queue_receive(queue: QueueHandle, item: *mut c_void, max_wait: TickType) -> bool {
     sys::xQueueReceive(queue.as_ptr() as *mut _, item, max_wait) == sys::pdTRUE
 }
+#[inline(always)]
+pub unsafe fn queue_peek(queue: QueueHandle, item: *mut c_void, max_wait: TickType) -> bool {
+    sys::xQueuePeek(queue.as_ptr() as *mut _, item, max_wait) == sys::pdTRUE
+}
+
+#[inline(always)]
+pub unsafe fn queue_messages_waiting(queue: QueueHandle) -> UBaseType {
+    sys::uxQueueMessagesWaiting(queue.as_ptr() as *mut _)
+}
+#[inline(always)]
+pub unsafe fn queue_spaces_available(queue: QueueHandle) -> UBaseType {
+    sys::uxQueueSpacesAvailable(queue.as_ptr() as *mut _)
+}
+#[inline(always)]
+pub unsafe fn queue_reset(queue: QueueHandle) {
+    sys::xQueueGenericReset(queue.as_ptr() as *mut _, sys::pdFALSE as _);
+}
 
 #[inline(always)]
 pub unsafe fn queue_send_isr(
@@ -143,9 +256,65 @@ pub unsafe fn queue_send_isr(
     item: *const c_void,
     xHigherPriorityTaskWoken: *mut BaseType,
 ) -> bool {
-    sys::xQueueGenericSendFromISR(queue.as_ptr() as *mut _, item, xHigherPriorityTaskWoken, 0)
+    sys::xQueueGenericSendFromISR(
+        queue.as_ptr() as *mut _,
+        item,
+        xHigherPriorityTaskWoken,
+        QUEUE_SEND_TO_BACK,
+    ) == sys::pdTRUE
+}
+#[inline(always)]
+pub unsafe fn queue_overwrite_isr(
+    queue: QueueHandle,
+    item: *const c_void,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    sys::xQueueGenericSendFromISR(
+        queue.as_ptr() as *mut _,
+        item,
+        xHigherPriorityTaskWoken,
+        QUEUE_OVERWRITE,
+    ) == sys::pdTRUE
+}
+#[inline(always)]
+pub unsafe fn queue_receive_isr(
+    queue: QueueHandle,
+    item: *mut c_void,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    sys::xQueueReceiveFromISR(queue.as_ptr() as *mut _, item, xHigherPriorityTaskWoken)
         == sys::pdTRUE
 }
+#[inline(always)]
+pub unsafe fn queue_peek_isr(queue: QueueHandle, item: *mut c_void) -> bool {
+    sys::xQueuePeekFromISR(queue.as_ptr() as *mut _, item) == sys::pdTRUE
+}
+#[inline(always)]
+pub unsafe fn queue_is_full_isr(queue: QueueHandle) -> bool {
+    sys::xQueueIsQueueFullFromISR(queue.as_ptr() as *mut _) != sys::pdFALSE
+}
+
+#[inline(always)]
+pub unsafe fn queue_set_create(length: UBaseType) -> MaybeQueueHandle {
+    mem::transmute(sys::xQueueCreateSet(length))
+}
+#[inline(always)]
+pub unsafe fn queue_set_add(member: QueueHandle, set: QueueHandle) -> bool {
+    sys::xQueueAddToSet(member.as_ptr() as _, set.as_ptr() as _) == sys::pdPASS
+}
+#[inline(always)]
+pub unsafe fn queue_set_remove(member: QueueHandle, set: QueueHandle) -> bool {
+    sys::xQueueRemoveFromSet(member.as_ptr() as _, set.as_ptr() as _) == sys::pdPASS
+}
+#[inline(always)]
+pub unsafe fn queue_set_select(set: QueueHandle, max_wait: TickType) -> MaybeQueueHandle {
+    mem::transmute(sys::xQueueSelectFromSet(set.as_ptr() as _, max_wait))
+}
+#[inline(always)]
+pub unsafe fn queue_set_select_isr(set: QueueHandle) -> MaybeQueueHandle {
+    mem::transmute(sys::xQueueSelectFromSetFromISR(set.as_ptr() as _))
+}
+
 #[inline(always)]
 pub unsafe fn task_yield_from_isr() {
     sys::vPortYieldFromISR()
@@ -157,52 +326,44 @@ pub unsafe fn task_yield() {
 }
 
 #[inline(always)]
-pub unsafe fn task_notify_take(clear_count: bool, wait: TickType) -> u32 {
-    sys::ulTaskGenericNotifyTake(
-        0,
-        if clear_count {
-            sys::pdTRUE
-        } else {
-            sys::pdFALSE
-        },
-        wait,
-    )
+pub unsafe fn task_notify_take(index: UBaseType, clear_count: bool, wait: TickType) -> u32 {
+    port::task_notify_take(index, clear_count, wait)
 }
 #[inline(always)]
 pub unsafe fn task_notify_wait(
+    index: UBaseType,
     ulBitsToClearOnEntry: u32,
     ulBitsToClearOnExit: u32,
     pulNotificationValue: *mut u32,
     xTicksToWait: TickType,
 ) -> bool {
-    sys::xTaskGenericNotifyWait(
-        0,
+    port::task_notify_wait(
+        index,
         ulBitsToClearOnEntry,
         ulBitsToClearOnExit,
         pulNotificationValue,
         xTicksToWait,
-    ) == sys::pdPASS
+    )
 }
 
 #[inline(always)]
-pub unsafe fn task_notify(task: TaskHandle, value: u32, action: sys::eNotifyAction) -> bool {
-    sys::xTaskGenericNotify(task.as_ptr() as _, 0, value, action, ptr::null_mut()) == sys::pdPASS
+pub unsafe fn task_notify(
+    task: TaskHandle,
+    index: UBaseType,
+    value: u32,
+    action: sys::eNotifyAction,
+) -> bool {
+    port::task_notify(task, index, value, action)
 }
 #[inline(always)]
 pub unsafe fn task_notify_isr(
     task: TaskHandle,
+    index: UBaseType,
     value: u32,
     action: sys::eNotifyAction,
     xHigherPriorityTaskWoken: *mut BaseType,
 ) -> bool {
-    sys::xTaskGenericNotifyFromISR(
-        task.as_ptr() as _,
-        0,
-        value,
-        action,
-        ptr::null_mut(),
-        xHigherPriorityTaskWoken,
-    ) == sys::pdPASS
+    port::task_notify_isr(task, index, value, action, xHigherPriorityTaskWoken)
 }
 
 #[inline(always)]
@@ -212,6 +373,7 @@ pub unsafe fn create_task(
     name: &str,
     stack_size: u16,
     priority: UBaseType,
+    core_affinity_mask: UBaseType,
     task_handle: &mut MaybeTaskHandle,
 ) -> bool {
     let mut buf = [0u8; sys::configMAX_TASK_NAME_LEN as usize];
@@ -219,14 +381,40 @@ pub unsafe fn create_task(
     let size = (sys::configMAX_TASK_NAME_LEN as usize - 1).min(name_bytes.len());
     buf[..size].copy_from_slice(&name_bytes[..size]);
 
-    sys::xTaskCreate(
-        Some(f),
+    port::create_task(
+        f,
+        value,
         buf.as_ptr() as _,
         stack_size,
+        priority,
+        core_affinity_mask,
+        task_handle,
+    )
+}
+#[inline(always)]
+pub unsafe fn create_task_static(
+    f: extern "C" fn(*mut c_void),
+    value: *mut c_void,
+    name: &str,
+    stack: &mut [StackType],
+    priority: UBaseType,
+    core_affinity_mask: UBaseType,
+    tcb: *mut StaticTaskFfi,
+) -> Result<TaskHandle, FreeRtosError> {
+    let mut buf = [0u8; sys::configMAX_TASK_NAME_LEN as usize];
+    let name_bytes = name.as_bytes();
+    let size = (sys::configMAX_TASK_NAME_LEN as usize - 1).min(name_bytes.len());
+    buf[..size].copy_from_slice(&name_bytes[..size]);
+
+    port::create_task_static(
+        f,
         value,
+        buf.as_ptr() as _,
+        stack,
         priority,
-        mem::transmute(task_handle),
-    ) == sys::pdPASS
+        core_affinity_mask,
+        tcb,
+    )
 }
 #[inline(always)]
 pub unsafe fn delete_task(task: MaybeTaskHandle) {
@@ -240,6 +428,75 @@ pub unsafe fn task_get_name(task: TaskHandle) -> *const c_char {
 pub unsafe fn get_stack_high_water_mark(task: MaybeTaskHandle) -> UBaseType {
     sys::uxTaskGetStackHighWaterMark(mem::transmute(task))
 }
+#[inline(always)]
+pub unsafe fn task_suspend(task: MaybeTaskHandle) {
+    sys::vTaskSuspend(mem::transmute(task))
+}
+#[inline(always)]
+pub unsafe fn task_resume(task: TaskHandle) {
+    sys::vTaskResume(task.as_ptr() as _)
+}
+#[inline(always)]
+pub unsafe fn task_resume_isr(task: TaskHandle, xHigherPriorityTaskWoken: *mut BaseType) {
+    if sys::xTaskResumeFromISR(task.as_ptr() as _) == sys::pdTRUE {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+}
+#[inline(always)]
+pub unsafe fn task_abort_delay(task: TaskHandle) -> bool {
+    sys::xTaskAbortDelay(task.as_ptr() as _) == sys::pdPASS
+}
+#[inline(always)]
+pub unsafe fn task_get_priority(task: MaybeTaskHandle) -> UBaseType {
+    sys::uxTaskPriorityGet(mem::transmute(task))
+}
+#[inline(always)]
+pub unsafe fn task_set_priority(task: MaybeTaskHandle, priority: UBaseType) {
+    sys::vTaskPrioritySet(mem::transmute(task), priority)
+}
+#[inline(always)]
+pub unsafe fn task_get_state(task: TaskHandle) -> sys::eTaskState {
+    sys::eTaskGetState(task.as_ptr() as _)
+}
+
+/// `eInvalid`, lets `vTaskGetInfo` find out the state itself.
+const TASK_STATE_INVALID: sys::eTaskState = 5;
+/// `eReady`, the kernel still reports a running task as running.
+const TASK_STATE_READY: sys::eTaskState = 1;
+
+#[inline(always)]
+pub unsafe fn task_get_info(task: TaskHandle, status: *mut TaskStatusFfi) {
+    sys::vTaskGetInfo(task.as_ptr() as _, status, sys::pdTRUE, TASK_STATE_INVALID)
+}
+/// For a task that is known to be ready or running. Given the state, `vTaskGetInfo`
+/// neither enters a critical section nor suspends the scheduler.
+#[inline(always)]
+pub unsafe fn task_get_info_ready(task: TaskHandle, status: *mut TaskStatusFfi) {
+    sys::vTaskGetInfo(task.as_ptr() as _, status, sys::pdTRUE, TASK_STATE_READY)
+}
+#[inline(always)]
+pub unsafe fn task_set_tls_pointer(task: MaybeTaskHandle, index: BaseType, value: *mut c_void) {
+    sys::vTaskSetThreadLocalStoragePointer(mem::transmute(task), index, value)
+}
+#[cfg(feature = "tls-deletion-callbacks")]
+#[inline(always)]
+pub unsafe fn task_set_tls_pointer_with_callback(
+    task: MaybeTaskHandle,
+    index: BaseType,
+    value: *mut c_void,
+    callback: unsafe extern "C" fn(i32, *mut c_void),
+) {
+    sys::vTaskSetThreadLocalStoragePointerAndDelCallback(
+        mem::transmute(task),
+        index,
+        value,
+        Some(callback),
+    )
+}
+#[inline(always)]
+pub unsafe fn task_get_tls_pointer(task: MaybeTaskHandle, index: BaseType) -> *mut c_void {
+    sys::pvTaskGetThreadLocalStoragePointer(mem::transmute(task), index)
+}
 
 #[inline(always)]
 pub unsafe fn get_current_task() -> MaybeTaskHandle {
@@ -253,22 +510,22 @@ pub unsafe fn get_system_state(
 ) -> UBaseType {
     sys::uxTaskGetSystemState(tasks as _, tasks_len, total_run_time)
 }
+#[inline(always)]
+pub unsafe fn get_idle_task_handle(core: UBaseType) -> MaybeTaskHandle {
+    port::get_idle_task_handle(core)
+}
 
+// The kernel keeps the `name` pointer, it must live as long as the timer.
 #[inline(always)]
 pub unsafe fn timer_create(
-    name: &str,
+    name: *const c_char,
     period: TickType,
     auto_reload: bool,
     timer_id: usize,
     callback: extern "C" fn(TimerHandle),
 ) -> MaybeTimerHandle {
-    let mut buf = [0u8; sys::configMAX_TASK_NAME_LEN as usize];
-    let name_bytes = name.as_bytes();
-    let size = (sys::configMAX_TASK_NAME_LEN as usize - 1).min(name_bytes.len());
-    buf[..size].copy_from_slice(&name_bytes[..size]);
-
     mem::transmute(sys::xTimerCreate(
-        buf.as_ptr() as _,
+        name,
         period,
         if auto_reload {
             sys::pdTRUE as _
@@ -280,24 +537,43 @@ pub unsafe fn timer_create(
     ))
 }
 #[inline(always)]
+pub unsafe fn timer_create_static(
+    name: *const c_char,
+    period: TickType,
+    auto_reload: bool,
+    timer_id: usize,
+    callback: extern "C" fn(TimerHandle),
+    buffer: *mut StaticTimerFfi,
+) -> MaybeTimerHandle {
+    mem::transmute(sys::xTimerCreateStatic(
+        name,
+        period,
+        if auto_reload {
+            sys::pdTRUE as _
+        } else {
+            sys::pdFALSE as _
+        },
+        timer_id as _,
+        Some(mem::transmute(callback)),
+        buffer,
+    ))
+}
+#[inline(always)]
 pub unsafe fn timer_start(timer: TimerHandle, block_time: TickType) -> bool {
-    sys::xTimerGenericCommandFromTask(
-        timer.as_ptr() as _,
-        1,
+    port::timer_command(
+        timer,
+        port::TIMER_COMMAND_START,
         sys::xTaskGetTickCount(),
-        ptr::null_mut(),
         block_time,
-    ) == sys::pdPASS
+    )
 }
 #[inline(always)]
 pub unsafe fn timer_stop(timer: TimerHandle, block_time: TickType) -> bool {
-    sys::xTimerGenericCommandFromTask(timer.as_ptr() as _, 3, 0, ptr::null_mut(), block_time)
-        == sys::pdPASS
+    port::timer_command(timer, port::TIMER_COMMAND_STOP, 0, block_time)
 }
 #[inline(always)]
 pub unsafe fn timer_delete(timer: TimerHandle, block_time: TickType) -> bool {
-    sys::xTimerGenericCommandFromTask(timer.as_ptr() as _, 5, 0, ptr::null_mut(), block_time)
-        == sys::pdPASS
+    port::timer_command(timer, port::TIMER_COMMAND_DELETE, 0, block_time)
 }
 #[inline(always)]
 pub unsafe fn timer_change_period(
@@ -305,40 +581,219 @@ pub unsafe fn timer_change_period(
     block_time: TickType,
     new_period: TickType,
 ) -> bool {
-    sys::xTimerGenericCommandFromTask(
-        timer.as_ptr() as _,
-        4,
+    port::timer_command(
+        timer,
+        port::TIMER_COMMAND_CHANGE_PERIOD,
         new_period,
-        ptr::null_mut(),
         block_time,
-    ) == sys::pdPASS
+    )
 }
 #[inline(always)]
 pub unsafe fn timer_reset(timer: TimerHandle, block_time: TickType) -> bool {
-    sys::xTimerGenericCommandFromTask(
-        timer.as_ptr() as _,
-        2,
+    port::timer_command(
+        timer,
+        port::TIMER_COMMAND_RESET,
         sys::xTaskGetTickCount(),
-        ptr::null_mut(),
         block_time,
-    ) == sys::pdPASS
+    )
 }
 #[inline(always)]
 pub unsafe fn timer_get_id(timer: TimerHandle) -> usize {
     sys::pvTimerGetTimerID(timer.as_ptr() as _) as usize
 }
+/// Run `f` on the timer daemon task, after the timer commands queued before.
+#[inline(always)]
+pub unsafe fn timer_pend_function_call(
+    f: extern "C" fn(*mut c_void, u32),
+    param: *mut c_void,
+    block_time: TickType,
+) -> bool {
+    sys::xTimerPendFunctionCall(Some(mem::transmute(f)), param, 0, block_time) == sys::pdPASS
+}
 
 #[inline(always)]
-pub unsafe fn enter_critical() -> UBaseType {
-    sys::ulTaskEnterCriticalFromISR()
+pub unsafe fn event_group_create() -> MaybeEventGroupHandle {
+    mem::transmute(sys::xEventGroupCreate())
+}
+#[inline(always)]
+pub unsafe fn event_group_create_static(buffer: *mut StaticEventGroupFfi) -> MaybeEventGroupHandle {
+    mem::transmute(sys::xEventGroupCreateStatic(buffer))
 }
 #[inline(always)]
-pub unsafe fn exit_critical(previous_state: UBaseType) {
-    sys::vTaskExitCriticalFromISR(previous_state)
+pub unsafe fn event_group_delete(group: EventGroupHandle) {
+    sys::vEventGroupDelete(group.as_ptr() as _)
+}
+#[inline(always)]
+pub unsafe fn event_group_set_bits(group: EventGroupHandle, bits: EventBits) -> EventBits {
+    sys::xEventGroupSetBits(group.as_ptr() as _, bits)
+}
+#[inline(always)]
+pub unsafe fn event_group_clear_bits(group: EventGroupHandle, bits: EventBits) -> EventBits {
+    sys::xEventGroupClearBits(group.as_ptr() as _, bits)
+}
+#[inline(always)]
+pub unsafe fn event_group_wait_bits(
+    group: EventGroupHandle,
+    bits: EventBits,
+    clear_on_exit: bool,
+    wait_for_all: bool,
+    xTicksToWait: TickType,
+) -> EventBits {
+    sys::xEventGroupWaitBits(
+        group.as_ptr() as _,
+        bits,
+        clear_on_exit as BaseType,
+        wait_for_all as BaseType,
+        xTicksToWait,
+    )
+}
+#[inline(always)]
+pub unsafe fn event_group_sync(
+    group: EventGroupHandle,
+    bits_to_set: EventBits,
+    bits_to_wait_for: EventBits,
+    xTicksToWait: TickType,
+) -> EventBits {
+    sys::xEventGroupSync(
+        group.as_ptr() as _,
+        bits_to_set,
+        bits_to_wait_for,
+        xTicksToWait,
+    )
+}
+#[inline(always)]
+pub unsafe fn event_group_get_bits_from_isr(group: EventGroupHandle) -> EventBits {
+    sys::xEventGroupGetBitsFromISR(group.as_ptr() as _)
+}
+/// Deferred to the timer daemon task, like `xEventGroupSetBitsFromISR`.
+#[inline(always)]
+pub unsafe fn event_group_set_bits_from_isr(
+    group: EventGroupHandle,
+    bits: EventBits,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    sys::xTimerPendFunctionCallFromISR(
+        Some(sys::vEventGroupSetBitsCallback),
+        group.as_ptr() as _,
+        bits as _,
+        xHigherPriorityTaskWoken,
+    ) == sys::pdPASS
+}
+/// Deferred to the timer daemon task, like `xEventGroupClearBitsFromISR`.
+#[inline(always)]
+pub unsafe fn event_group_clear_bits_from_isr(group: EventGroupHandle, bits: EventBits) -> bool {
+    sys::xTimerPendFunctionCallFromISR(
+        Some(sys::vEventGroupClearBitsCallback),
+        group.as_ptr() as _,
+        bits as _,
+        ptr::null_mut(),
+    ) == sys::pdPASS
+}
+
+#[inline(always)]
+pub unsafe fn stream_buffer_create(
+    size: usize,
+    trigger_level: usize,
+    is_message_buffer: bool,
+) -> MaybeStreamBufferHandle {
+    port::stream_buffer_create(size, trigger_level, is_message_buffer)
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_delete(buffer: StreamBufferHandle) {
+    sys::vStreamBufferDelete(buffer.as_ptr() as _)
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_send(
+    buffer: StreamBufferHandle,
+    data: *const c_void,
+    len: usize,
+    max_wait: TickType,
+) -> usize {
+    sys::xStreamBufferSend(buffer.as_ptr() as _, data, len, max_wait)
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_send_isr(
+    buffer: StreamBufferHandle,
+    data: *const c_void,
+    len: usize,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> usize {
+    sys::xStreamBufferSendFromISR(buffer.as_ptr() as _, data, len, xHigherPriorityTaskWoken)
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_receive(
+    buffer: StreamBufferHandle,
+    data: *mut c_void,
+    len: usize,
+    max_wait: TickType,
+) -> usize {
+    sys::xStreamBufferReceive(buffer.as_ptr() as _, data, len, max_wait)
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_receive_isr(
+    buffer: StreamBufferHandle,
+    data: *mut c_void,
+    len: usize,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> usize {
+    sys::xStreamBufferReceiveFromISR(buffer.as_ptr() as _, data, len, xHigherPriorityTaskWoken)
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_bytes_available(buffer: StreamBufferHandle) -> usize {
+    sys::xStreamBufferBytesAvailable(buffer.as_ptr() as _)
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_spaces_available(buffer: StreamBufferHandle) -> usize {
+    sys::xStreamBufferSpacesAvailable(buffer.as_ptr() as _)
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_set_trigger_level(buffer: StreamBufferHandle, level: usize) -> bool {
+    sys::xStreamBufferSetTriggerLevel(buffer.as_ptr() as _, level) == sys::pdTRUE
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_reset(buffer: StreamBufferHandle) -> bool {
+    sys::xStreamBufferReset(buffer.as_ptr() as _) == sys::pdPASS
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_is_empty(buffer: StreamBufferHandle) -> bool {
+    sys::xStreamBufferIsEmpty(buffer.as_ptr() as _) == sys::pdTRUE
+}
+#[inline(always)]
+pub unsafe fn stream_buffer_is_full(buffer: StreamBufferHandle) -> bool {
+    sys::xStreamBufferIsFull(buffer.as_ptr() as _) == sys::pdTRUE
+}
+#[inline(always)]
+pub unsafe fn message_buffer_next_length(buffer: StreamBufferHandle) -> usize {
+    sys::xStreamBufferNextMessageLengthBytes(buffer.as_ptr() as _)
 }
 
-#[cfg(feature = "smp")]
 #[inline(always)]
-pub unsafe fn set_core_affinity(task_handle: MaybeTaskHandle, core_affinity_mask: UBaseType) -> () {
-    sys::vTaskCoreAffinitySet(mem::transmute(task_handle), core_affinity_mask);
+pub unsafe fn enter_critical() {
+    port::enter_critical()
+}
+#[inline(always)]
+pub unsafe fn exit_critical() {
+    port::exit_critical()
+}
+#[inline(always)]
+pub unsafe fn enter_critical_isr() -> UBaseType {
+    port::enter_critical_isr()
+}
+#[inline(always)]
+pub unsafe fn exit_critical_isr(previous_state: UBaseType) {
+    port::exit_critical_isr(previous_state)
 }
+#[inline(always)]
+pub unsafe fn is_inside_interrupt() -> bool {
+    sys::xPortInIsrContext() != sys::pdFALSE
+}
+
+#[inline(always)]
+pub unsafe fn task_suspend_all() {
+    sys::vTaskSuspendAll()
+}
+#[inline(always)]
+pub unsafe fn task_resume_all() -> bool {
+    sys::xTaskResumeAll() == sys::pdTRUE
+}
+
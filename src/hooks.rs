@@ -0,0 +1,249 @@
+//! Rust handlers for the application hooks of the kernel.
+//!
+//! Every hook is exported as the C symbol the kernel calls when its feature is enabled,
+//! the matching `configUSE_*_HOOK` or `configCHECK_FOR_STACK_OVERFLOW` option must be set
+//! in `FreeRTOSConfig.h` as well. Handlers are plain functions, they run in the context the
+//! kernel calls the hook in and must not block.
+//!
+//! ```ignore
+//! hooks::on_stack_overflow(|_task, name| log::error!("stack overflow in {}", name));
+//! hooks::on_idle(|| cortex_m::asm::wfi()).unwrap();
+//! ```
+
+use core::mem;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(any(feature = "hook-stack-overflow", feature = "hook-idle"))]
+use crate::base::*;
+#[cfg(feature = "hook-stack-overflow")]
+use crate::task::Task;
+
+/// A registered handler, stored as the address of the function, 0 if there is none.
+struct Handler(AtomicUsize);
+
+impl Handler {
+    const fn new() -> Handler {
+        Handler(AtomicUsize::new(0))
+    }
+
+    // Only the idle hook doesn't use it.
+    #[allow(dead_code)]
+    fn set(&self, handler: usize) {
+        self.0.store(handler, Ordering::Release);
+    }
+
+    fn get(&self) -> Option<usize> {
+        match self.0.load(Ordering::Acquire) {
+            0 => None,
+            handler => Some(handler),
+        }
+    }
+}
+
+#[cfg(feature = "hook-stack-overflow")]
+static STACK_OVERFLOW: Handler = Handler::new();
+
+/// Call `handler` with the task and its name when the kernel detects a stack overflow.
+/// Once it returns the hook never does, the task's memory can't be trusted anymore: it
+/// spins forever, so a watchdog can reset the system.
+///
+/// The scheduler can't be suspended from the handler, use
+/// [`TaskSnapshot::take_from_hook()`](crate::TaskSnapshot::take_from_hook) to dump the tasks.
+#[cfg(feature = "hook-stack-overflow")]
+pub fn on_stack_overflow(handler: fn(Task, &str)) {
+    STACK_OVERFLOW.set(handler as usize);
+}
+
+/// Called by the kernel when it detects that a task overflowed its stack.
+///
+/// # Safety
+///
+/// `task` and `name` have to be the handle and the name of that task.
+#[cfg(feature = "hook-stack-overflow")]
+#[no_mangle]
+pub unsafe extern "C" fn vApplicationStackOverflowHook(task: TaskHandle, name: *const c_char) {
+    // The name is in the control block of the task, which might be damaged too.
+    let bytes = core::slice::from_raw_parts(name as *const u8, crate::glue::MAX_TASK_NAME_LEN);
+    let bytes = &bytes[..bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len())];
+    let name = match core::str::from_utf8(bytes) {
+        Ok(name) => name,
+        Err(e) => core::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]),
+    };
+
+    if let Some(handler) = STACK_OVERFLOW.get() {
+        let handler: fn(Task, &str) = mem::transmute(handler);
+        handler(Task::from_raw(task), name);
+    }
+
+    // Panicking can't unwind out of an `extern "C"` function, so halt instead.
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[cfg(feature = "hook-malloc-failed")]
+static MALLOC_FAILED: Handler = Handler::new();
+
+/// Call `handler` when the kernel's heap can't satisfy an allocation, before the
+/// allocation fails.
+#[cfg(feature = "hook-malloc-failed")]
+pub fn on_malloc_failed(handler: fn()) {
+    MALLOC_FAILED.set(handler as usize);
+}
+
+/// Called by the kernel when its heap can't satisfy an allocation.
+///
+/// # Safety
+///
+/// Only the kernel may call it.
+#[cfg(feature = "hook-malloc-failed")]
+#[no_mangle]
+pub unsafe extern "C" fn vApplicationMallocFailedHook() {
+    if let Some(handler) = MALLOC_FAILED.get() {
+        let handler: fn() = mem::transmute(handler);
+        handler();
+    }
+}
+
+/// The number of handlers [`on_idle()`] accepts.
+#[cfg(feature = "hook-idle")]
+pub const MAX_IDLE_HANDLERS: usize = 8;
+
+#[cfg(feature = "hook-idle")]
+static IDLE: [Handler; MAX_IDLE_HANDLERS] = [
+    Handler::new(),
+    Handler::new(),
+    Handler::new(),
+    Handler::new(),
+    Handler::new(),
+    Handler::new(),
+    Handler::new(),
+    Handler::new(),
+];
+
+/// Add `handler` to the ones the idle task calls on every iteration, in the order they
+/// were added. Fails once [`MAX_IDLE_HANDLERS`] are registered.
+#[cfg(feature = "hook-idle")]
+pub fn on_idle(handler: fn()) -> Result<(), FreeRtosError> {
+    for slot in IDLE.iter() {
+        if slot
+            .0
+            .compare_exchange(0, handler as usize, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            return Ok(());
+        }
+    }
+    Err(FreeRtosError::OutOfMemory)
+}
+
+/// Called by the idle task on every iteration.
+///
+/// # Safety
+///
+/// Only the kernel may call it.
+#[cfg(feature = "hook-idle")]
+#[no_mangle]
+pub unsafe extern "C" fn vApplicationIdleHook() {
+    for handler in IDLE.iter().map_while(Handler::get) {
+        let handler: fn() = mem::transmute(handler);
+        handler();
+    }
+}
+
+#[cfg(feature = "hook-tick")]
+static TICK: Handler = Handler::new();
+
+/// Call `handler` from the tick interrupt, only functions that are safe to use from an
+/// interrupt may be used in it.
+#[cfg(feature = "hook-tick")]
+pub fn on_tick(handler: fn()) {
+    TICK.set(handler as usize);
+}
+
+/// Called by the kernel from the tick interrupt.
+///
+/// # Safety
+///
+/// Only the kernel may call it.
+#[cfg(feature = "hook-tick")]
+#[no_mangle]
+pub unsafe extern "C" fn vApplicationTickHook() {
+    if let Some(handler) = TICK.get() {
+        let handler: fn() = mem::transmute(handler);
+        handler();
+    }
+}
+
+#[cfg(feature = "hook-daemon-task-startup")]
+static DAEMON_TASK_STARTUP: Handler = Handler::new();
+
+/// Call `handler` once from the timer daemon task when it starts, before the first timer
+/// runs. Must be registered before the scheduler is started.
+#[cfg(feature = "hook-daemon-task-startup")]
+pub fn on_daemon_task_startup(handler: fn()) {
+    DAEMON_TASK_STARTUP.set(handler as usize);
+}
+
+/// Called by the timer daemon task when it starts.
+///
+/// # Safety
+///
+/// Only the kernel may call it.
+#[cfg(feature = "hook-daemon-task-startup")]
+#[no_mangle]
+pub unsafe extern "C" fn vApplicationDaemonTaskStartupHook() {
+    if let Some(handler) = DAEMON_TASK_STARTUP.get() {
+        let handler: fn() = mem::transmute(handler);
+        handler();
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "mock",
+    any(feature = "hook-idle", feature = "hook-malloc-failed")
+))]
+mod tests {
+    use core::sync::atomic::AtomicU32;
+
+    use super::*;
+
+    #[cfg(feature = "hook-idle")]
+    #[test]
+    fn idle_handlers_run_in_order() {
+        static CALLS: AtomicU32 = AtomicU32::new(0);
+        fn first() {
+            CALLS.store(CALLS.load(Ordering::SeqCst) * 10 + 1, Ordering::SeqCst);
+        }
+        fn second() {
+            CALLS.store(CALLS.load(Ordering::SeqCst) * 10 + 2, Ordering::SeqCst);
+        }
+        fn nothing() {}
+
+        on_idle(first).unwrap();
+        on_idle(second).unwrap();
+        unsafe { vApplicationIdleHook() };
+        assert_eq!(CALLS.load(Ordering::SeqCst), 12);
+
+        for _ in 2..MAX_IDLE_HANDLERS {
+            on_idle(nothing).unwrap();
+        }
+        assert_eq!(on_idle(nothing), Err(FreeRtosError::OutOfMemory));
+    }
+
+    #[cfg(feature = "hook-malloc-failed")]
+    #[test]
+    fn malloc_failed_handler_is_called() {
+        static CALLS: AtomicU32 = AtomicU32::new(0);
+        fn handler() {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+        }
+
+        unsafe { vApplicationMallocFailedHook() };
+        assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+        on_malloc_failed(handler);
+        unsafe { vApplicationMallocFailedHook() };
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,255 @@
+use core::cell::UnsafeCell;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicU8, Ordering};
+
+use crate::base::*;
+use crate::glue;
+use crate::prelude::*;
+use crate::task::*;
+#[cfg(feature = "thread-local-storage")]
+use crate::task_local::{store_on_delete, take_on_delete};
+use crate::units::*;
+
+const RUNNING: u8 = 0;
+const FINISHED: u8 = 1;
+const PANICKED: u8 = 2;
+const DELETED: u8 = 3;
+
+/// The notification index joining tasks wait on, reserved the first time it's needed.
+fn join_index() -> Result<NotificationIndex, FreeRtosError> {
+    static INDEX: AtomicU32 = AtomicU32::new(u32::MAX);
+    NotificationIndex::reserve_once(&INDEX).ok_or(FreeRtosError::NoNotificationIndex)
+}
+
+/// Shared between a joinable task and its [`JoinHandle`].
+pub(crate) struct Packet<R> {
+    state: AtomicU8,
+    /// The notification index the joining task waits on.
+    index: NotificationIndex,
+    /// Written by the task before it switches to `FINISHED`, only read after that.
+    result: UnsafeCell<Option<R>>,
+    /// The task waiting in [`JoinHandle::wait()`], null if none.
+    joiner: AtomicPtr<c_void>,
+}
+
+unsafe impl<R: Send> Sync for Packet<R> {}
+
+impl<R> Packet<R> {
+    /// Switch to `state` unless the task already left `RUNNING`, and wake the joining task.
+    fn complete(&self, state: u8) {
+        self.state
+            .compare_exchange(RUNNING, state, Ordering::SeqCst, Ordering::SeqCst)
+            .ok();
+
+        if let Some(joiner) = TaskHandle::new(self.joiner.load(Ordering::SeqCst)) {
+            Task::from_raw(joiner).notify_indexed(self.index, TaskNotification::Increment);
+        }
+    }
+}
+
+/// The task's side of the packet, dropping it without a result means the task was deleted.
+struct Completion<R>(Arc<Packet<R>>);
+
+impl<R> Drop for Completion<R> {
+    fn drop(&mut self) {
+        self.0.complete(DELETED);
+    }
+}
+
+/// Only dropped if the task's closure unwinds.
+struct Unwinding<'a, R>(&'a Packet<R>);
+
+impl<'a, R> Drop for Unwinding<'a, R> {
+    fn drop(&mut self) {
+        self.0.complete(PANICKED);
+    }
+}
+
+/// Handle to a task started with [`TaskBuilder::spawn_joinable()`], to wait for the value
+/// it returns.
+///
+/// Waiting uses a notification index reserved with [`NotificationIndex::reserve()`] on the
+/// joining task, the first joinable task fails to start if none is left.
+///
+/// ```ignore
+/// let handle = Task::new().spawn_joinable(|| 6 * 7).unwrap();
+/// assert_eq!(handle.join(Ticks::infinite()), Ok(42));
+/// ```
+pub struct JoinHandle<R> {
+    task: Task,
+    packet: Arc<Packet<R>>,
+}
+
+impl<R: Send + 'static> JoinHandle<R> {
+    pub(crate) fn spawn<F>(builder: &TaskBuilder, func: F) -> Result<JoinHandle<R>, FreeRtosError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+    {
+        let (packet, body) = unsafe { Self::task_body(func)? };
+        let task = builder.start(body)?;
+
+        Ok(JoinHandle { task, packet })
+    }
+}
+
+impl<R: Send> JoinHandle<R> {
+    /// The packet and the function of a task running `func`. Unsafe because the task
+    /// must not outlive anything `func` or `R` borrow.
+    pub(crate) unsafe fn task_body<F>(
+        func: F,
+    ) -> Result<(Arc<Packet<R>>, impl FnOnce() + Send), FreeRtosError>
+    where
+        F: FnOnce() -> R + Send,
+    {
+        let packet = Arc::new(Packet {
+            state: AtomicU8::new(RUNNING),
+            index: join_index()?,
+            result: UnsafeCell::new(None),
+            joiner: AtomicPtr::new(ptr::null_mut()),
+        });
+
+        let completion = Completion(packet.clone());
+        let body = move || {
+            let packet = completion.0.clone();
+            #[cfg(feature = "thread-local-storage")]
+            {
+                // From now on `Task::delete()` completes the packet, when it drops the task
+                // locals.
+                let on_delete: Box<dyn FnOnce() + Send + '_> =
+                    Box::new(move || drop(completion));
+                // SAFETY: only the lifetime changes. The closure is dropped when the task
+                // returns or is deleted, and the task doesn't outlive what `R` borrows.
+                let on_delete =
+                    mem::transmute::<Box<dyn FnOnce() + Send + '_>, BoxedClosure>(on_delete);
+                store_on_delete(on_delete);
+            }
+
+            let unwinding = Unwinding(&packet);
+            let result = func();
+            mem::forget(unwinding);
+
+            *packet.result.get() = Some(result);
+            packet
+                .state
+                .compare_exchange(RUNNING, FINISHED, Ordering::SeqCst, Ordering::SeqCst)
+                .ok();
+
+            #[cfg(feature = "thread-local-storage")]
+            drop(take_on_delete());
+            #[cfg(not(feature = "thread-local-storage"))]
+            drop(completion);
+        };
+
+        Ok((packet, body))
+    }
+
+    pub(crate) fn from_parts(task: Task, packet: Arc<Packet<R>>) -> JoinHandle<R> {
+        JoinHandle { task, packet }
+    }
+}
+
+impl<R> JoinHandle<R> {
+    /// The task computing the result.
+    pub fn task(&self) -> &Task {
+        &self.task
+    }
+
+    /// Has the task returned, panicked or been deleted?
+    pub fn is_finished(&self) -> bool {
+        self.packet.state.load(Ordering::SeqCst) != RUNNING
+    }
+
+    /// Wait until the task returns. Fails with [`FreeRtosError::TaskPanicked`] if its
+    /// closure panicked and unwound, or [`FreeRtosError::TaskDeleted`] if it was deleted.
+    /// Without the `thread-local-storage` feature a deleted task is never noticed, waiting
+    /// for it times out.
+    pub fn wait(&self, max_wait: impl Into<Ticks>) -> Result<(), FreeRtosError> {
+        let max_wait = max_wait.into().ticks;
+        let start = unsafe { glue::task_get_tick_count() };
+
+        loop {
+            match self.packet.state.load(Ordering::SeqCst) {
+                FINISHED => return Ok(()),
+                PANICKED => return Err(FreeRtosError::TaskPanicked),
+                DELETED => return Err(FreeRtosError::TaskDeleted),
+                _ => {}
+            }
+
+            let wait = if max_wait == MAX_DELAY {
+                MAX_DELAY
+            } else {
+                let elapsed = unsafe { glue::task_get_tick_count() }.wrapping_sub(start);
+                max_wait.saturating_sub(elapsed)
+            };
+            if wait == 0 {
+                self.packet.joiner.store(ptr::null_mut(), Ordering::SeqCst);
+                return Err(FreeRtosError::Timeout);
+            }
+
+            let me = Task::current();
+            self.packet
+                .joiner
+                .store(me.clone().into_raw().as_ptr(), Ordering::SeqCst);
+            if self.packet.state.load(Ordering::SeqCst) == RUNNING {
+                me.take_notification_indexed(self.packet.index, true, Ticks::new(wait));
+            }
+        }
+    }
+
+    /// Wait until the task returns and take its result.
+    pub fn join(self, max_wait: impl Into<Ticks>) -> Result<R, FreeRtosError> {
+        self.wait(max_wait)?;
+
+        let result = unsafe { (*self.packet.result.get()).take() };
+        Ok(result.expect("JoinHandle: a finished task should have left its result"))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::queue::Queue;
+
+    #[test]
+    fn join_waits_for_the_result() {
+        let kernel = Kernel::new();
+        let out = Arc::new(Queue::new(1).unwrap());
+
+        let handle = Task::new()
+            .spawn_joinable(|| {
+                CurrentTask::delay(Ticks::new(3));
+                6 * 7
+            })
+            .unwrap();
+        let o = out.clone();
+        Task::new()
+            .start(move || o.send(handle.join(Ticks::new(10)), Ticks::zero()).unwrap())
+            .unwrap();
+
+        kernel.advance_ticks(2);
+        assert!(out.receive(Ticks::zero()).is_err());
+        kernel.advance_ticks(1);
+        assert_eq!(out.receive(Ticks::zero()), Ok(Ok(42)));
+    }
+
+    #[test]
+    #[cfg(feature = "thread-local-storage")]
+    fn deleted_task_fails_the_join() {
+        let kernel = Kernel::new();
+        let handle = Task::new()
+            .spawn_joinable(|| {
+                CurrentTask::delay(Ticks::new(100));
+                1
+            })
+            .unwrap();
+
+        kernel.run();
+        assert!(!handle.is_finished());
+        assert_eq!(handle.wait(Ticks::zero()), Err(FreeRtosError::Timeout));
+        unsafe { handle.task().clone().delete() };
+        assert!(handle.is_finished());
+        assert_eq!(handle.join(Ticks::zero()), Err(FreeRtosError::TaskDeleted));
+    }
+}
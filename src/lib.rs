@@ -60,23 +60,54 @@
 
 #[macro_use]
 extern crate alloc;
+#[cfg(feature = "posix")]
+extern crate freertos_posix_sys as sys;
+#[cfg(feature = "mock")]
+extern crate std;
 
+#[cfg(not(feature = "mock"))]
 pub mod glue;
+#[cfg(feature = "mock")]
+pub use crate::mock::glue;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(not(feature = "mock"))]
+mod port;
 mod prelude;
 
 mod allocator;
 mod base;
+mod channel;
+mod cpu_monitor;
 mod critical;
 mod delays;
+mod event_group;
+mod export;
 mod isr;
+mod join;
 mod mutex;
 mod queue;
+mod queue_set;
+mod scope;
 mod semaphore;
+mod stream_buffer;
 mod task;
+#[cfg(feature = "thread-local-storage")]
+mod task_local;
 mod timers;
 mod units;
 mod utils;
 
+#[cfg(any(
+    feature = "hook-stack-overflow",
+    feature = "hook-malloc-failed",
+    feature = "hook-idle",
+    feature = "hook-tick",
+    feature = "hook-daemon-task-startup"
+))]
+pub mod hooks;
+#[cfg(feature = "trace")]
+pub mod trace;
 pub mod patterns;
 
 // Internal stuff that is only public for first Proof of Concept
@@ -85,12 +116,21 @@ pub use crate::base::*;
 
 pub use crate::allocator::*;
 pub use crate::base::FreeRtosError;
+pub use crate::channel::*;
+pub use crate::cpu_monitor::*;
 pub use crate::critical::*;
 pub use crate::delays::*;
+pub use crate::event_group::*;
 pub use crate::isr::*;
+pub use crate::join::*;
 pub use crate::mutex::*;
 pub use crate::queue::*;
+pub use crate::queue_set::*;
+pub use crate::scope::*;
 pub use crate::semaphore::*;
+pub use crate::stream_buffer::*;
 pub use crate::task::*;
+#[cfg(feature = "thread-local-storage")]
+pub use crate::task_local::*;
 pub use crate::timers::*;
 pub use crate::units::*;
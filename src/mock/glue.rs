@@ -0,0 +1,1135 @@
+//! Drop-in replacement of the kernel glue, backed by the mock kernel.
+//!
+//! Keeps the exact signatures of the real glue so nothing above it can tell the difference.
+//! The functions have the safety requirements of the kernel functions they stand in for.
+
+#![allow(clippy::missing_safety_doc)]
+
+use core::convert::TryFrom;
+use core::ptr;
+use std::alloc::{self, Layout};
+use std::collections::VecDeque;
+
+use crate::base::*;
+use crate::mock::kernel::{self, *};
+use crate::mock::sys;
+use crate::prelude::*;
+
+pub use sys::configTICK_RATE_HZ as TICK_RATE_HZ;
+pub use sys::portMAX_DELAY as MAX_DELAY;
+pub const TICK_PERIOD_MS: u32 = 1000 / TICK_RATE_HZ;
+pub const MAX_TASK_NAME_LEN: usize = sys::configMAX_TASK_NAME_LEN as usize;
+pub const NUM_TLS_POINTERS: BaseType = sys::configNUM_THREAD_LOCAL_STORAGE_POINTERS as _;
+pub const NOTIFICATION_ARRAY_ENTRIES: UBaseType = sys::configTASK_NOTIFICATION_ARRAY_ENTRIES as _;
+pub const NUMBER_OF_CORES: usize = 1;
+
+/// Allocations remember their size in front of the returned block, `port_free` doesn't get it.
+const ALLOC_HEADER: usize = 16;
+
+pub unsafe fn strlen(c_str: *const c_char) -> usize {
+    if c_str.is_null() {
+        return 0;
+    }
+
+    let mut len = 0;
+    while *c_str.add(len) != 0 {
+        len += 1;
+    }
+    len
+}
+
+pub unsafe fn start_scheduler() -> ! {
+    panic!("the mock kernel doesn't have a scheduler loop, drive it through `mock::Kernel`");
+}
+
+pub unsafe fn port_malloc(xWantedSize: usize) -> *mut c_void {
+    let layout = Layout::from_size_align_unchecked(xWantedSize + ALLOC_HEADER, ALLOC_HEADER);
+    let block = alloc::alloc(layout);
+    if block.is_null() {
+        return ptr::null_mut();
+    }
+    *(block as *mut usize) = xWantedSize;
+    block.add(ALLOC_HEADER) as *mut c_void
+}
+pub unsafe fn port_free(pv: *mut c_void) {
+    if pv.is_null() {
+        return;
+    }
+    let block = (pv as *mut u8).sub(ALLOC_HEADER);
+    let size = *(block as *mut usize);
+    alloc::dealloc(
+        block,
+        Layout::from_size_align_unchecked(size + ALLOC_HEADER, ALLOC_HEADER),
+    );
+}
+
+pub unsafe fn task_delay_until(
+    pxPreviousWakeTime: *mut TickType,
+    xTimeIncrement: TickType,
+) -> bool {
+    let now = task_get_tick_count();
+    let previous = *pxPreviousWakeTime;
+    let wake_time = previous.wrapping_add(xTimeIncrement);
+    *pxPreviousWakeTime = wake_time;
+
+    // Same overflow handling as `xTaskDelayUntil`.
+    let should_delay = if now < previous {
+        wake_time < previous && wake_time > now
+    } else {
+        wake_time < previous || wake_time > now
+    };
+    if should_delay {
+        task_delay(wake_time.wrapping_sub(now));
+    } else {
+        task_yield();
+    }
+    should_delay
+}
+pub unsafe fn task_delay(xTicksToDelay: TickType) {
+    kernel::kernel().delay(current_task(), xTicksToDelay)
+}
+
+pub unsafe fn get_number_of_tasks() -> UBaseType {
+    kernel::kernel().lock().live_tasks().count() as UBaseType
+}
+
+pub unsafe fn task_get_tick_count() -> TickType {
+    kernel::kernel().lock().tick
+}
+
+pub unsafe fn task_get_tick_count_from_isr() -> TickType {
+    task_get_tick_count()
+}
+
+fn add_queue(queue: MockQueue) -> MaybeQueueHandle {
+    Some(handle_from_id(kernel::kernel().lock().add_queue(queue)))
+}
+
+pub unsafe fn create_recursive_mutex() -> MaybeQueueHandle {
+    add_queue(MockQueue::new_mutex())
+}
+pub unsafe fn create_mutex() -> MaybeQueueHandle {
+    add_queue(MockQueue::new_mutex())
+}
+pub unsafe fn create_recursive_mutex_static(_buffer: *mut StaticQueueFfi) -> MaybeQueueHandle {
+    create_recursive_mutex()
+}
+pub unsafe fn create_mutex_static(_buffer: *mut StaticQueueFfi) -> MaybeQueueHandle {
+    create_mutex()
+}
+
+/// `xQueueSemaphoreTake`, shared by semaphores and mutexes.
+fn semaphore_take(queue: QueueHandle, max: TickType, recursive: bool) -> bool {
+    let id = id_from_handle(queue);
+    let me = current_task();
+    kernel::kernel()
+        .wait_for(me, Resource::Queue(id), max, |st| {
+            let queue = st.queue(id);
+            if let Some(mutex) = &mut queue.mutex {
+                if recursive && mutex.holder.is_some() && mutex.holder == me {
+                    mutex.depth += 1;
+                    return Some(());
+                }
+            }
+            queue.items.pop_front()?;
+            if let Some(mutex) = &mut queue.mutex {
+                mutex.holder = me;
+                mutex.depth = 1;
+            }
+            st.wake_all(Resource::Queue(id));
+            Some(())
+        })
+        .is_some()
+}
+
+/// Give back a semaphore or mutex, returns whether a task was woken by it.
+fn semaphore_give(queue: QueueHandle, recursive: bool) -> Option<bool> {
+    let id = id_from_handle(queue);
+    let me = current_task();
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let queue = st.queue(id);
+    if let Some(mutex) = &mut queue.mutex {
+        if mutex.holder != me {
+            return None;
+        }
+        if recursive {
+            mutex.depth -= 1;
+            if mutex.depth > 0 {
+                return Some(false);
+            }
+        }
+        mutex.holder = None;
+        mutex.depth = 0;
+    }
+    if queue.is_full() {
+        return None;
+    }
+    let woken = st.push_item(id, Vec::new());
+    drop(kernel.preempt(st, me));
+    Some(woken)
+}
+
+pub unsafe fn take_mutex(mutex: QueueHandle, max: TickType) -> bool {
+    semaphore_take(mutex, max, false)
+}
+pub unsafe fn give_mutex(mutex: QueueHandle) -> bool {
+    semaphore_give(mutex, false).is_some()
+}
+pub unsafe fn give_mutex_isr(mutex: QueueHandle, xHigherPriorityTaskWoken: *mut BaseType) -> bool {
+    let id = id_from_handle(mutex);
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let queue = st.queue(id);
+    if queue.is_full() {
+        return false;
+    }
+    if st.push_item(id, Vec::new()) && !xHigherPriorityTaskWoken.is_null() {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+    true
+}
+pub unsafe fn take_semaphore_isr(
+    semaphore: QueueHandle,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    let id = id_from_handle(semaphore);
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    if st.queue(id).items.pop_front().is_none() {
+        return false;
+    }
+    if st.wake_all(Resource::Queue(id)) && !xHigherPriorityTaskWoken.is_null() {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+    true
+}
+pub unsafe fn get_semaphore_count(semaphore: QueueHandle) -> UBaseType {
+    queue_messages_waiting(semaphore)
+}
+pub unsafe fn give_recursive_mutex(mutex: QueueHandle) -> bool {
+    semaphore_give(mutex, true).is_some()
+}
+pub unsafe fn take_recursive_mutex(mutex: QueueHandle, max: TickType) -> bool {
+    semaphore_take(mutex, max, true)
+}
+pub unsafe fn mutex_get_holder(mutex: QueueHandle) -> MaybeTaskHandle {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let holder = st.queue(id_from_handle(mutex)).mutex.as_ref()?.holder?;
+    Some(handle_from_id(holder))
+}
+
+pub unsafe fn delete_semaphore(mutex: QueueHandle) {
+    queue_delete(mutex)
+}
+
+pub unsafe fn create_binary_semaphore() -> MaybeQueueHandle {
+    add_queue(MockQueue::new_semaphore(1, 0))
+}
+pub unsafe fn create_counting_semaphore(max: UBaseType, initial: UBaseType) -> MaybeQueueHandle {
+    add_queue(MockQueue::new_semaphore(max as usize, initial as usize))
+}
+pub unsafe fn create_binary_semaphore_static(_buffer: *mut StaticQueueFfi) -> MaybeQueueHandle {
+    create_binary_semaphore()
+}
+pub unsafe fn create_counting_semaphore_static(
+    max: UBaseType,
+    initial: UBaseType,
+    _buffer: *mut StaticQueueFfi,
+) -> MaybeQueueHandle {
+    create_counting_semaphore(max, initial)
+}
+
+pub unsafe fn queue_create(length: UBaseType, item_size: UBaseType) -> MaybeQueueHandle {
+    add_queue(MockQueue::new(length as usize, item_size as usize))
+}
+pub unsafe fn queue_create_static(
+    length: UBaseType,
+    item_size: UBaseType,
+    _storage: *mut u8,
+    _buffer: *mut StaticQueueFfi,
+) -> MaybeQueueHandle {
+    queue_create(length, item_size)
+}
+pub unsafe fn queue_delete(queue: QueueHandle) {
+    kernel::kernel().lock().queues[id_from_handle(queue) - 1] = None;
+}
+pub unsafe fn queue_send(queue: QueueHandle, item: *const c_void, max_wait: TickType) -> bool {
+    queue_insert(queue, item, max_wait, false)
+}
+pub unsafe fn queue_send_to_front(
+    queue: QueueHandle,
+    item: *const c_void,
+    max_wait: TickType,
+) -> bool {
+    queue_insert(queue, item, max_wait, true)
+}
+unsafe fn queue_insert(
+    queue: QueueHandle,
+    item: *const c_void,
+    max_wait: TickType,
+    to_front: bool,
+) -> bool {
+    let id = id_from_handle(queue);
+    kernel::kernel()
+        .wait_for(current_task(), Resource::Queue(id), max_wait, |st| {
+            let queue = st.queue(id);
+            if queue.is_full() {
+                return None;
+            }
+            let item = core::slice::from_raw_parts(item as *const u8, queue.item_size).to_vec();
+            st.insert_item(id, item, to_front);
+            #[cfg(feature = "trace")]
+            trace_queue(st, crate::trace::EventKind::QueueSend, id);
+            Some(())
+        })
+        .is_some()
+}
+pub unsafe fn queue_overwrite(queue: QueueHandle, item: *const c_void) -> bool {
+    let me = current_task();
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    overwrite(&mut st, queue, item);
+    drop(kernel.preempt(st, me));
+    true
+}
+/// Replace the item of a queue of length 1. Like the kernel, the queue set is only told
+/// about the item if the queue was empty. Returns whether any task was woken.
+unsafe fn overwrite(st: &mut State, queue: QueueHandle, item: *const c_void) -> bool {
+    let id = id_from_handle(queue);
+    let queue = st.queue(id);
+    let item = core::slice::from_raw_parts(item as *const u8, queue.item_size).to_vec();
+    match queue.items.front_mut() {
+        Some(current) => {
+            *current = item;
+            st.wake_all(Resource::Queue(id))
+        }
+        None => st.push_item(id, item),
+    }
+}
+
+pub unsafe fn queue_receive(queue: QueueHandle, item: *mut c_void, max_wait: TickType) -> bool {
+    let id = id_from_handle(queue);
+    kernel::kernel()
+        .wait_for(current_task(), Resource::Queue(id), max_wait, |st| {
+            let received = st.queue(id).items.pop_front()?;
+            ptr::copy_nonoverlapping(received.as_ptr(), item as *mut u8, received.len());
+            st.wake_all(Resource::Queue(id));
+            #[cfg(feature = "trace")]
+            trace_queue(st, crate::trace::EventKind::QueueReceive, id);
+            Some(())
+        })
+        .is_some()
+}
+#[cfg(feature = "trace")]
+fn trace_queue(st: &State, kind: crate::trace::EventKind, queue: usize) {
+    let task = current_task().unwrap_or(0);
+    crate::trace::record(kind, task, queue, st.tick as _);
+}
+pub unsafe fn queue_peek(queue: QueueHandle, item: *mut c_void, max_wait: TickType) -> bool {
+    let id = id_from_handle(queue);
+    kernel::kernel()
+        .wait_for(current_task(), Resource::Queue(id), max_wait, |st| {
+            let peeked = st.queue(id).items.front()?;
+            ptr::copy_nonoverlapping(peeked.as_ptr(), item as *mut u8, peeked.len());
+            Some(())
+        })
+        .is_some()
+}
+
+pub unsafe fn queue_messages_waiting(queue: QueueHandle) -> UBaseType {
+    kernel::kernel().lock().queue(id_from_handle(queue)).items.len() as UBaseType
+}
+pub unsafe fn queue_spaces_available(queue: QueueHandle) -> UBaseType {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let queue = st.queue(id_from_handle(queue));
+    (queue.length - queue.items.len()) as UBaseType
+}
+pub unsafe fn queue_reset(queue: QueueHandle) {
+    let id = id_from_handle(queue);
+    let me = current_task();
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    st.queue(id).items.clear();
+    st.wake_all(Resource::Queue(id));
+    drop(kernel.preempt(st, me));
+}
+
+pub unsafe fn queue_send_isr(
+    queue: QueueHandle,
+    item: *const c_void,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    let id = id_from_handle(queue);
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let queue = st.queue(id);
+    if queue.is_full() {
+        return false;
+    }
+    let item = core::slice::from_raw_parts(item as *const u8, queue.item_size).to_vec();
+    if st.push_item(id, item) && !xHigherPriorityTaskWoken.is_null() {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+    true
+}
+pub unsafe fn queue_overwrite_isr(
+    queue: QueueHandle,
+    item: *const c_void,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    if overwrite(&mut st, queue, item) && !xHigherPriorityTaskWoken.is_null() {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+    true
+}
+pub unsafe fn queue_receive_isr(
+    queue: QueueHandle,
+    item: *mut c_void,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    let id = id_from_handle(queue);
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let received = match st.queue(id).items.pop_front() {
+        Some(received) => received,
+        None => return false,
+    };
+    ptr::copy_nonoverlapping(received.as_ptr(), item as *mut u8, received.len());
+    if st.wake_all(Resource::Queue(id)) && !xHigherPriorityTaskWoken.is_null() {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+    true
+}
+pub unsafe fn queue_peek_isr(queue: QueueHandle, item: *mut c_void) -> bool {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    match st.queue(id_from_handle(queue)).items.front() {
+        Some(peeked) => {
+            ptr::copy_nonoverlapping(peeked.as_ptr(), item as *mut u8, peeked.len());
+            true
+        }
+        None => false,
+    }
+}
+pub unsafe fn queue_is_full_isr(queue: QueueHandle) -> bool {
+    kernel::kernel().lock().queue(id_from_handle(queue)).is_full()
+}
+pub unsafe fn queue_set_create(length: UBaseType) -> MaybeQueueHandle {
+    queue_create(length, core::mem::size_of::<usize>() as UBaseType)
+}
+pub unsafe fn queue_set_add(member: QueueHandle, set: QueueHandle) -> bool {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let member = st.queue(id_from_handle(member));
+    if member.set.is_some() || !member.items.is_empty() {
+        return false;
+    }
+    member.set = Some(id_from_handle(set));
+    true
+}
+pub unsafe fn queue_set_remove(member: QueueHandle, set: QueueHandle) -> bool {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let member = st.queue(id_from_handle(member));
+    if member.set != Some(id_from_handle(set)) || !member.items.is_empty() {
+        return false;
+    }
+    member.set = None;
+    true
+}
+pub unsafe fn queue_set_select(set: QueueHandle, max_wait: TickType) -> MaybeQueueHandle {
+    let mut member = [0u8; core::mem::size_of::<usize>()];
+    if queue_receive(set, member.as_mut_ptr() as *mut _, max_wait) {
+        Some(handle_from_id(usize::from_ne_bytes(member)))
+    } else {
+        None
+    }
+}
+pub unsafe fn queue_set_select_isr(set: QueueHandle) -> MaybeQueueHandle {
+    queue_set_select(set, 0)
+}
+
+pub unsafe fn task_yield_from_isr() {
+    let kernel = kernel::kernel();
+    drop(kernel.preempt(kernel.lock(), current_task()));
+}
+
+pub unsafe fn task_yield() {
+    kernel::kernel().yield_now(current_task());
+}
+
+pub unsafe fn task_notify_take(index: UBaseType, clear_count: bool, wait: TickType) -> u32 {
+    let me = current_task().expect("mock kernel: only tasks have notifications");
+    let i = index as usize;
+    let kernel = kernel::kernel();
+    let taken = kernel.wait_for(Some(me), Resource::Notification(me, i), wait, |st| {
+        let tcb = st.tcb_mut(me);
+        if tcb.notify_value[i] == 0 {
+            return None;
+        }
+        let value = tcb.notify_value[i];
+        tcb.notify_value[i] = if clear_count { 0 } else { value - 1 };
+        tcb.notify_pending[i] = false;
+        Some(value)
+    });
+    match taken {
+        Some(value) => value,
+        None => {
+            kernel.lock().tcb_mut(me).notify_pending[i] = false;
+            0
+        }
+    }
+}
+pub unsafe fn task_notify_wait(
+    index: UBaseType,
+    ulBitsToClearOnEntry: u32,
+    ulBitsToClearOnExit: u32,
+    pulNotificationValue: *mut u32,
+    xTicksToWait: TickType,
+) -> bool {
+    let me = current_task().expect("mock kernel: only tasks have notifications");
+    let i = index as usize;
+    let kernel = kernel::kernel();
+    {
+        let mut st = kernel.lock();
+        let tcb = st.tcb_mut(me);
+        if !tcb.notify_pending[i] {
+            tcb.notify_value[i] &= !ulBitsToClearOnEntry;
+        }
+    }
+    let received = kernel
+        .wait_for(Some(me), Resource::Notification(me, i), xTicksToWait, |st| {
+            if st.tcb(me).notify_pending[i] {
+                Some(())
+            } else {
+                None
+            }
+        })
+        .is_some();
+
+    let mut st = kernel.lock();
+    let tcb = st.tcb_mut(me);
+    if !pulNotificationValue.is_null() {
+        *pulNotificationValue = tcb.notify_value[i];
+    }
+    if received {
+        tcb.notify_value[i] &= !ulBitsToClearOnExit;
+    }
+    tcb.notify_pending[i] = false;
+    received
+}
+
+/// Apply a notification to `task`, returns `None` if the value couldn't be set.
+fn notify(
+    task: TaskHandle,
+    index: UBaseType,
+    value: u32,
+    action: sys::eNotifyAction,
+) -> Option<bool> {
+    let id = id_from_handle(task);
+    let i = index as usize;
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let tcb = st.tcb_mut(id);
+    let current = &mut tcb.notify_value[i];
+    match action {
+        sys::eNotifyAction_eSetBits => *current |= value,
+        sys::eNotifyAction_eIncrement => *current = current.wrapping_add(1),
+        sys::eNotifyAction_eSetValueWithOverwrite => *current = value,
+        sys::eNotifyAction_eSetValueWithoutOverwrite if tcb.notify_pending[i] => return None,
+        sys::eNotifyAction_eSetValueWithoutOverwrite => *current = value,
+        _ => {}
+    }
+    tcb.notify_pending[i] = true;
+    Some(st.wake_all(Resource::Notification(id, i)))
+}
+
+pub unsafe fn task_notify(
+    task: TaskHandle,
+    index: UBaseType,
+    value: u32,
+    action: sys::eNotifyAction,
+) -> bool {
+    let notified = notify(task, index, value, action).is_some();
+    let kernel = kernel::kernel();
+    drop(kernel.preempt(kernel.lock(), current_task()));
+    notified
+}
+pub unsafe fn task_notify_isr(
+    task: TaskHandle,
+    index: UBaseType,
+    value: u32,
+    action: sys::eNotifyAction,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    match notify(task, index, value, action) {
+        Some(woken) => {
+            if woken && !xHigherPriorityTaskWoken.is_null() {
+                *xHigherPriorityTaskWoken = sys::pdTRUE;
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+pub unsafe fn create_task(
+    f: extern "C" fn(*mut c_void),
+    value: *mut c_void,
+    name: &str,
+    stack_size: u16,
+    priority: UBaseType,
+    _core_affinity_mask: UBaseType,
+    task_handle: &mut MaybeTaskHandle,
+) -> bool {
+    let kernel = kernel::kernel();
+    let id = kernel.create_task(current_task(), f, value, name, stack_size, priority);
+    *task_handle = Some(handle_from_id(id));
+    true
+}
+pub unsafe fn create_task_static(
+    f: extern "C" fn(*mut c_void),
+    value: *mut c_void,
+    name: &str,
+    stack: &mut [StackType],
+    priority: UBaseType,
+    core_affinity_mask: UBaseType,
+    _tcb: *mut StaticTaskFfi,
+) -> Result<TaskHandle, FreeRtosError> {
+    let stack_size = sys::configSTACK_DEPTH_TYPE::try_from(stack.len())
+        .map_err(|_| FreeRtosError::InvalidStackSize)?;
+    let mut task_handle = None;
+    create_task(f, value, name, stack_size, priority, core_affinity_mask, &mut task_handle);
+    task_handle.ok_or(FreeRtosError::OutOfMemory)
+}
+pub unsafe fn delete_task(task: MaybeTaskHandle) {
+    kernel::kernel().delete_task(current_task(), task.map(id_from_handle))
+}
+pub unsafe fn task_get_name(task: TaskHandle) -> *const c_char {
+    kernel::kernel().lock().tcb(id_from_handle(task)).name.as_ptr() as *const c_char
+}
+pub unsafe fn get_stack_high_water_mark(task: MaybeTaskHandle) -> UBaseType {
+    let id = task
+        .map(id_from_handle)
+        .or_else(current_task)
+        .expect("mock kernel: the controlling thread has no stack to measure");
+    kernel::kernel().lock().tcb(id).stack_size as UBaseType
+}
+
+pub unsafe fn task_suspend(task: MaybeTaskHandle) {
+    kernel::kernel().suspend_task(current_task(), task.map(id_from_handle))
+}
+pub unsafe fn task_resume(task: TaskHandle) {
+    let kernel = kernel::kernel();
+    kernel.resume_task(current_task(), id_from_handle(task));
+    drop(kernel.preempt(kernel.lock(), current_task()));
+}
+pub unsafe fn task_resume_isr(task: TaskHandle, xHigherPriorityTaskWoken: *mut BaseType) {
+    if kernel::kernel().resume_task(current_task(), id_from_handle(task))
+        && !xHigherPriorityTaskWoken.is_null()
+    {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+}
+pub unsafe fn task_abort_delay(task: TaskHandle) -> bool {
+    let kernel = kernel::kernel();
+    let aborted = kernel.abort_delay(id_from_handle(task));
+    drop(kernel.preempt(kernel.lock(), current_task()));
+    aborted
+}
+pub unsafe fn task_get_priority(task: MaybeTaskHandle) -> UBaseType {
+    let id = task
+        .map(id_from_handle)
+        .or_else(current_task)
+        .expect("mock kernel: the controlling thread has no priority");
+    kernel::kernel().lock().tcb(id).priority
+}
+pub unsafe fn task_set_priority(task: MaybeTaskHandle, priority: UBaseType) {
+    kernel::kernel().set_priority(current_task(), task.map(id_from_handle), priority)
+}
+pub unsafe fn task_get_state(task: TaskHandle) -> sys::eTaskState {
+    kernel::kernel().lock().task_state(id_from_handle(task)) as sys::eTaskState
+}
+pub unsafe fn task_get_info(task: TaskHandle, status: *mut TaskStatusFfi) {
+    let kernel = kernel::kernel();
+    let st = kernel.lock();
+    status.write(task_status(&st, id_from_handle(task)));
+}
+pub unsafe fn task_get_info_ready(task: TaskHandle, status: *mut TaskStatusFfi) {
+    task_get_info(task, status)
+}
+pub unsafe fn task_set_tls_pointer(task: MaybeTaskHandle, index: BaseType, value: *mut c_void) {
+    let id = task
+        .map(id_from_handle)
+        .or_else(current_task)
+        .expect("mock kernel: the controlling thread has no thread local storage");
+    kernel::kernel().lock().tcb_mut(id).tls[index as usize] = value as usize;
+}
+#[cfg(feature = "tls-deletion-callbacks")]
+pub unsafe fn task_set_tls_pointer_with_callback(
+    task: MaybeTaskHandle,
+    index: BaseType,
+    value: *mut c_void,
+    callback: unsafe extern "C" fn(i32, *mut c_void),
+) {
+    task_set_tls_pointer(task, index, value);
+    let id = task.map(id_from_handle).or_else(current_task).unwrap();
+    kernel::kernel().lock().tcb_mut(id).tls_callbacks[index as usize] = Some(callback);
+}
+pub unsafe fn task_get_tls_pointer(task: MaybeTaskHandle, index: BaseType) -> *mut c_void {
+    let id = task
+        .map(id_from_handle)
+        .or_else(current_task)
+        .expect("mock kernel: the controlling thread has no thread local storage");
+    kernel::kernel().lock().tcb(id).tls[index as usize] as *mut c_void
+}
+
+/// What `vTaskGetInfo` and `uxTaskGetSystemState` report for a task.
+fn task_status(st: &State, id: TaskId) -> TaskStatusFfi {
+    let tcb = st.tcb(id);
+    TaskStatusFfi {
+        xHandle: handle_from_id(id).as_ptr(),
+        pcTaskName: tcb.name.as_ptr() as *const c_char,
+        xTaskNumber: tcb.number,
+        eCurrentState: st.task_state(id) as sys::eTaskState,
+        uxCurrentPriority: tcb.priority,
+        uxBasePriority: tcb.priority,
+        ulRunTimeCounter: 0,
+        pxStackBase: ptr::null_mut(),
+        usStackHighWaterMark: tcb.stack_size,
+    }
+}
+
+pub unsafe fn get_current_task() -> MaybeTaskHandle {
+    current_task().map(handle_from_id)
+}
+pub unsafe fn get_system_state(
+    tasks: *mut TaskStatusFfi,
+    tasks_len: UBaseType,
+    total_run_time: *mut u32,
+) -> UBaseType {
+    let kernel = kernel::kernel();
+    let st = kernel.lock();
+    if st.live_tasks().count() > tasks_len as usize {
+        return 0;
+    }
+
+    let mut filled = 0;
+    for (id, _) in st.live_tasks() {
+        tasks.add(filled).write(task_status(&st, id));
+        filled += 1;
+    }
+    if !total_run_time.is_null() {
+        *total_run_time = 0;
+    }
+    filled as UBaseType
+}
+/// The mock kernel has no idle task.
+pub unsafe fn get_idle_task_handle(_core: UBaseType) -> MaybeTaskHandle {
+    None
+}
+
+pub unsafe fn timer_create(
+    _name: *const c_char,
+    period: TickType,
+    auto_reload: bool,
+    timer_id: usize,
+    callback: extern "C" fn(TimerHandle),
+) -> MaybeTimerHandle {
+    let id = kernel::kernel().lock().add_timer(MockTimer {
+        period,
+        auto_reload,
+        id: timer_id,
+        callback,
+        expiry: None,
+    });
+    Some(handle_from_id(id))
+}
+pub unsafe fn timer_create_static(
+    name: *const c_char,
+    period: TickType,
+    auto_reload: bool,
+    timer_id: usize,
+    callback: extern "C" fn(TimerHandle),
+    _buffer: *mut StaticTimerFfi,
+) -> MaybeTimerHandle {
+    timer_create(name, period, auto_reload, timer_id, callback)
+}
+pub unsafe fn timer_start(timer: TimerHandle, block_time: TickType) -> bool {
+    timer_reset(timer, block_time)
+}
+pub unsafe fn timer_stop(timer: TimerHandle, _block_time: TickType) -> bool {
+    kernel::kernel().lock().timer(id_from_handle(timer)).expiry = None;
+    true
+}
+pub unsafe fn timer_delete(timer: TimerHandle, _block_time: TickType) -> bool {
+    kernel::kernel().lock().timers[id_from_handle(timer) - 1] = None;
+    true
+}
+pub unsafe fn timer_change_period(
+    timer: TimerHandle,
+    _block_time: TickType,
+    new_period: TickType,
+) -> bool {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let now = st.tick;
+    let timer = st.timer(id_from_handle(timer));
+    timer.period = new_period;
+    timer.expiry = Some(now.wrapping_add(new_period));
+    true
+}
+pub unsafe fn timer_reset(timer: TimerHandle, _block_time: TickType) -> bool {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let now = st.tick;
+    let timer = st.timer(id_from_handle(timer));
+    timer.expiry = Some(now.wrapping_add(timer.period));
+    true
+}
+pub unsafe fn timer_get_id(timer: TimerHandle) -> usize {
+    kernel::kernel().lock().timer(id_from_handle(timer)).id
+}
+/// Timer commands take effect right away, so the call can too.
+pub unsafe fn timer_pend_function_call(
+    f: extern "C" fn(*mut c_void, u32),
+    param: *mut c_void,
+    _block_time: TickType,
+) -> bool {
+    f(param, 0);
+    true
+}
+
+pub unsafe fn event_group_create() -> MaybeEventGroupHandle {
+    Some(handle_from_id(kernel::kernel().lock().add_event_group()))
+}
+pub unsafe fn event_group_create_static(_buffer: *mut StaticEventGroupFfi) -> MaybeEventGroupHandle {
+    event_group_create()
+}
+pub unsafe fn event_group_delete(group: EventGroupHandle) {
+    kernel::kernel().lock().event_groups[id_from_handle(group) - 1] = None;
+}
+pub unsafe fn event_group_set_bits(group: EventGroupHandle, bits: EventBits) -> EventBits {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let (bits, _) = st.set_event_bits(id_from_handle(group), bits);
+    drop(kernel.preempt(st, current_task()));
+    bits
+}
+pub unsafe fn event_group_clear_bits(group: EventGroupHandle, bits: EventBits) -> EventBits {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let group = st.event_group(id_from_handle(group));
+    let previous = group.bits;
+    group.bits &= !bits;
+    previous
+}
+
+/// `xEventGroupWaitBits`, and `xEventGroupSync` if `set` is given.
+fn event_group_wait(
+    group: EventGroupHandle,
+    mask: EventBits,
+    all: bool,
+    clear_on_exit: bool,
+    wait: TickType,
+    set: Option<EventBits>,
+) -> EventBits {
+    let id = id_from_handle(group);
+    let me = current_task();
+    let kernel = kernel::kernel();
+    let mut first = true;
+    let result = kernel.wait_for(me, Resource::EventGroup(id), wait, |st| {
+        if !first {
+            let group = st.event_group(id);
+            let pos = group.waiters.iter().position(|w| Some(w.task) == me)?;
+            let bits = group.waiters[pos].result?;
+            group.waiters.remove(pos);
+            return Some(bits);
+        }
+        first = false;
+
+        let bits = match set {
+            // Checked against the bits from before the other tasks cleared theirs.
+            Some(set) => {
+                let before = st.event_group(id).bits;
+                st.set_event_bits(id, set);
+                before | set
+            }
+            None => st.event_group(id).bits,
+        };
+        let waiter = EventWaiter {
+            task: me.unwrap_or(0),
+            mask,
+            all,
+            clear: clear_on_exit,
+            result: None,
+        };
+        let group = st.event_group(id);
+        if waiter.satisfied_by(bits) {
+            if clear_on_exit {
+                group.bits &= !mask;
+            }
+            return Some(bits);
+        }
+        if wait != 0 && me.is_some() {
+            group.waiters.push(waiter);
+        }
+        None
+    });
+
+    result.unwrap_or_else(|| {
+        let mut st = kernel.lock();
+        let group = st.event_group(id);
+        group.waiters.retain(|w| Some(w.task) != me);
+        group.bits
+    })
+}
+
+pub unsafe fn event_group_wait_bits(
+    group: EventGroupHandle,
+    bits: EventBits,
+    clear_on_exit: bool,
+    wait_for_all: bool,
+    xTicksToWait: TickType,
+) -> EventBits {
+    event_group_wait(group, bits, wait_for_all, clear_on_exit, xTicksToWait, None)
+}
+pub unsafe fn event_group_sync(
+    group: EventGroupHandle,
+    bits_to_set: EventBits,
+    bits_to_wait_for: EventBits,
+    xTicksToWait: TickType,
+) -> EventBits {
+    event_group_wait(
+        group,
+        bits_to_wait_for,
+        true,
+        true,
+        xTicksToWait,
+        Some(bits_to_set),
+    )
+}
+pub unsafe fn event_group_get_bits_from_isr(group: EventGroupHandle) -> EventBits {
+    kernel::kernel().lock().event_group(id_from_handle(group)).bits
+}
+/// Applied right away instead of through the timer daemon task.
+pub unsafe fn event_group_set_bits_from_isr(
+    group: EventGroupHandle,
+    bits: EventBits,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    let (_, woken) = kernel::kernel()
+        .lock()
+        .set_event_bits(id_from_handle(group), bits);
+    if woken && !xHigherPriorityTaskWoken.is_null() {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+    true
+}
+/// Applied right away instead of through the timer daemon task.
+pub unsafe fn event_group_clear_bits_from_isr(group: EventGroupHandle, bits: EventBits) -> bool {
+    event_group_clear_bits(group, bits);
+    true
+}
+
+pub unsafe fn stream_buffer_create(
+    size: usize,
+    trigger_level: usize,
+    is_message_buffer: bool,
+) -> MaybeStreamBufferHandle {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    st.stream_buffers.push(Some(MockStreamBuffer {
+        size,
+        trigger_level: trigger_level.max(1),
+        is_message_buffer,
+        data: VecDeque::with_capacity(size),
+    }));
+    Some(handle_from_id(st.stream_buffers.len()))
+}
+pub unsafe fn stream_buffer_delete(buffer: StreamBufferHandle) {
+    kernel::kernel().lock().stream_buffers[id_from_handle(buffer) - 1] = None;
+}
+pub unsafe fn stream_buffer_send(
+    buffer: StreamBufferHandle,
+    data: *const c_void,
+    len: usize,
+    max_wait: TickType,
+) -> usize {
+    let id = id_from_handle(buffer);
+    let data = core::slice::from_raw_parts(data as *const u8, len);
+    let kernel = kernel::kernel();
+    let mut sent = kernel.wait_for(current_task(), Resource::StreamBuffer(id), max_wait, |st| {
+        let buffer = st.stream_buffer(id);
+        if buffer.spaces() < buffer.required(len) {
+            return None;
+        }
+        let sent = buffer.write(data);
+        if buffer.triggered() {
+            st.wake_all(Resource::StreamBuffer(id));
+        }
+        Some(sent)
+    });
+
+    // Once the time is up a stream buffer still takes what fits.
+    if sent.is_none() {
+        let mut st = kernel.lock();
+        let buffer = st.stream_buffer(id);
+        sent = Some(buffer.write(data));
+        if buffer.triggered() {
+            st.wake_all(Resource::StreamBuffer(id));
+        }
+    }
+    sent.unwrap_or(0)
+}
+pub unsafe fn stream_buffer_send_isr(
+    buffer: StreamBufferHandle,
+    data: *const c_void,
+    len: usize,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> usize {
+    let id = id_from_handle(buffer);
+    let data = core::slice::from_raw_parts(data as *const u8, len);
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let buffer = st.stream_buffer(id);
+    let sent = buffer.write(data);
+    if buffer.triggered()
+        && st.wake_all(Resource::StreamBuffer(id))
+        && !xHigherPriorityTaskWoken.is_null()
+    {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+    sent
+}
+pub unsafe fn stream_buffer_receive(
+    buffer: StreamBufferHandle,
+    data: *mut c_void,
+    len: usize,
+    max_wait: TickType,
+) -> usize {
+    let id = id_from_handle(buffer);
+    let buf = core::slice::from_raw_parts_mut(data as *mut u8, len);
+    kernel::kernel()
+        .wait_for(current_task(), Resource::StreamBuffer(id), max_wait, |st| {
+            let buffer = st.stream_buffer(id);
+            if buffer.data.is_empty() {
+                return None;
+            }
+            let received = buffer.read(buf);
+            if received > 0 {
+                st.wake_all(Resource::StreamBuffer(id));
+            }
+            Some(received)
+        })
+        .unwrap_or(0)
+}
+pub unsafe fn stream_buffer_receive_isr(
+    buffer: StreamBufferHandle,
+    data: *mut c_void,
+    len: usize,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> usize {
+    let id = id_from_handle(buffer);
+    let buf = core::slice::from_raw_parts_mut(data as *mut u8, len);
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let received = st.stream_buffer(id).read(buf);
+    if received > 0
+        && st.wake_all(Resource::StreamBuffer(id))
+        && !xHigherPriorityTaskWoken.is_null()
+    {
+        *xHigherPriorityTaskWoken = sys::pdTRUE;
+    }
+    received
+}
+pub unsafe fn stream_buffer_bytes_available(buffer: StreamBufferHandle) -> usize {
+    kernel::kernel()
+        .lock()
+        .stream_buffer(id_from_handle(buffer))
+        .data
+        .len()
+}
+pub unsafe fn stream_buffer_spaces_available(buffer: StreamBufferHandle) -> usize {
+    kernel::kernel()
+        .lock()
+        .stream_buffer(id_from_handle(buffer))
+        .spaces()
+}
+pub unsafe fn stream_buffer_set_trigger_level(buffer: StreamBufferHandle, level: usize) -> bool {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let buffer = st.stream_buffer(id_from_handle(buffer));
+    if level > buffer.size {
+        return false;
+    }
+    buffer.trigger_level = level.max(1);
+    true
+}
+pub unsafe fn stream_buffer_reset(buffer: StreamBufferHandle) -> bool {
+    let id = id_from_handle(buffer);
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    if st.has_waiters(Resource::StreamBuffer(id)) {
+        return false;
+    }
+    st.stream_buffer(id).data.clear();
+    true
+}
+pub unsafe fn stream_buffer_is_empty(buffer: StreamBufferHandle) -> bool {
+    stream_buffer_bytes_available(buffer) == 0
+}
+pub unsafe fn stream_buffer_is_full(buffer: StreamBufferHandle) -> bool {
+    let kernel = kernel::kernel();
+    let mut st = kernel.lock();
+    let buffer = st.stream_buffer(id_from_handle(buffer));
+    buffer.spaces() < buffer.required(1)
+}
+pub unsafe fn message_buffer_next_length(buffer: StreamBufferHandle) -> usize {
+    kernel::kernel()
+        .lock()
+        .stream_buffer(id_from_handle(buffer))
+        .next_message_len()
+}
+
+/// The mock kernel runs one task at a time, there is nothing to lock.
+#[derive(Default)]
+pub struct Spinlock;
+
+impl Spinlock {
+    pub const fn new() -> Self {
+        Spinlock
+    }
+
+    pub unsafe fn enter(&self) {}
+    pub unsafe fn exit(&self) {}
+    pub unsafe fn enter_isr(&self) -> UBaseType {
+        0
+    }
+    pub unsafe fn exit_isr(&self, _previous_state: UBaseType) {}
+}
+
+/// Only one task runs at a time, there is nothing to mask.
+pub unsafe fn enter_critical() {}
+pub unsafe fn exit_critical() {}
+pub unsafe fn enter_critical_isr() -> UBaseType {
+    0
+}
+pub unsafe fn exit_critical_isr(_previous_state: UBaseType) {}
+/// Everything that doesn't run in a mock task, like the test itself, acts as an interrupt.
+pub unsafe fn is_inside_interrupt() -> bool {
+    current_task().is_none()
+}
+
+pub unsafe fn task_suspend_all() {
+    kernel::kernel().suspend_all();
+}
+pub unsafe fn task_resume_all() -> bool {
+    kernel::kernel().resume_all(current_task())
+}
+
@@ -0,0 +1,863 @@
+use alloc::collections::VecDeque;
+use core::cell::{Cell, RefCell};
+use core::mem;
+use core::ptr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use crate::base::*;
+use crate::mock::sys;
+use crate::prelude::*;
+
+pub type TaskId = usize;
+
+const NOTIFICATION_ARRAY_ENTRIES: usize = sys::configTASK_NOTIFICATION_ARRAY_ENTRIES as usize;
+const TLS_ENTRIES: usize = sys::configNUM_THREAD_LOCAL_STORAGE_POINTERS as usize;
+
+std::thread_local! {
+    static KERNEL: RefCell<Option<Arc<Shared>>> = const { RefCell::new(None) };
+    static CURRENT_TASK: Cell<Option<TaskId>> = const { Cell::new(None) };
+}
+
+/// Who is allowed to run right now. Only a single thread ever makes progress at a time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Running {
+    /// The thread that owns the [`Kernel`](super::Kernel), either because no task is ready
+    /// or because it hasn't started the scheduling round yet.
+    Controller,
+    Task(TaskId),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TcbState {
+    Ready,
+    Running,
+    Blocked,
+    Suspended,
+    Deleted,
+}
+
+/// Something a task can be blocked on besides the passing of time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resource {
+    /// Any queue like object: queues, semaphores and mutexes.
+    Queue(usize),
+    /// A notification value of the given task, by index.
+    Notification(TaskId, usize),
+    EventGroup(usize),
+    StreamBuffer(usize),
+}
+
+pub struct Tcb {
+    /// Zero terminated, handed out through `pcTaskGetName`.
+    pub name: Box<[u8]>,
+    pub number: UBaseType,
+    pub priority: UBaseType,
+    pub stack_size: u16,
+    pub state: TcbState,
+    ready_seq: u64,
+    blocked_on: Option<Resource>,
+    wake_at: Option<TickType>,
+    /// Set by `xTaskAbortDelay`, the blocking call returns as if it timed out.
+    delay_aborted: bool,
+    pub notify_value: [u32; NOTIFICATION_ARRAY_ENTRIES],
+    pub notify_pending: [bool; NOTIFICATION_ARRAY_ENTRIES],
+    /// The thread local storage pointers, as addresses.
+    pub tls: [usize; TLS_ENTRIES],
+    /// Called with the pointer of the same index when the task is deleted.
+    pub tls_callbacks: [Option<TlsDeleteCallback>; TLS_ENTRIES],
+}
+
+pub type TlsDeleteCallback = unsafe extern "C" fn(i32, *mut c_void);
+
+pub struct MutexState {
+    pub holder: Option<TaskId>,
+    pub depth: u32,
+}
+
+pub struct MockQueue {
+    pub length: usize,
+    pub item_size: usize,
+    /// Semaphores and mutexes use empty items, so the length is their count.
+    pub items: VecDeque<Vec<u8>>,
+    pub mutex: Option<MutexState>,
+    /// The queue set this queue is a member of.
+    pub set: Option<usize>,
+}
+
+impl MockQueue {
+    pub fn new(length: usize, item_size: usize) -> MockQueue {
+        MockQueue {
+            length,
+            item_size,
+            items: VecDeque::with_capacity(length),
+            mutex: None,
+            set: None,
+        }
+    }
+
+    pub fn new_semaphore(max: usize, initial: usize) -> MockQueue {
+        let mut queue = MockQueue::new(max, 0);
+        queue.items.extend((0..initial).map(|_| Vec::new()));
+        queue
+    }
+
+    pub fn new_mutex() -> MockQueue {
+        let mut queue = MockQueue::new_semaphore(1, 1);
+        queue.mutex = Some(MutexState {
+            holder: None,
+            depth: 0,
+        });
+        queue
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.length
+    }
+}
+
+pub struct MockTimer {
+    pub period: TickType,
+    pub auto_reload: bool,
+    pub id: usize,
+    pub callback: extern "C" fn(TimerHandle),
+    pub expiry: Option<TickType>,
+}
+
+/// A task blocked in `xEventGroupWaitBits` or `xEventGroupSync`.
+pub struct EventWaiter {
+    pub task: TaskId,
+    pub mask: EventBits,
+    pub all: bool,
+    pub clear: bool,
+    /// The bits that unblocked the task, set by whoever set them.
+    pub result: Option<EventBits>,
+}
+
+impl EventWaiter {
+    pub fn satisfied_by(&self, bits: EventBits) -> bool {
+        if self.all {
+            bits & self.mask == self.mask
+        } else {
+            bits & self.mask != 0
+        }
+    }
+}
+
+pub struct MockEventGroup {
+    pub bits: EventBits,
+    pub waiters: Vec<EventWaiter>,
+}
+
+/// Message buffers keep each message's length in front of it, as a `usize`.
+pub struct MockStreamBuffer {
+    pub size: usize,
+    pub trigger_level: usize,
+    pub is_message_buffer: bool,
+    pub data: VecDeque<u8>,
+}
+
+impl MockStreamBuffer {
+    pub const LENGTH_SIZE: usize = core::mem::size_of::<usize>();
+
+    pub fn spaces(&self) -> usize {
+        self.size - self.data.len()
+    }
+
+    /// The bytes `write` needs to be free before writing a message of `len` bytes.
+    pub fn required(&self, len: usize) -> usize {
+        if self.is_message_buffer {
+            len + Self::LENGTH_SIZE
+        } else {
+            len
+        }
+    }
+
+    /// Write as much of `data` as fits, a message is written completely or not at all.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        if self.is_message_buffer {
+            if self.spaces() < self.required(data.len()) {
+                return 0;
+            }
+            self.data.extend(data.len().to_ne_bytes().iter());
+            self.data.extend(data.iter());
+            data.len()
+        } else {
+            let len = data.len().min(self.spaces());
+            self.data.extend(data[..len].iter());
+            len
+        }
+    }
+
+    pub fn next_message_len(&self) -> usize {
+        if self.data.len() < Self::LENGTH_SIZE {
+            return 0;
+        }
+        let mut len = [0u8; Self::LENGTH_SIZE];
+        for (dst, src) in len.iter_mut().zip(self.data.iter()) {
+            *dst = *src;
+        }
+        usize::from_ne_bytes(len)
+    }
+
+    /// Read up to `buf.len()` bytes, or the next message if it fits into `buf`.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = if self.is_message_buffer {
+            let len = self.next_message_len();
+            if self.data.is_empty() || len > buf.len() {
+                return 0;
+            }
+            self.data.drain(..Self::LENGTH_SIZE);
+            len
+        } else {
+            buf.len().min(self.data.len())
+        };
+        for (dst, src) in buf.iter_mut().zip(self.data.drain(..len)) {
+            *dst = src;
+        }
+        len
+    }
+
+    /// Whether a blocked reader should be woken up.
+    pub fn triggered(&self) -> bool {
+        !self.data.is_empty() && (self.is_message_buffer || self.data.len() >= self.trigger_level)
+    }
+}
+
+pub struct State {
+    pub tick: TickType,
+    pub running: Running,
+    seq: u64,
+    /// Never shrinks, the id of a task is its index plus one.
+    pub tasks: Vec<Tcb>,
+    pub queues: Vec<Option<MockQueue>>,
+    pub timers: Vec<Option<MockTimer>>,
+    pub event_groups: Vec<Option<MockEventGroup>>,
+    pub stream_buffers: Vec<Option<MockStreamBuffer>>,
+    /// Nesting depth of `vTaskSuspendAll`, no task switches happen while it's not 0.
+    pub scheduler_suspended: u32,
+}
+
+impl State {
+    fn new() -> State {
+        State {
+            tick: 0,
+            running: Running::Controller,
+            seq: 0,
+            tasks: Vec::new(),
+            queues: Vec::new(),
+            timers: Vec::new(),
+            event_groups: Vec::new(),
+            stream_buffers: Vec::new(),
+            scheduler_suspended: 0,
+        }
+    }
+
+    pub fn tcb(&self, id: TaskId) -> &Tcb {
+        &self.tasks[id - 1]
+    }
+
+    pub fn tcb_mut(&mut self, id: TaskId) -> &mut Tcb {
+        &mut self.tasks[id - 1]
+    }
+
+    pub fn queue(&mut self, id: usize) -> &mut MockQueue {
+        self.queues[id - 1]
+            .as_mut()
+            .expect("mock kernel: use of a deleted queue")
+    }
+
+    pub fn timer(&mut self, id: usize) -> &mut MockTimer {
+        self.timers[id - 1]
+            .as_mut()
+            .expect("mock kernel: use of a deleted timer")
+    }
+
+    pub fn event_group(&mut self, id: usize) -> &mut MockEventGroup {
+        self.event_groups[id - 1]
+            .as_mut()
+            .expect("mock kernel: use of a deleted event group")
+    }
+
+    pub fn stream_buffer(&mut self, id: usize) -> &mut MockStreamBuffer {
+        self.stream_buffers[id - 1]
+            .as_mut()
+            .expect("mock kernel: use of a deleted stream buffer")
+    }
+
+    pub fn add_queue(&mut self, queue: MockQueue) -> usize {
+        self.queues.push(Some(queue));
+        self.queues.len()
+    }
+
+    pub fn add_timer(&mut self, timer: MockTimer) -> usize {
+        self.timers.push(Some(timer));
+        self.timers.len()
+    }
+
+    pub fn add_event_group(&mut self) -> usize {
+        self.event_groups.push(Some(MockEventGroup {
+            bits: 0,
+            waiters: Vec::new(),
+        }));
+        self.event_groups.len()
+    }
+
+    /// Set bits the way `xEventGroupSetBits` does: every waiter the new bits satisfy is
+    /// unblocked, then the bits those waiters clear on exit are cleared.
+    /// Returns the bits afterwards and whether any task was woken.
+    pub fn set_event_bits(&mut self, id: usize, bits: EventBits) -> (EventBits, bool) {
+        let group = self.event_group(id);
+        group.bits |= bits;
+        let current = group.bits;
+
+        let mut clear = 0;
+        let mut woken = Vec::new();
+        for waiter in group.waiters.iter_mut() {
+            if waiter.result.is_none() && waiter.satisfied_by(current) {
+                waiter.result = Some(current);
+                if waiter.clear {
+                    clear |= waiter.mask;
+                }
+                woken.push(waiter.task);
+            }
+        }
+        group.bits &= !clear;
+        let remaining = group.bits;
+
+        for &task in &woken {
+            self.make_ready(task);
+        }
+        (remaining, !woken.is_empty())
+    }
+
+    /// Add an item to the back of a queue and wake up the tasks waiting on it, or on the
+    /// set the queue is a member of. Returns whether any task was woken.
+    pub fn push_item(&mut self, id: usize, item: Vec<u8>) -> bool {
+        self.insert_item(id, item, false)
+    }
+
+    /// Like [`push_item`](Self::push_item), but adds the item to either end of the queue.
+    pub fn insert_item(&mut self, id: usize, item: Vec<u8>, to_front: bool) -> bool {
+        let queue = self.queue(id);
+        if to_front {
+            queue.items.push_front(item);
+        } else {
+            queue.items.push_back(item);
+        }
+        let set = queue.set;
+
+        let mut woken = self.wake_all(Resource::Queue(id));
+        if let Some(set) = set {
+            self.queue(set).items.push_back(id.to_ne_bytes().to_vec());
+            woken |= self.wake_all(Resource::Queue(set));
+        }
+        woken
+    }
+
+    /// Move a task to the back of the ready list of its priority.
+    pub fn make_ready(&mut self, id: TaskId) {
+        self.seq += 1;
+        let seq = self.seq;
+        let tcb = self.tcb_mut(id);
+        tcb.state = TcbState::Ready;
+        tcb.ready_seq = seq;
+        tcb.blocked_on = None;
+        tcb.wake_at = None;
+    }
+
+    /// Make every task that waits on `resource` ready, so it can retry its operation.
+    /// Returns whether any task was woken.
+    pub fn wake_all(&mut self, resource: Resource) -> bool {
+        let mut woken = false;
+        for id in 1..=self.tasks.len() {
+            let tcb = self.tcb(id);
+            if tcb.state == TcbState::Blocked && tcb.blocked_on == Some(resource) {
+                self.make_ready(id);
+                woken = true;
+            }
+        }
+        woken
+    }
+
+    pub fn has_waiters(&self, resource: Resource) -> bool {
+        self.tasks
+            .iter()
+            .any(|tcb| tcb.state == TcbState::Blocked && tcb.blocked_on == Some(resource))
+    }
+
+    pub fn live_tasks(&self) -> impl Iterator<Item = (TaskId, &Tcb)> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .map(|(i, tcb)| (i + 1, tcb))
+            .filter(|(_, tcb)| tcb.state != TcbState::Deleted)
+    }
+
+    /// The task state as `eTaskGetState` would report it to the controller.
+    pub fn task_state(&self, id: TaskId) -> TaskState {
+        let tcb = self.tcb(id);
+        match tcb.state {
+            TcbState::Running => TaskState::Running,
+            TcbState::Ready => TaskState::Ready,
+            // Like the kernel, a task delayed without a timeout is reported as suspended.
+            // Waiting for an object or a notification without one is still blocked.
+            TcbState::Blocked if tcb.wake_at.is_none() && tcb.blocked_on.is_none() => {
+                TaskState::Suspended
+            }
+            TcbState::Blocked => TaskState::Blocked,
+            TcbState::Suspended => TaskState::Suspended,
+            TcbState::Deleted => TaskState::Deleted,
+        }
+    }
+
+    /// Pick the highest priority ready task, the one that waited longest among equals.
+    fn dispatch(&mut self) {
+        let next = self
+            .tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, tcb)| tcb.state == TcbState::Ready)
+            .max_by(|(_, a), (_, b)| {
+                a.priority
+                    .cmp(&b.priority)
+                    .then(b.ready_seq.cmp(&a.ready_seq))
+            })
+            .map(|(i, _)| i + 1);
+
+        #[cfg(feature = "trace")]
+        let previous = self.running;
+        self.running = match next {
+            Some(id) => {
+                self.tcb_mut(id).state = TcbState::Running;
+                Running::Task(id)
+            }
+            None => Running::Controller,
+        };
+
+        #[cfg(feature = "trace")]
+        if previous != self.running {
+            use crate::trace::{record, EventKind};
+            if let Running::Task(id) = previous {
+                record(EventKind::TaskSwitchedOut, id, 0, self.tick as _);
+            }
+            if let Running::Task(id) = self.running {
+                record(EventKind::TaskSwitchedIn, id, 0, self.tick as _);
+            }
+        }
+    }
+
+    fn has_ready_above(&self, priority: UBaseType) -> bool {
+        self.tasks
+            .iter()
+            .any(|tcb| tcb.state == TcbState::Ready && tcb.priority > priority)
+    }
+
+    /// Advance the tick count by one, wake up timed out tasks and collect the expired timers.
+    fn tick(&mut self) -> Vec<(TimerHandle, extern "C" fn(TimerHandle))> {
+        self.tick = self.tick.wrapping_add(1);
+        let tick = self.tick;
+
+        for id in 1..=self.tasks.len() {
+            let tcb = self.tcb(id);
+            if tcb.state == TcbState::Blocked && tcb.wake_at == Some(tick) {
+                self.make_ready(id);
+            }
+        }
+
+        let mut expired = Vec::new();
+        for (i, timer) in self.timers.iter_mut().enumerate() {
+            if let Some(timer) = timer {
+                if timer.expiry == Some(tick) {
+                    timer.expiry = if timer.auto_reload {
+                        Some(tick.wrapping_add(timer.period))
+                    } else {
+                        None
+                    };
+                    expired.push((handle_from_id(i + 1), timer.callback));
+                }
+            }
+        }
+        expired
+    }
+}
+
+pub struct Shared {
+    state: Mutex<State>,
+    switch: Condvar,
+}
+
+/// Entry point of a task, moved to the thread that runs it.
+struct TaskStart {
+    entry: extern "C" fn(*mut c_void),
+    arg: *mut c_void,
+}
+
+unsafe impl Send for TaskStart {}
+
+impl Shared {
+    pub fn new() -> Arc<Shared> {
+        Arc::new(Shared {
+            state: Mutex::new(State::new()),
+            switch: Condvar::new(),
+        })
+    }
+
+    /// Make this kernel the one used by the calling thread.
+    pub fn install(self: &Arc<Self>) {
+        KERNEL.with(|k| *k.borrow_mut() = Some(self.clone()));
+    }
+
+    pub fn uninstall(self: &Arc<Self>) {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            if k.as_ref().is_some_and(|k| Arc::ptr_eq(k, self)) {
+                *k = None;
+            }
+        });
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait_turn<'a>(
+        &'a self,
+        mut st: MutexGuard<'a, State>,
+        me: Running,
+    ) -> MutexGuard<'a, State> {
+        while st.running != me {
+            st = self
+                .switch
+                .wait(st)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        st
+    }
+
+    /// Hand the CPU to the next task, or back to the controller if none is ready,
+    /// and return once `me` runs again.
+    fn switch<'a>(&'a self, mut st: MutexGuard<'a, State>, me: Running) -> MutexGuard<'a, State> {
+        st.dispatch();
+        self.switch.notify_all();
+        self.wait_turn(st, me)
+    }
+
+    /// Let the controller run every ready task until all of them are blocked.
+    pub fn run(&self) {
+        assert!(
+            current_task().is_none(),
+            "mock kernel: the scheduler can only be driven from the controlling thread"
+        );
+        drop(self.switch(self.lock(), Running::Controller));
+    }
+
+    pub fn advance_ticks(&self, ticks: TickType) {
+        self.run();
+        for _ in 0..ticks {
+            let expired = self.lock().tick();
+            // Timer callbacks run on the controlling thread, outside of any task.
+            for (handle, callback) in expired {
+                callback(handle);
+            }
+            self.run();
+        }
+    }
+
+    /// Yield to a higher priority task that became ready because of something `me` did.
+    pub fn preempt<'a>(&'a self, st: MutexGuard<'a, State>, me: Option<TaskId>) -> MutexGuard<'a, State> {
+        match me {
+            Some(me) if st.scheduler_suspended == 0 && st.has_ready_above(st.tcb(me).priority) => {
+                let mut st = st;
+                st.make_ready(me);
+                self.switch(st, Running::Task(me))
+            }
+            _ => st,
+        }
+    }
+
+    pub fn suspend_all(&self) {
+        self.lock().scheduler_suspended += 1;
+    }
+
+    /// Returns whether `me` was preempted once the scheduler was resumed.
+    pub fn resume_all(&self, me: Option<TaskId>) -> bool {
+        let mut st = self.lock();
+        assert!(st.scheduler_suspended > 0, "mock kernel: the scheduler isn't suspended");
+        st.scheduler_suspended -= 1;
+
+        let preempted = match me {
+            Some(me) => st.scheduler_suspended == 0 && st.has_ready_above(st.tcb(me).priority),
+            None => false,
+        };
+        drop(self.preempt(st, me));
+        preempted
+    }
+
+    /// Let the other tasks of the same priority run first.
+    pub fn yield_now(&self, me: Option<TaskId>) {
+        if let Some(me) = me {
+            let mut st = self.lock();
+            if st.scheduler_suspended > 0 {
+                return;
+            }
+            st.make_ready(me);
+            drop(self.switch(st, Running::Task(me)));
+        }
+    }
+
+    /// Block `me` until it is woken up because of `resource` or `wake_at` has been reached.
+    pub fn block<'a>(
+        &'a self,
+        mut st: MutexGuard<'a, State>,
+        me: TaskId,
+        resource: Option<Resource>,
+        wake_at: Option<TickType>,
+    ) -> MutexGuard<'a, State> {
+        assert!(
+            st.scheduler_suspended == 0,
+            "mock kernel: tasks can't block while the scheduler is suspended"
+        );
+        let tcb = st.tcb_mut(me);
+        tcb.state = TcbState::Blocked;
+        tcb.blocked_on = resource;
+        tcb.wake_at = wake_at;
+        self.switch(st, Running::Task(me))
+    }
+
+    /// Retry `attempt` until it succeeds or `wait` ticks have passed, blocking on
+    /// `resource` in between. Mirrors the retry loop the kernel uses for every
+    /// blocking call.
+    pub fn wait_for<R>(
+        &self,
+        me: Option<TaskId>,
+        resource: Resource,
+        wait: TickType,
+        mut attempt: impl FnMut(&mut State) -> Option<R>,
+    ) -> Option<R> {
+        let mut st = self.lock();
+        let start = st.tick;
+        loop {
+            if let Some(r) = attempt(&mut st) {
+                drop(self.preempt(st, me));
+                return Some(r);
+            }
+
+            let aborted = me.is_some_and(|me| mem::take(&mut st.tcb_mut(me).delay_aborted));
+            if aborted
+                || wait == 0
+                || (wait != sys::portMAX_DELAY && st.tick.wrapping_sub(start) >= wait)
+            {
+                return None;
+            }
+            let me = match me {
+                Some(me) => me,
+                None => panic!("mock kernel: only tasks can block, use a zero timeout outside of them"),
+            };
+            let wake_at = if wait == sys::portMAX_DELAY {
+                None
+            } else {
+                Some(start.wrapping_add(wait))
+            };
+            st = self.block(st, me, Some(resource), wake_at);
+        }
+    }
+
+    /// Delay `me` for the given amount of ticks.
+    pub fn delay(&self, me: Option<TaskId>, ticks: TickType) {
+        let me = me.expect("mock kernel: only tasks can be delayed");
+        if ticks == 0 {
+            return self.yield_now(Some(me));
+        }
+        let st = self.lock();
+        let wake_at = if ticks == sys::portMAX_DELAY {
+            None
+        } else {
+            Some(st.tick.wrapping_add(ticks))
+        };
+        let mut st = self.block(st, me, None, wake_at);
+        st.tcb_mut(me).delay_aborted = false;
+    }
+
+    pub fn create_task(
+        self: &Arc<Self>,
+        me: Option<TaskId>,
+        entry: extern "C" fn(*mut c_void),
+        arg: *mut c_void,
+        name: &str,
+        stack_size: u16,
+        priority: UBaseType,
+    ) -> TaskId {
+        let id = {
+            let mut st = self.lock();
+            let number = st.tasks.len() as UBaseType + 1;
+            st.tasks.push(Tcb {
+                name: c_name(name),
+                number,
+                priority,
+                stack_size,
+                state: TcbState::Suspended,
+                ready_seq: 0,
+                blocked_on: None,
+                wake_at: None,
+                delay_aborted: false,
+                notify_value: [0; NOTIFICATION_ARRAY_ENTRIES],
+                notify_pending: [false; NOTIFICATION_ARRAY_ENTRIES],
+                tls: [0; TLS_ENTRIES],
+                tls_callbacks: [None; TLS_ENTRIES],
+            });
+            let id = st.tasks.len();
+            st.make_ready(id);
+            id
+        };
+
+        let kernel = self.clone();
+        let start = TaskStart { entry, arg };
+        thread::Builder::new()
+            .name(name.into())
+            .spawn(move || kernel.task_main(id, start))
+            .expect("mock kernel: failed to spawn the thread of a task");
+
+        drop(self.preempt(self.lock(), me));
+        id
+    }
+
+    fn task_main(self: Arc<Self>, id: TaskId, start: TaskStart) {
+        self.install();
+        CURRENT_TASK.with(|t| t.set(Some(id)));
+        drop(self.wait_turn(self.lock(), Running::Task(id)));
+
+        (start.entry)(start.arg);
+
+        // Returning from a task function is an error in FreeRTOS, treat it as a deletion.
+        self.run_tls_callbacks(id);
+        let mut st = self.lock();
+        if st.tcb(id).state != TcbState::Deleted {
+            st.tcb_mut(id).state = TcbState::Deleted;
+            st.dispatch();
+            self.switch.notify_all();
+        }
+    }
+
+    /// Delete `task`, or `me` if it is `None`.
+    ///
+    /// Like `vTaskDelete(NULL)` this never returns when a task deletes itself, its thread
+    /// stays parked until the test process exits.
+    pub fn delete_task(&self, me: Option<TaskId>, task: Option<TaskId>) {
+        let target = task
+            .or(me)
+            .expect("mock kernel: only tasks can delete themselves");
+        if self.lock().tcb(target).state == TcbState::Deleted {
+            return;
+        }
+        self.run_tls_callbacks(target);
+        let mut st = self.lock();
+        st.tcb_mut(target).state = TcbState::Deleted;
+        if Some(target) == me {
+            st.dispatch();
+            self.switch.notify_all();
+            self.park(st);
+        }
+    }
+
+    /// Block the thread of a deleted task for good, it is never dispatched again.
+    fn park(&self, mut st: MutexGuard<State>) -> ! {
+        loop {
+            st = self
+                .switch
+                .wait(st)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Call the deletion callbacks of the thread local storage pointers of `task`, without
+    /// holding the lock so they can use the kernel.
+    fn run_tls_callbacks(&self, task: TaskId) {
+        let callbacks: Vec<_> = {
+            let mut st = self.lock();
+            let tcb = st.tcb_mut(task);
+            (0..TLS_ENTRIES)
+                .filter_map(|i| tcb.tls_callbacks[i].take().map(|c| (i, c, tcb.tls[i])))
+                .collect()
+        };
+        for (index, callback, value) in callbacks {
+            unsafe { callback(index as i32, value as *mut c_void) };
+        }
+    }
+
+    /// Suspend `task`, or `me` if it is `None`. Returns once `me` is resumed.
+    pub fn suspend_task(&self, me: Option<TaskId>, task: Option<TaskId>) {
+        let target = task
+            .or(me)
+            .expect("mock kernel: only tasks can suspend themselves");
+        let mut st = self.lock();
+        let tcb = st.tcb_mut(target);
+        tcb.state = TcbState::Suspended;
+        tcb.blocked_on = None;
+        tcb.wake_at = None;
+        if Some(target) == me {
+            drop(self.switch(st, Running::Task(target)));
+        }
+    }
+
+    /// Resume a suspended task, returns whether it has a higher priority than `me`.
+    pub fn resume_task(&self, me: Option<TaskId>, task: TaskId) -> bool {
+        let mut st = self.lock();
+        if st.tcb(task).state != TcbState::Suspended {
+            return false;
+        }
+        st.make_ready(task);
+        match me {
+            Some(me) => st.tcb(task).priority > st.tcb(me).priority,
+            None => true,
+        }
+    }
+
+    /// Make a blocked task ready, its blocking call fails as if it had timed out.
+    pub fn abort_delay(&self, task: TaskId) -> bool {
+        let mut st = self.lock();
+        if st.tcb(task).state != TcbState::Blocked {
+            return false;
+        }
+        st.make_ready(task);
+        st.tcb_mut(task).delay_aborted = true;
+        true
+    }
+
+    pub fn set_priority(&self, me: Option<TaskId>, task: Option<TaskId>, priority: UBaseType) {
+        let target = task
+            .or(me)
+            .expect("mock kernel: only tasks have a priority of their own");
+        let mut st = self.lock();
+        st.tcb_mut(target).priority = priority;
+        drop(self.preempt(st, me));
+    }
+}
+
+/// The kernel used by the calling thread.
+pub fn kernel() -> Arc<Shared> {
+    KERNEL
+        .with(|k| k.borrow().clone())
+        .expect("no mock kernel is active on this thread, create one with `mock::Kernel::new()`")
+}
+
+/// The task running on the calling thread, `None` on the controlling thread.
+pub fn current_task() -> Option<TaskId> {
+    CURRENT_TASK.with(|t| t.get())
+}
+
+pub fn id_from_handle(handle: ptr::NonNull<c_void>) -> usize {
+    handle.as_ptr() as usize
+}
+
+pub fn handle_from_id(id: usize) -> ptr::NonNull<c_void> {
+    ptr::NonNull::new(id as *mut c_void).unwrap()
+}
+
+/// Truncate and zero terminate a name the same way the kernel does.
+pub fn c_name(name: &str) -> Box<[u8]> {
+    let len = (sys::configMAX_TASK_NAME_LEN as usize - 1).min(name.len());
+    let mut buf = vec![0u8; len + 1];
+    buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+    buf.into_boxed_slice()
+}
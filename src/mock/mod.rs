@@ -0,0 +1,115 @@
+//! Deterministic kernel written in Rust, for unit testing application code on the host.
+//!
+//! With the `mock` feature the [`glue`](crate::glue) layer is backed by this module instead
+//! of a FreeRTOS kernel, everything built on top of it (tasks, queues, semaphores, mutexes,
+//! notifications, timers, event groups and stream buffers) keeps working unchanged.
+//!
+//! Every task gets its own thread, but only a single one of them is ever allowed to run:
+//! the highest priority ready task, tasks of equal priority run in the order they became
+//! ready. A task runs until it blocks, there is no time slicing. Time only passes when the
+//! test calls [`Kernel::advance_ticks`], which runs every task that became ready until all
+//! of them are blocked again, tick by tick. Timer callbacks run on the thread of the test.
+//!
+//! The thread that created the [`Kernel`] is the controller. It can create tasks and use
+//! every object without blocking, blocking calls that can't complete immediately panic
+//! there instead of waiting.
+//!
+//! Differences to the real kernel:
+//! - Mutexes don't implement priority inheritance.
+//! - Critical sections don't do anything, there are no interrupts to mask. Suspending the
+//!   scheduler holds back preemption, blocking while it's suspended panics.
+//! - The run time counters and stack high water marks aren't measured.
+//! - The application hooks of the `hooks` module are never called.
+//! - With the `trace` feature only context switches and queue sends and receives of tasks
+//!   are recorded, timestamped with the tick count.
+//! - Event group bits set or cleared from an interrupt change right away, not once the
+//!   timer daemon task gets to run.
+//! - A panic inside a task can't unwind out of the task function and aborts the test,
+//!   checks are best made by the controller.
+//!
+//! ```
+//! use freertos::mock::Kernel;
+//! use freertos::*;
+//! use std::sync::Arc;
+//!
+//! let kernel = Kernel::new();
+//! let queue = Arc::new(Queue::new(1).unwrap());
+//!
+//! let sender = queue.clone();
+//! Task::new()
+//!     .start(move || {
+//!         CurrentTask::delay(Ticks::new(10));
+//!         sender.send(42u32, Ticks::zero()).unwrap();
+//!     })
+//!     .unwrap();
+//!
+//! kernel.advance_ticks(9);
+//! assert!(queue.receive(Ticks::zero()).is_err());
+//! kernel.advance_ticks(1);
+//! assert_eq!(queue.receive(Ticks::zero()), Ok(42));
+//! ```
+
+use std::sync::Arc;
+
+use crate::base::*;
+use crate::task::Task;
+
+pub mod glue;
+mod kernel;
+pub(crate) mod sys;
+#[cfg(test)]
+mod tests;
+
+use self::kernel::Shared;
+
+/// A mock kernel instance, owned by the test that drives it.
+///
+/// Creating it makes it the kernel of the calling thread, so tests that run in parallel
+/// each get their own kernel.
+pub struct Kernel {
+    shared: Arc<Shared>,
+}
+
+impl Kernel {
+    /// Create a new kernel and make it the kernel of the calling thread.
+    pub fn new() -> Kernel {
+        let shared = Shared::new();
+        shared.install();
+        Kernel { shared }
+    }
+
+    /// Run every ready task until all of them are blocked, without advancing time.
+    pub fn run(&self) {
+        self.shared.run();
+    }
+
+    /// Advance the tick count one tick at a time, running every task that becomes ready
+    /// and every timer that expires along the way.
+    pub fn advance_ticks(&self, ticks: TickType) {
+        self.shared.advance_ticks(ticks);
+    }
+
+    /// The current tick count.
+    pub fn tick_count(&self) -> TickType {
+        self.shared.lock().tick
+    }
+
+    /// The state of a task, as `eTaskGetState` would report it.
+    pub fn task_state(&self, task: &Task) -> TaskState {
+        let id = kernel::id_from_handle(task.clone().into_raw());
+        self.shared.lock().task_state(id)
+    }
+}
+
+impl Default for Kernel {
+    fn default() -> Kernel {
+        Kernel::new()
+    }
+}
+
+impl Drop for Kernel {
+    /// Tasks that are still blocked keep their threads parked until the test process exits.
+    fn drop(&mut self) {
+        self.shared.uninstall();
+    }
+}
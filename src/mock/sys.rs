@@ -0,0 +1,54 @@
+//! The subset of the kernel's C types the rest of the crate refers to through `sys`,
+//! matching the 32 bit targets.
+
+use crate::base::{c_char, c_void};
+
+pub type BaseType_t = i32;
+pub type UBaseType_t = u32;
+pub type StackType_t = u32;
+pub type configSTACK_DEPTH_TYPE = u16;
+pub type TickType_t = u32;
+pub type EventBits_t = TickType_t;
+pub type eNotifyAction = u32;
+pub type eTaskState = u32;
+
+pub const pdTRUE: BaseType_t = 1;
+
+pub const configTICK_RATE_HZ: u32 = 1000;
+pub const configMAX_TASK_NAME_LEN: u32 = 16;
+pub const configTASK_NOTIFICATION_ARRAY_ENTRIES: u32 = 8;
+pub const configNUM_THREAD_LOCAL_STORAGE_POINTERS: u32 = 4;
+pub const portMAX_DELAY: TickType_t = TickType_t::MAX;
+
+pub const eNotifyAction_eSetBits: eNotifyAction = 1;
+pub const eNotifyAction_eIncrement: eNotifyAction = 2;
+pub const eNotifyAction_eSetValueWithOverwrite: eNotifyAction = 3;
+pub const eNotifyAction_eSetValueWithoutOverwrite: eNotifyAction = 4;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct TaskStatus_t {
+    pub xHandle: *mut c_void,
+    pub pcTaskName: *const c_char,
+    pub xTaskNumber: UBaseType_t,
+    pub eCurrentState: eTaskState,
+    pub uxCurrentPriority: UBaseType_t,
+    pub uxBasePriority: UBaseType_t,
+    pub ulRunTimeCounter: u32,
+    pub pxStackBase: *mut StackType_t,
+    pub usStackHighWaterMark: u16,
+}
+
+// Storage for statically allocated objects is accepted but not used by the mock kernel.
+pub struct StaticTask_t {
+    _private: [usize; 0],
+}
+pub struct StaticQueue_t {
+    _private: [usize; 0],
+}
+pub struct StaticTimer_t {
+    _private: [usize; 0],
+}
+pub struct StaticEventGroup_t {
+    _private: [usize; 0],
+}
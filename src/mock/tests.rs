@@ -0,0 +1,170 @@
+use super::Kernel;
+use crate::prelude::*;
+use crate::*;
+
+/// Everything that is in the queue right now.
+fn drain<T: Sized + Copy + Send>(queue: &Queue<T>) -> Vec<T> {
+    core::iter::from_fn(|| queue.receive(Ticks::zero()).ok()).collect()
+}
+
+#[test]
+fn receive_wakes_on_delayed_send() {
+    let kernel = Kernel::new();
+    let queue = Arc::new(Queue::new(1).unwrap());
+    let out = Arc::new(Queue::new(1).unwrap());
+
+    let (sender, receiver, o) = (queue.clone(), queue.clone(), out.clone());
+    Task::new()
+        .priority(TaskPriority(1))
+        .start(move || {
+            CurrentTask::delay(Ticks::new(10));
+            sender.send(42u32, Ticks::zero()).unwrap();
+        })
+        .unwrap();
+    Task::new()
+        .priority(TaskPriority(2))
+        .start(move || {
+            let received = receiver.receive(Ticks::infinite()).unwrap();
+            o.send((received, get_tick_count()), Ticks::zero()).unwrap();
+        })
+        .unwrap();
+
+    kernel.advance_ticks(9);
+    assert!(out.receive(Ticks::zero()).is_err());
+    kernel.advance_ticks(1);
+    assert_eq!(out.receive(Ticks::zero()), Ok((42, 10)));
+}
+
+#[test]
+fn receive_times_out() {
+    let kernel = Kernel::new();
+    let queue = Arc::new(Queue::<u32>::new(1).unwrap());
+    let out = Arc::new(Queue::new(1).unwrap());
+
+    let (q, o) = (queue.clone(), out.clone());
+    let task = Task::new()
+        .start(move || {
+            let timed_out = q.receive(Ticks::new(5)) == Err(FreeRtosError::QueueReceiveTimeout);
+            o.send((timed_out, get_tick_count()), Ticks::zero()).unwrap();
+        })
+        .unwrap();
+
+    kernel.run();
+    assert_eq!(kernel.task_state(&task), TaskState::Blocked);
+    kernel.advance_ticks(4);
+    assert_eq!(kernel.task_state(&task), TaskState::Blocked);
+    kernel.advance_ticks(1);
+    assert_eq!(out.receive(Ticks::zero()), Ok((true, 5)));
+    assert_eq!(kernel.task_state(&task), TaskState::Deleted);
+}
+
+#[test]
+fn take_times_out_then_blocks_until_given() {
+    let kernel = Kernel::new();
+    let semaphore = Arc::new(Semaphore::new_binary().unwrap());
+    let out = Arc::new(Queue::new(2).unwrap());
+
+    let (s, o) = (semaphore.clone(), out.clone());
+    let task = Task::new()
+        .start(move || {
+            o.send(s.take(Ticks::new(5)).is_ok(), Ticks::zero()).unwrap();
+            o.send(s.take(Ticks::infinite()).is_ok(), Ticks::zero()).unwrap();
+        })
+        .unwrap();
+
+    kernel.run();
+    assert_eq!(kernel.task_state(&task), TaskState::Blocked);
+    kernel.advance_ticks(5);
+    assert_eq!(out.receive(Ticks::zero()), Ok(false));
+    // Waiting on the semaphore without a timeout is still blocked, only a delay without
+    // one is reported as suspended.
+    assert_eq!(kernel.task_state(&task), TaskState::Blocked);
+
+    kernel.advance_ticks(100);
+    assert!(out.receive(Ticks::zero()).is_err());
+    semaphore.give().unwrap();
+    kernel.run();
+    assert_eq!(out.receive(Ticks::zero()), Ok(true));
+}
+
+#[test]
+fn delay_and_delay_until() {
+    let kernel = Kernel::new();
+    let out = Arc::new(Queue::new(10).unwrap());
+
+    let o = out.clone();
+    Task::new()
+        .start(move || {
+            CurrentTask::delay(Ticks::new(3));
+            o.send(get_tick_count(), Ticks::zero()).unwrap();
+
+            let mut delay = TaskDelay::new();
+            for _ in 0..3 {
+                delay.delay_until(Ticks::new(4));
+                o.send(get_tick_count(), Ticks::zero()).unwrap();
+            }
+        })
+        .unwrap();
+
+    kernel.advance_ticks(20);
+    assert_eq!(drain(&out), [3, 7, 11, 15]);
+}
+
+#[test]
+fn higher_priority_task_preempts() {
+    let kernel = Kernel::new();
+    let semaphore = Arc::new(Semaphore::new_binary().unwrap());
+    let out = Arc::new(Queue::new(10).unwrap());
+
+    let (s, o) = (semaphore.clone(), out.clone());
+    Task::new()
+        .priority(TaskPriority(3))
+        .start(move || {
+            s.take(Ticks::infinite()).unwrap();
+            o.send(1, Ticks::zero()).unwrap();
+        })
+        .unwrap();
+    let (s, o) = (semaphore.clone(), out.clone());
+    Task::new()
+        .priority(TaskPriority(1))
+        .start(move || {
+            o.send(0, Ticks::zero()).unwrap();
+            s.give().unwrap();
+            o.send(2, Ticks::zero()).unwrap();
+        })
+        .unwrap();
+    let o = out.clone();
+    Task::new()
+        .priority(TaskPriority(1))
+        .start(move || o.send(3, Ticks::zero()).unwrap())
+        .unwrap();
+
+    kernel.run();
+    // The preempted task goes behind the other one of its priority, like in FreeRTOS.
+    assert_eq!(drain(&out), [0, 1, 3, 2]);
+}
+
+#[test]
+fn deleting_itself_stops_the_task() {
+    let kernel = Kernel::new();
+    let out = Arc::new(Queue::new(10).unwrap());
+
+    let o = out.clone();
+    let task = Task::new()
+        .priority(TaskPriority(2))
+        .start(move || {
+            o.send(1, Ticks::zero()).unwrap();
+            unsafe { Task::current().delete() };
+            o.send(2, Ticks::zero()).unwrap();
+        })
+        .unwrap();
+    let o = out.clone();
+    Task::new()
+        .priority(TaskPriority(1))
+        .start(move || o.send(3, Ticks::zero()).unwrap())
+        .unwrap();
+
+    kernel.advance_ticks(10);
+    assert_eq!(drain(&out), [1, 3]);
+    assert_eq!(kernel.task_state(&task), TaskState::Deleted);
+}
@@ -1,26 +1,45 @@
 use crate::base::*;
 use crate::glue;
+use crate::queue::StaticQueueBuffer;
+use crate::queue_set::QueueSetMember;
+use crate::task::{Task, TaskName};
 use crate::units::*;
-use core::cell::UnsafeCell;
+use core::cell::{Cell, RefCell, UnsafeCell};
 use core::fmt;
 use core::mem;
 use core::ops::{Deref, DerefMut};
 use core::ptr;
+#[cfg(feature = "mutex-diagnostics")]
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 pub type Mutex<T> = BasicMutex<T, Normal>;
-pub type RecursiveMutex<T> = BasicMutex<T, Recursive>;
+/// A mutex the task holding it can lock again. Every guard only gives shared access, the
+/// value is kept in a `RefCell` to change it.
+pub type RecursiveMutex<T> = BasicMutex<RefCell<T>, Recursive>;
 
-unsafe impl<T: Sync + Send, M> Send for BasicMutex<T, M> {}
+unsafe impl<T: Send, M> Send for BasicMutex<T, M> {}
 
-unsafe impl<T: Sync + Send, M> Sync for BasicMutex<T, M> {}
+unsafe impl<T: Send, M> Sync for BasicMutex<T, M> {}
 
 /// Mutual exclusion access to a contained value. Can be recursive -
 /// the current owner of a lock can re-lock it.
 pub struct BasicMutex<T: ?Sized, M> {
     mutex: M,
+    /// The tick count when the mutex was last locked.
+    #[cfg(feature = "mutex-diagnostics")]
+    locked_at: AtomicUsize,
     data: UnsafeCell<T>,
 }
 
+/// The task that held a mutex when locking it timed out, see
+/// [`FreeRtosError::MutexHeld`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MutexHolder {
+    pub name: TaskName,
+    /// How long the task had held the mutex at the time.
+    pub held_for: Ticks,
+}
+
 impl<T: ?Sized, M> fmt::Debug for BasicMutex<T, M>
 where
     M: Lockable + fmt::Debug,
@@ -33,20 +52,27 @@ where
 impl<T> BasicMutex<T, Normal> {
     /// Create a new mutex with the given inner value
     pub fn new(t: T) -> Result<Self, FreeRtosError> {
-        Ok(BasicMutex {
-            mutex: Normal::create()?,
-            data: UnsafeCell::new(t),
-        })
+        Ok(BasicMutex::from_parts(Normal::create()?, t))
+    }
+
+    /// Create a new mutex with the given inner value, without allocating
+    pub fn new_static(t: T, buffer: &'static mut StaticQueueBuffer) -> Result<Self, FreeRtosError> {
+        Ok(BasicMutex::from_parts(Normal::create_static(buffer)?, t))
     }
 }
 
-impl<T> BasicMutex<T, Recursive> {
+impl<T> BasicMutex<RefCell<T>, Recursive> {
     /// Create a new recursive mutex with the given inner value
     pub fn new(t: T) -> Result<Self, FreeRtosError> {
-        Ok(BasicMutex {
-            mutex: Recursive::create()?,
-            data: UnsafeCell::new(t),
-        })
+        Ok(BasicMutex::from_parts(Recursive::create()?, RefCell::new(t)))
+    }
+
+    /// Create a new recursive mutex with the given inner value, without allocating
+    pub fn new_static(t: T, buffer: &'static mut StaticQueueBuffer) -> Result<Self, FreeRtosError> {
+        Ok(BasicMutex::from_parts(
+            Recursive::create_static(buffer)?,
+            RefCell::new(t),
+        ))
     }
 }
 
@@ -54,9 +80,22 @@ impl<T, M> BasicMutex<T, M>
 where
     M: Lockable,
 {
-    /// Try to obtain a lock and mutable access to our inner value
+    fn from_parts(mutex: M, t: T) -> Self {
+        BasicMutex {
+            mutex,
+            #[cfg(feature = "mutex-diagnostics")]
+            locked_at: AtomicUsize::new(0),
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    /// Try to obtain a lock and access to our inner value, mutable access unless the
+    /// mutex is recursive
     pub fn lock(&self, max_wait: impl Into<Ticks>) -> Result<MutexGuard<T, M>, FreeRtosError> {
-        self.mutex.take(max_wait)?;
+        if let Err(e) = self.mutex.take(max_wait) {
+            return Err(self.timeout_error(e));
+        }
+        self.mark_locked();
 
         Ok(MutexGuard {
             mutex: &self.mutex,
@@ -64,6 +103,58 @@ where
         })
     }
 
+    /// Obtain the lock only if that's possible without waiting.
+    pub fn try_lock(&self) -> Result<MutexGuard<'_, T, M>, FreeRtosError> {
+        self.lock(Ticks::zero())
+    }
+
+    /// The task currently holding the lock, if any.
+    pub fn holder(&self) -> Option<Task> {
+        unsafe { glue::mutex_get_holder(self.mutex.handle()).map(Task::from_raw) }
+    }
+
+    /// Is the mutex locked by any task right now?
+    pub fn is_locked(&self) -> bool {
+        self.holder().is_some()
+    }
+
+    #[cfg(not(feature = "mutex-diagnostics"))]
+    fn mark_locked(&self) {}
+
+    #[cfg(not(feature = "mutex-diagnostics"))]
+    fn timeout_error(&self, error: FreeRtosError) -> FreeRtosError {
+        error
+    }
+
+    /// Remember when the mutex was locked, a recursive mutex locked again by its holder
+    /// keeps the time of the outermost lock.
+    #[cfg(feature = "mutex-diagnostics")]
+    fn mark_locked(&self) {
+        if self.mutex.is_outermost() {
+            let now = unsafe { glue::task_get_tick_count() };
+            self.locked_at.store(now as usize, Ordering::Relaxed);
+        }
+    }
+
+    /// Find out who holds the mutex, only the lower bits of the tick count are kept so
+    /// this works with 64 bit ticks on 32 bit targets too.
+    #[cfg(feature = "mutex-diagnostics")]
+    fn timeout_error(&self, error: FreeRtosError) -> FreeRtosError {
+        unsafe {
+            match glue::mutex_get_holder(self.mutex.handle()) {
+                Some(task) => {
+                    let now = glue::task_get_tick_count() as usize;
+                    let held_for = now.wrapping_sub(self.locked_at.load(Ordering::Relaxed));
+                    FreeRtosError::MutexHeld(MutexHolder {
+                        name: TaskName::from_task(task),
+                        held_for: Ticks::new(held_for as TickType),
+                    })
+                }
+                None => error,
+            }
+        }
+    }
+
     /// Consume the mutex and return its inner value
     pub fn into_inner(self) -> T {
         // Manually deconstruct the structure, because it implements Drop
@@ -73,6 +164,7 @@ where
                 let Self {
                     ref mutex,
                     ref data,
+                    ..
                 } = self;
                 (ptr::read(mutex), ptr::read(data))
             };
@@ -85,6 +177,30 @@ where
     }
 }
 
+impl<'a, T: 'a> QueueSetMember<'a> for BasicMutex<T, Normal> {
+    type Ready = MutexGuard<'a, T, Normal>;
+
+    fn queue_handle(&self) -> QueueHandle {
+        self.mutex.handle()
+    }
+
+    fn take_ready(&'a self) -> Result<Self::Ready, FreeRtosError> {
+        self.lock(Ticks::zero())
+    }
+}
+
+impl<'a, T: 'a> QueueSetMember<'a> for BasicMutex<T, Recursive> {
+    type Ready = MutexGuard<'a, T, Recursive>;
+
+    fn queue_handle(&self) -> QueueHandle {
+        self.mutex.handle()
+    }
+
+    fn take_ready(&'a self) -> Result<Self::Ready, FreeRtosError> {
+        self.lock(Ticks::zero())
+    }
+}
+
 /// Holds the mutex until we are dropped
 pub struct MutexGuard<'a, T: ?Sized + 'a, M: 'a>
 where
@@ -105,10 +221,8 @@ where
     }
 }
 
-impl<'mutex, T: ?Sized, M> DerefMut for MutexGuard<'mutex, T, M>
-where
-    M: Lockable,
-{
+/// Only a guard of a non-recursive mutex is the single reference to the value.
+impl<'mutex, T: ?Sized> DerefMut for MutexGuard<'mutex, T, Normal> {
     fn deref_mut<'a>(&'a mut self) -> &'a mut T {
         unsafe { &mut *self.data.get() }
     }
@@ -128,12 +242,27 @@ where
     Self: Sized,
 {
     fn create() -> Result<Self, FreeRtosError>;
+    fn handle(&self) -> QueueHandle;
     fn take(&self, max_wait: impl Into<Ticks>) -> Result<(), FreeRtosError>;
     fn give(&self);
+
+    /// Did the last successful `take()` lock the mutex, rather than lock it again?
+    fn is_outermost(&self) -> bool {
+        true
+    }
 }
 
 pub struct Normal(QueueHandle);
 
+impl Normal {
+    fn create_static(buffer: &'static mut StaticQueueBuffer) -> Result<Self, FreeRtosError> {
+        match unsafe { glue::create_mutex_static(buffer.as_mut_ptr()) } {
+            Some(h) => Ok(Normal(h)),
+            None => Err(FreeRtosError::OutOfMemory),
+        }
+    }
+}
+
 impl Lockable for Normal {
     fn create() -> Result<Self, FreeRtosError> {
         match unsafe { glue::create_mutex() } {
@@ -142,6 +271,10 @@ impl Lockable for Normal {
         }
     }
 
+    fn handle(&self) -> QueueHandle {
+        self.0
+    }
+
     fn take(&self, max_wait: impl Into<Ticks>) -> Result<(), FreeRtosError> {
         if unsafe { glue::take_mutex(self.0, max_wait.into().ticks) } {
             Ok(())
@@ -169,39 +302,180 @@ impl fmt::Debug for Normal {
     }
 }
 
-pub struct Recursive(QueueHandle);
+pub struct Recursive {
+    handle: QueueHandle,
+    /// How often the holder locked the mutex, only touched while holding it.
+    depth: Cell<u32>,
+}
+
+impl Recursive {
+    fn new(handle: QueueHandle) -> Self {
+        Recursive {
+            handle,
+            depth: Cell::new(0),
+        }
+    }
+
+    fn create_static(buffer: &'static mut StaticQueueBuffer) -> Result<Self, FreeRtosError> {
+        match unsafe { glue::create_recursive_mutex_static(buffer.as_mut_ptr()) } {
+            Some(m) => Ok(Recursive::new(m)),
+            None => Err(FreeRtosError::OutOfMemory),
+        }
+    }
+}
 
 impl Lockable for Recursive {
     fn create() -> Result<Self, FreeRtosError> {
         match unsafe { glue::create_recursive_mutex() } {
-            Some(m) => Ok(Recursive(m)),
+            Some(m) => Ok(Recursive::new(m)),
             None => Err(FreeRtosError::OutOfMemory),
         }
     }
 
+    fn handle(&self) -> QueueHandle {
+        self.handle
+    }
+
     fn take(&self, max_wait: impl Into<Ticks>) -> Result<(), FreeRtosError> {
-        if unsafe { glue::take_recursive_mutex(self.0, max_wait.into().ticks) } {
+        if unsafe { glue::take_recursive_mutex(self.handle, max_wait.into().ticks) } {
+            self.depth.set(self.depth.get() + 1);
             Ok(())
         } else {
             Err(FreeRtosError::MutexTimeout)
         }
     }
 
+    /// Called from `MutexGuard::drop`, so a give without a matching take is ignored
+    /// instead of panicking.
     fn give(&self) {
-        unsafe {
-            glue::give_mutex(self.0);
+        let depth = self.depth.get();
+        debug_assert!(depth > 0, "recursive mutex given more often than it was taken");
+        if depth == 0 {
+            return;
         }
+        self.depth.set(depth - 1);
+
+        let given = unsafe { glue::give_recursive_mutex(self.handle) };
+        debug_assert!(given, "recursive mutex given by a task that doesn't hold it");
+    }
+
+    fn is_outermost(&self) -> bool {
+        self.depth.get() == 1
     }
 }
 
 impl Drop for Recursive {
     fn drop(&mut self) {
-        unsafe { glue::delete_semaphore(self.0) }
+        unsafe { glue::delete_semaphore(self.handle) }
     }
 }
 
 impl fmt::Debug for Recursive {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.0)
+        write!(f, "{:?}", self.handle)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::prelude::*;
+    use crate::task::*;
+
+    #[test]
+    fn holder_is_the_locking_task() {
+        let kernel = Kernel::new();
+        let mutex = Arc::new(Mutex::new(0u32).unwrap());
+        assert!(!mutex.is_locked());
+        assert!(mutex.holder().is_none());
+
+        let m = mutex.clone();
+        let task = Task::new()
+            .name("holder")
+            .start(move || {
+                let _guard = m.lock(Ticks::infinite()).unwrap();
+                CurrentTask::delay(Ticks::new(10));
+            })
+            .unwrap();
+
+        kernel.advance_ticks(3);
+        assert!(mutex.is_locked());
+        assert_eq!(mutex.holder().unwrap().name().as_str(), "holder");
+        assert_eq!(mutex.holder().unwrap().into_raw(), task.clone().into_raw());
+        let error = mutex.try_lock().err().unwrap();
+        #[cfg(feature = "mutex-diagnostics")]
+        assert_eq!(
+            error,
+            FreeRtosError::MutexHeld(MutexHolder {
+                name: task.name(),
+                held_for: Ticks::new(3),
+            })
+        );
+        #[cfg(not(feature = "mutex-diagnostics"))]
+        assert_eq!(error, FreeRtosError::MutexTimeout);
+
+        kernel.advance_ticks(10);
+        assert!(!mutex.is_locked());
+        assert!(mutex.try_lock().is_ok());
+    }
+
+    #[cfg(feature = "mutex-diagnostics")]
+    #[test]
+    fn nested_lock_keeps_the_time_of_the_outer_one() {
+        let kernel = Kernel::new();
+        let mutex = Arc::new(RecursiveMutex::new(()).unwrap());
+
+        let m = mutex.clone();
+        let task = Task::new()
+            .start(move || {
+                let _outer = m.lock(Ticks::infinite()).unwrap();
+                CurrentTask::delay(Ticks::new(4));
+                let _inner = m.lock(Ticks::zero()).unwrap();
+                CurrentTask::delay(Ticks::new(10));
+            })
+            .unwrap();
+
+        kernel.advance_ticks(6);
+        assert_eq!(
+            mutex.try_lock().err(),
+            Some(FreeRtosError::MutexHeld(MutexHolder {
+                name: task.name(),
+                held_for: Ticks::new(6),
+            }))
+        );
+    }
+
+    #[test]
+    fn recursive_guards_share_the_value() {
+        let kernel = Kernel::new();
+        let mutex = Arc::new(RecursiveMutex::new(Vec::new()).unwrap());
+
+        let m = mutex.clone();
+        Task::new()
+            .priority(TaskPriority(2))
+            .start(move || {
+                let outer = m.lock(Ticks::infinite()).unwrap();
+                outer.borrow_mut().push(1);
+                {
+                    let inner = m.lock(Ticks::zero()).unwrap();
+                    inner.borrow_mut().push(2);
+                    let _borrowed = inner.borrow_mut();
+                    assert!(outer.try_borrow_mut().is_err());
+                }
+                CurrentTask::delay(Ticks::new(5));
+                outer.borrow_mut().push(3);
+            })
+            .unwrap();
+        let m = mutex.clone();
+        Task::new()
+            .start(move || m.lock(Ticks::infinite()).unwrap().borrow_mut().push(4))
+            .unwrap();
+
+        kernel.advance_ticks(2);
+        assert!(mutex.is_locked());
+        kernel.advance_ticks(5);
+        assert!(!mutex.is_locked());
+        assert_eq!(*mutex.try_lock().unwrap().borrow(), [1, 2, 3, 4]);
     }
 }
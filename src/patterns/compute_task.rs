@@ -14,7 +14,7 @@ pub trait ComputeTaskBuilder {
 }
 
 impl ComputeTaskBuilder for TaskBuilder {
-    #[cfg(target_os = "none")]
+    #[cfg(any(target_os = "none", feature = "posix", feature = "mock"))]
     /// Spawn a task that can post a return value to the outside.
     fn compute<F, R>(&self, func: F) -> Result<ComputeTask<R>, FreeRtosError>
     where
@@ -52,7 +52,7 @@ impl ComputeTaskBuilder for TaskBuilder {
         })
     }
 
-    #[cfg(not(target_os = "none"))]
+    #[cfg(not(any(target_os = "none", feature = "posix", feature = "mock")))]
     fn compute<F, R>(&self, func: F) -> Result<ComputeTask<R>, FreeRtosError>
     where
         F: FnOnce() -> R,
@@ -62,7 +62,7 @@ impl ComputeTaskBuilder for TaskBuilder {
         let r = func();
 
         Ok(ComputeTask {
-            task: Task::new().start(|_this_task| {}).unwrap(),
+            task: Task::new().start(|| {}).unwrap(),
             result: Arc::new(Mutex::new(Some(r)).unwrap()),
             status: Arc::new(Queue::new(1).unwrap()),
             finished: false,
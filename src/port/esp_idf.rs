@@ -0,0 +1,128 @@
+use core::cell::UnsafeCell;
+use core::convert::TryInto;
+use core::mem;
+
+use crate::base::*;
+
+pub const NUMBER_OF_CORES: usize = sys::configNUMBER_OF_CORES as usize;
+
+/// A `portMUX_TYPE`, the spinlock ESP-IDF takes along with masking interrupts.
+pub struct PortMux(UnsafeCell<sys::portMUX_TYPE>);
+
+unsafe impl Sync for PortMux {}
+
+impl PortMux {
+    pub const fn new() -> Self {
+        PortMux(UnsafeCell::new(sys::portMUX_TYPE {
+            owner: sys::portMUX_FREE_VAL,
+            count: 0,
+        }))
+    }
+
+    /// Mask interrupts on the current core and take the spinlock, from tasks and
+    /// interrupts alike. Can be nested.
+    #[inline(always)]
+    pub unsafe fn enter(&self) {
+        sys::xPortEnterCriticalTimeout(self.0.get(), sys::portMUX_NO_TIMEOUT);
+    }
+
+    #[inline(always)]
+    pub unsafe fn exit(&self) {
+        sys::vPortExitCritical(self.0.get())
+    }
+
+    /// The same as [`enter()`](Self::enter), the interrupt state is kept in the spinlock.
+    #[inline(always)]
+    pub unsafe fn enter_isr(&self) -> UBaseType {
+        self.enter();
+        0
+    }
+    #[inline(always)]
+    pub unsafe fn exit_isr(&self, _previous_state: UBaseType) {
+        self.exit()
+    }
+}
+
+/// Every [`ExclusiveData`](crate::ExclusiveData) has a spinlock of its own.
+pub type Spinlock = PortMux;
+
+/// ESP-IDF has no kernel wide critical section, every caller brings its own spinlock.
+static CRITICAL: PortMux = PortMux::new();
+
+#[inline(always)]
+pub unsafe fn enter_critical() {
+    CRITICAL.enter()
+}
+#[inline(always)]
+pub unsafe fn exit_critical() {
+    CRITICAL.exit()
+}
+#[inline(always)]
+pub unsafe fn enter_critical_isr() -> UBaseType {
+    CRITICAL.enter_isr()
+}
+#[inline(always)]
+pub unsafe fn exit_critical_isr(previous_state: UBaseType) {
+    CRITICAL.exit_isr(previous_state)
+}
+
+/// A task can be pinned to one core or run on all of them, a mask with any other number
+/// of bits set doesn't pin it.
+fn core_id(core_affinity_mask: UBaseType) -> BaseType {
+    if core_affinity_mask.count_ones() == 1 {
+        core_affinity_mask.trailing_zeros() as BaseType
+    } else {
+        sys::tskNO_AFFINITY as BaseType
+    }
+}
+
+#[inline(always)]
+pub unsafe fn create_task(
+    f: extern "C" fn(*mut c_void),
+    value: *mut c_void,
+    name: *const c_char,
+    stack_size: u16,
+    priority: UBaseType,
+    core_affinity_mask: UBaseType,
+    task_handle: &mut MaybeTaskHandle,
+) -> bool {
+    sys::xTaskCreatePinnedToCore(
+        Some(f),
+        name,
+        stack_size.into(),
+        value,
+        priority,
+        task_handle as *mut MaybeTaskHandle as *mut _,
+        core_id(core_affinity_mask),
+    ) == sys::pdPASS
+}
+#[inline(always)]
+pub unsafe fn create_task_static(
+    f: extern "C" fn(*mut c_void),
+    value: *mut c_void,
+    name: *const c_char,
+    stack: &mut [StackType],
+    priority: UBaseType,
+    core_affinity_mask: UBaseType,
+    tcb: *mut StaticTaskFfi,
+) -> Result<TaskHandle, FreeRtosError> {
+    let stack_depth = stack
+        .len()
+        .try_into()
+        .map_err(|_| FreeRtosError::InvalidStackSize)?;
+    let task_handle: MaybeTaskHandle = mem::transmute(sys::xTaskCreateStaticPinnedToCore(
+        Some(f),
+        name,
+        stack_depth,
+        value,
+        priority,
+        stack.as_mut_ptr(),
+        tcb,
+        core_id(core_affinity_mask),
+    ));
+    task_handle.ok_or(FreeRtosError::OutOfMemory)
+}
+#[inline(always)]
+pub unsafe fn get_idle_task_handle(core: UBaseType) -> MaybeTaskHandle {
+    mem::transmute(sys::xTaskGetIdleTaskHandleForCore(core as _))
+}
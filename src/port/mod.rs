@@ -0,0 +1,82 @@
+//! Differences between the kernels the crate can be built against.
+//!
+//! [`glue`](crate::glue) calls `sys` directly only for the parts of the API that are the
+//! same in every supported kernel, everything that differs goes through the backends
+//! selected here. One backend provides the API of the kernel version:
+//!
+//! - `v11`, the default: FreeRTOS V10.5 and newer, including the SMP kernel, the POSIX
+//!   port and the FreeRTOS fork of ESP-IDF 5.3 and newer.
+//! - `v10`, with the `kernel-v10` feature: FreeRTOS V10.0 up to V10.3, from before task
+//!   notification arrays and `xTaskDelayUntil`.
+//!
+//! Another one provides the number of cores, task creation with a core affinity, the idle
+//! tasks, critical sections and the `Spinlock` behind [`ExclusiveData`](crate::ExclusiveData):
+//!
+//! - `single_core`, the default.
+//! - `smp`, with the `smp` feature: the upstream SMP kernel, as exposed by
+//!   `freertos-esp32-sys`.
+//! - `esp_idf`, with the `esp-idf` feature: the ESP-IDF fork, single or dual core.
+//!   Critical sections take a `portMUX_TYPE` spinlock and tasks are pinned to a core when
+//!   they are created.
+
+#[cfg(all(feature = "kernel-v10", any(feature = "smp", feature = "esp-idf")))]
+compile_error!("the `kernel-v10` feature can't be combined with `smp` or `esp-idf`");
+
+#[cfg(feature = "kernel-v10")]
+mod v10;
+#[cfg(feature = "kernel-v10")]
+pub use self::v10::*;
+
+#[cfg(not(feature = "kernel-v10"))]
+mod v11;
+#[cfg(not(feature = "kernel-v10"))]
+pub use self::v11::*;
+
+#[cfg(not(feature = "esp-idf"))]
+use crate::base::UBaseType;
+
+#[cfg(not(any(feature = "smp", feature = "esp-idf")))]
+mod single_core;
+#[cfg(not(any(feature = "smp", feature = "esp-idf")))]
+pub use self::single_core::*;
+
+#[cfg(all(feature = "smp", not(feature = "esp-idf")))]
+mod smp;
+#[cfg(all(feature = "smp", not(feature = "esp-idf")))]
+pub use self::smp::*;
+
+#[cfg(feature = "esp-idf")]
+mod esp_idf;
+#[cfg(feature = "esp-idf")]
+pub use self::esp_idf::*;
+
+/// The kernel's critical sections used as a spinlock. With a single core masking
+/// interrupts is all it takes, and the upstream SMP kernel takes its task and ISR locks in
+/// them, which keep every other core out too.
+#[cfg(not(feature = "esp-idf"))]
+#[derive(Default)]
+pub struct Spinlock;
+
+#[cfg(not(feature = "esp-idf"))]
+impl Spinlock {
+    pub const fn new() -> Self {
+        Spinlock
+    }
+
+    #[inline(always)]
+    pub unsafe fn enter(&self) {
+        enter_critical()
+    }
+    #[inline(always)]
+    pub unsafe fn exit(&self) {
+        exit_critical()
+    }
+    #[inline(always)]
+    pub unsafe fn enter_isr(&self) -> UBaseType {
+        enter_critical_isr()
+    }
+    #[inline(always)]
+    pub unsafe fn exit_isr(&self, previous_state: UBaseType) {
+        exit_critical_isr(previous_state)
+    }
+}
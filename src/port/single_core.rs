@@ -0,0 +1,72 @@
+use core::convert::TryInto;
+use core::mem;
+
+use crate::base::*;
+
+pub const NUMBER_OF_CORES: usize = 1;
+
+#[inline(always)]
+pub unsafe fn create_task(
+    f: extern "C" fn(*mut c_void),
+    value: *mut c_void,
+    name: *const c_char,
+    stack_size: u16,
+    priority: UBaseType,
+    _core_affinity_mask: UBaseType,
+    task_handle: &mut MaybeTaskHandle,
+) -> bool {
+    sys::xTaskCreate(
+        Some(f),
+        name,
+        stack_size as _,
+        value,
+        priority,
+        task_handle as *mut MaybeTaskHandle as *mut _,
+    ) == sys::pdPASS
+}
+#[inline(always)]
+pub unsafe fn create_task_static(
+    f: extern "C" fn(*mut c_void),
+    value: *mut c_void,
+    name: *const c_char,
+    stack: &mut [StackType],
+    priority: UBaseType,
+    _core_affinity_mask: UBaseType,
+    tcb: *mut StaticTaskFfi,
+) -> Result<TaskHandle, FreeRtosError> {
+    let stack_depth = stack
+        .len()
+        .try_into()
+        .map_err(|_| FreeRtosError::InvalidStackSize)?;
+    let task_handle: MaybeTaskHandle = mem::transmute(sys::xTaskCreateStatic(
+        Some(f),
+        name,
+        stack_depth,
+        value,
+        priority,
+        stack.as_mut_ptr(),
+        tcb,
+    ));
+    task_handle.ok_or(FreeRtosError::OutOfMemory)
+}
+#[inline(always)]
+pub unsafe fn get_idle_task_handle(_core: UBaseType) -> MaybeTaskHandle {
+    mem::transmute(sys::xTaskGetIdleTaskHandle())
+}
+
+#[inline(always)]
+pub unsafe fn enter_critical() {
+    sys::vTaskEnterCritical()
+}
+#[inline(always)]
+pub unsafe fn exit_critical() {
+    sys::vTaskExitCritical()
+}
+#[inline(always)]
+pub unsafe fn enter_critical_isr() -> UBaseType {
+    sys::ulTaskEnterCriticalFromISR()
+}
+#[inline(always)]
+pub unsafe fn exit_critical_isr(previous_state: UBaseType) {
+    sys::vTaskExitCriticalFromISR(previous_state)
+}
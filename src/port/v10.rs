@@ -0,0 +1,124 @@
+use core::mem;
+use core::ptr;
+
+use crate::base::*;
+
+pub use sys::queueQUEUE_TYPE_BASE as QUEUE_TYPE_BASE;
+pub use sys::queueQUEUE_TYPE_BINARY_SEMAPHORE as QUEUE_TYPE_BINARY_SEMAPHORE;
+pub use sys::queueQUEUE_TYPE_MUTEX as QUEUE_TYPE_MUTEX;
+pub use sys::queueQUEUE_TYPE_RECURSIVE_MUTEX as QUEUE_TYPE_RECURSIVE_MUTEX;
+
+pub const TIMER_COMMAND_START: BaseType = sys::tmrCOMMAND_START as _;
+pub const TIMER_COMMAND_RESET: BaseType = sys::tmrCOMMAND_RESET as _;
+pub const TIMER_COMMAND_STOP: BaseType = sys::tmrCOMMAND_STOP as _;
+pub const TIMER_COMMAND_CHANGE_PERIOD: BaseType = sys::tmrCOMMAND_CHANGE_PERIOD as _;
+pub const TIMER_COMMAND_DELETE: BaseType = sys::tmrCOMMAND_DELETE as _;
+
+pub const NOTIFICATION_ARRAY_ENTRIES: UBaseType = 1;
+
+/// `vTaskDelayUntil` doesn't report whether the task was delayed, so this is worked out
+/// the same way the kernel does it, from the tick count before the call.
+#[inline(always)]
+pub unsafe fn task_delay_until(
+    pxPreviousWakeTime: *mut TickType,
+    xTimeIncrement: TickType,
+) -> bool {
+    let now = sys::xTaskGetTickCount();
+    let previous = *pxPreviousWakeTime;
+    let wake_time = previous.wrapping_add(xTimeIncrement);
+    let should_delay = if now < previous {
+        wake_time < previous && wake_time > now
+    } else {
+        wake_time < previous || wake_time > now
+    };
+
+    sys::vTaskDelayUntil(pxPreviousWakeTime, xTimeIncrement);
+    should_delay
+}
+
+// These kernels have a single notification value per task, `index` must be 0.
+
+#[inline(always)]
+pub unsafe fn task_notify_take(index: UBaseType, clear_count: bool, wait: TickType) -> u32 {
+    debug_assert_eq!(index, 0);
+    sys::ulTaskNotifyTake(
+        if clear_count {
+            sys::pdTRUE
+        } else {
+            sys::pdFALSE
+        },
+        wait,
+    )
+}
+#[inline(always)]
+pub unsafe fn task_notify_wait(
+    index: UBaseType,
+    ulBitsToClearOnEntry: u32,
+    ulBitsToClearOnExit: u32,
+    pulNotificationValue: *mut u32,
+    xTicksToWait: TickType,
+) -> bool {
+    debug_assert_eq!(index, 0);
+    sys::xTaskNotifyWait(
+        ulBitsToClearOnEntry,
+        ulBitsToClearOnExit,
+        pulNotificationValue,
+        xTicksToWait,
+    ) == sys::pdPASS
+}
+#[inline(always)]
+pub unsafe fn task_notify(
+    task: TaskHandle,
+    index: UBaseType,
+    value: u32,
+    action: sys::eNotifyAction,
+) -> bool {
+    debug_assert_eq!(index, 0);
+    sys::xTaskGenericNotify(task.as_ptr() as _, value, action, ptr::null_mut()) == sys::pdPASS
+}
+#[inline(always)]
+pub unsafe fn task_notify_isr(
+    task: TaskHandle,
+    index: UBaseType,
+    value: u32,
+    action: sys::eNotifyAction,
+    xHigherPriorityTaskWoken: *mut BaseType,
+) -> bool {
+    debug_assert_eq!(index, 0);
+    sys::xTaskGenericNotifyFromISR(
+        task.as_ptr() as _,
+        value,
+        action,
+        ptr::null_mut(),
+        xHigherPriorityTaskWoken,
+    ) == sys::pdPASS
+}
+
+#[inline(always)]
+pub unsafe fn stream_buffer_create(
+    size: usize,
+    trigger_level: usize,
+    is_message_buffer: bool,
+) -> MaybeStreamBufferHandle {
+    mem::transmute(sys::xStreamBufferGenericCreate(
+        size,
+        trigger_level,
+        is_message_buffer as BaseType,
+    ))
+}
+
+#[inline(always)]
+pub unsafe fn timer_command(
+    timer: TimerHandle,
+    command: BaseType,
+    value: TickType,
+    block_time: TickType,
+) -> bool {
+    sys::xTimerGenericCommand(
+        timer.as_ptr() as _,
+        command,
+        value,
+        ptr::null_mut(),
+        block_time,
+    ) == sys::pdPASS
+}
@@ -1,14 +1,29 @@
 use core::marker::PhantomData;
 use core::mem;
+use core::mem::MaybeUninit;
 
 use crate::base::*;
 use crate::glue;
 use crate::isr::*;
+use crate::queue_set::QueueSetMember;
 use crate::units::*;
 
 unsafe impl<T: Sized + Copy> Send for Queue<T> {}
 unsafe impl<T: Sized + Copy> Sync for Queue<T> {}
 
+/// Storage for the kernel's bookkeeping of a statically allocated queue, semaphore or mutex.
+pub struct StaticQueueBuffer(MaybeUninit<StaticQueueFfi>);
+
+impl StaticQueueBuffer {
+    pub const fn new() -> StaticQueueBuffer {
+        StaticQueueBuffer(MaybeUninit::uninit())
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut StaticQueueFfi {
+        self.0.as_mut_ptr()
+    }
+}
+
 /// A queue with a finite size. The items are owned by the queue and are
 /// copied.
 #[derive(Debug)]
@@ -20,7 +35,28 @@ pub struct Queue<T: Sized + Copy> {
 
 impl<T: Sized + Copy> Queue<T> {
     pub fn new(max_size: usize) -> Result<Queue<T>, FreeRtosError> {
-        match unsafe { glue::queue_create(max_size as u32, mem::size_of::<T>() as u32) } {
+        match unsafe { glue::queue_create(max_size as UBaseType, mem::size_of::<T>() as UBaseType) } {
+            Some(queue) => Ok(Queue {
+                queue,
+                item_type: PhantomData,
+            }),
+            None => Err(FreeRtosError::OutOfMemory),
+        }
+    }
+
+    /// Create a queue without allocating, holding up to `storage.len()` items in `storage`.
+    pub fn new_static(
+        storage: &'static mut [MaybeUninit<T>],
+        buffer: &'static mut StaticQueueBuffer,
+    ) -> Result<Queue<T>, FreeRtosError> {
+        match unsafe {
+            glue::queue_create_static(
+                storage.len() as UBaseType,
+                mem::size_of::<T>() as UBaseType,
+                storage.as_mut_ptr() as *mut u8,
+                buffer.as_mut_ptr(),
+            )
+        } {
             Some(queue) => Ok(Queue {
                 queue,
                 item_type: PhantomData,
@@ -63,6 +99,41 @@ impl<T: Sized + Copy> Queue<T> {
         }
     }
 
+    /// Send an item to the front of the queue, it is received before everything that is
+    /// already in the queue. Wait for the queue to have empty space for it.
+    pub fn send_to_front(&self, item: T, max_wait: impl Into<Ticks>) -> Result<(), FreeRtosError> {
+        unsafe {
+            if glue::queue_send_to_front(
+                self.queue,
+                &item as *const _ as *const _,
+                max_wait.into().ticks,
+            ) {
+                Ok(())
+            } else {
+                Err(FreeRtosError::QueueSendTimeout)
+            }
+        }
+    }
+
+    /// Replace the item of a queue that holds a single item, or send it if the queue is
+    /// empty. Only for queues of length 1, used as a mailbox.
+    pub fn overwrite(&self, item: T) {
+        unsafe {
+            glue::queue_overwrite(self.queue, &item as *const _ as *const _);
+        }
+    }
+
+    /// Replace the item of a queue of length 1, from an interrupt.
+    pub fn overwrite_from_isr(&self, context: &mut InterruptContext, item: T) {
+        unsafe {
+            glue::queue_overwrite_isr(
+                self.queue,
+                &item as *const _ as *const _,
+                context.get_task_field_mut(),
+            );
+        }
+    }
+
     /// Wait for an item to be available on the queue.
     pub fn receive(&self, max_wait: impl Into<Ticks>) -> Result<T, FreeRtosError> {
         unsafe {
@@ -78,6 +149,82 @@ impl<T: Sized + Copy> Queue<T> {
             }
         }
     }
+
+    /// Receive an item from an interrupt, if there is any.
+    pub fn receive_from_isr(&self, context: &mut InterruptContext) -> Result<T, FreeRtosError> {
+        unsafe {
+            let mut buff = mem::zeroed::<T>();
+            if glue::queue_receive_isr(
+                self.queue,
+                &mut buff as *mut _ as *mut _,
+                context.get_task_field_mut(),
+            ) {
+                Ok(buff)
+            } else {
+                Err(FreeRtosError::QueueReceiveTimeout)
+            }
+        }
+    }
+
+    /// Wait for an item to be available and get a copy of it, leaving it in the queue.
+    pub fn peek(&self, max_wait: impl Into<Ticks>) -> Result<T, FreeRtosError> {
+        unsafe {
+            let mut buff = mem::zeroed::<T>();
+            if glue::queue_peek(
+                self.queue,
+                &mut buff as *mut _ as *mut _,
+                max_wait.into().ticks,
+            ) {
+                Ok(buff)
+            } else {
+                Err(FreeRtosError::QueueReceiveTimeout)
+            }
+        }
+    }
+
+    /// Get a copy of the next item from an interrupt, leaving it in the queue.
+    pub fn peek_from_isr(&self, _context: &mut InterruptContext) -> Result<T, FreeRtosError> {
+        unsafe {
+            let mut buff = mem::zeroed::<T>();
+            if glue::queue_peek_isr(self.queue, &mut buff as *mut _ as *mut _) {
+                Ok(buff)
+            } else {
+                Err(FreeRtosError::QueueReceiveTimeout)
+            }
+        }
+    }
+
+    /// The number of items in the queue.
+    pub fn messages_waiting(&self) -> usize {
+        unsafe { glue::queue_messages_waiting(self.queue) as usize }
+    }
+
+    /// The number of items that can be sent before the queue is full.
+    pub fn spaces_available(&self) -> usize {
+        unsafe { glue::queue_spaces_available(self.queue) as usize }
+    }
+
+    /// Discard all items. Tasks waiting to send are unblocked.
+    pub fn reset(&self) {
+        unsafe { glue::queue_reset(self.queue) }
+    }
+
+    /// Is the queue full, from an interrupt?
+    pub fn is_full_from_isr(&self, _context: &mut InterruptContext) -> bool {
+        unsafe { glue::queue_is_full_isr(self.queue) }
+    }
+}
+
+impl<'a, T: Sized + Copy> QueueSetMember<'a> for Queue<T> {
+    type Ready = T;
+
+    fn queue_handle(&self) -> QueueHandle {
+        self.queue
+    }
+
+    fn take_ready(&'a self) -> Result<T, FreeRtosError> {
+        self.receive(Ticks::zero())
+    }
 }
 
 impl<T: Sized + Copy> Drop for Queue<T> {
@@ -87,3 +234,47 @@ impl<T: Sized + Copy> Drop for Queue<T> {
         }
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+
+    #[test]
+    fn front_peek_and_reset() {
+        let _kernel = Kernel::new();
+        let queue = Queue::new(3).unwrap();
+
+        queue.send(1u8, Ticks::zero()).unwrap();
+        queue.send_to_front(2, Ticks::zero()).unwrap();
+        assert_eq!(queue.messages_waiting(), 2);
+        assert_eq!(queue.spaces_available(), 1);
+        assert_eq!(queue.peek(Ticks::zero()), Ok(2));
+        assert_eq!(queue.receive(Ticks::zero()), Ok(2));
+
+        let mut context = InterruptContext::new();
+        assert!(!queue.is_full_from_isr(&mut context));
+        assert_eq!(queue.peek_from_isr(&mut context), Ok(1));
+        assert_eq!(queue.receive_from_isr(&mut context), Ok(1));
+        assert!(queue.receive_from_isr(&mut context).is_err());
+        drop(context);
+
+        queue.send(3, Ticks::zero()).unwrap();
+        queue.reset();
+        assert_eq!(queue.messages_waiting(), 0);
+    }
+
+    #[test]
+    fn overwrite_keeps_the_latest_item() {
+        let _kernel = Kernel::new();
+        let mailbox = Queue::new(1).unwrap();
+
+        mailbox.overwrite(1u32);
+        mailbox.overwrite(2);
+        let mut context = InterruptContext::new();
+        mailbox.overwrite_from_isr(&mut context, 3);
+        assert!(mailbox.is_full_from_isr(&mut context));
+        drop(context);
+        assert_eq!(mailbox.receive(Ticks::zero()), Ok(3));
+    }
+}
@@ -0,0 +1,245 @@
+use core::marker::PhantomData;
+
+use crate::base::*;
+use crate::glue;
+use crate::isr::*;
+use crate::prelude::*;
+use crate::units::*;
+
+/// Something that can be added to a [`QueueSet`]: queues, semaphores and mutexes.
+pub trait QueueSetMember<'a> {
+    /// What taking the member yields once it was selected.
+    type Ready;
+
+    fn queue_handle(&self) -> QueueHandle;
+
+    /// Take the item the member was selected for, without waiting.
+    fn take_ready(&'a self) -> Result<Self::Ready, FreeRtosError>;
+}
+
+unsafe impl<'a> Send for QueueSet<'a> {}
+unsafe impl<'a> Sync for QueueSet<'a> {}
+
+/// Block on several queues, semaphores and mutexes at once.
+///
+/// Every time an item is sent to a member, or a member is given, the set remembers that
+/// member. [`select()`](QueueSet::select) returns them in that order, and the selected
+/// member must then be taken, for example with [`select!`](crate::select).
+pub struct QueueSet<'a> {
+    handle: QueueHandle,
+    members: Vec<QueueHandle>,
+    _members: PhantomData<&'a ()>,
+}
+
+/// The member of a [`QueueSet`] that has an item ready.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueueSetReady(QueueHandle);
+
+impl QueueSetReady {
+    /// Is this the given member?
+    pub fn is<'a, M: QueueSetMember<'a>>(&self, member: &M) -> bool {
+        self.0 == member.queue_handle()
+    }
+}
+
+impl<'a> QueueSet<'a> {
+    /// Create a new queue set. The length has to be at least the sum of the lengths of
+    /// its members: the length of a queue, the maximum count of a semaphore or 1 for a
+    /// mutex.
+    pub fn new(length: usize) -> Result<QueueSet<'a>, FreeRtosError> {
+        match unsafe { glue::queue_set_create(length as UBaseType) } {
+            Some(handle) => Ok(QueueSet {
+                handle,
+                members: Vec::new(),
+                _members: PhantomData,
+            }),
+            None => Err(FreeRtosError::OutOfMemory),
+        }
+    }
+
+    /// Add a member. Fails with [`QueueFull`](FreeRtosError::QueueFull) if it isn't empty:
+    /// a queue with items, a semaphore that can be taken or a mutex that isn't locked,
+    /// or if it already is part of a set.
+    ///
+    /// A mutex is selected every time it is unlocked, including when the guard it was
+    /// selected for is dropped again.
+    pub fn add<M: QueueSetMember<'a>>(&mut self, member: &'a M) -> Result<(), FreeRtosError> {
+        let handle = member.queue_handle();
+        if unsafe { glue::queue_set_add(handle, self.handle) } {
+            self.members.push(handle);
+            Ok(())
+        } else {
+            Err(FreeRtosError::QueueFull)
+        }
+    }
+
+    /// Remove a member, which has to be empty.
+    pub fn remove<M: QueueSetMember<'a>>(&mut self, member: &'a M) -> Result<(), FreeRtosError> {
+        let handle = member.queue_handle();
+        if unsafe { glue::queue_set_remove(handle, self.handle) } {
+            self.members.retain(|h| *h != handle);
+            Ok(())
+        } else {
+            Err(FreeRtosError::QueueFull)
+        }
+    }
+
+    /// Wait for any of the members to have an item ready.
+    pub fn select(&self, max_wait: impl Into<Ticks>) -> Result<QueueSetReady, FreeRtosError> {
+        match unsafe { glue::queue_set_select(self.handle, max_wait.into().ticks) } {
+            Some(member) => Ok(QueueSetReady(member)),
+            None => Err(FreeRtosError::Timeout),
+        }
+    }
+
+    /// Get the member that has an item ready from an interrupt, if there is any.
+    pub fn select_from_isr(&self, _context: &mut InterruptContext) -> Option<QueueSetReady> {
+        unsafe { glue::queue_set_select_isr(self.handle).map(QueueSetReady) }
+    }
+}
+
+impl<'a> Drop for QueueSet<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            let mut removed = true;
+            for member in self.members.drain(..) {
+                removed &= glue::queue_set_remove(member, self.handle);
+            }
+
+            // A member that still has items can't leave the set, it would keep a pointer
+            // to the deleted set. The set is leaked instead.
+            if removed {
+                glue::queue_delete(self.handle);
+            }
+        }
+    }
+}
+
+/// Wait on the members of a [`QueueSet`] and run the arm of the one that is ready first,
+/// with what taking it yielded.
+///
+/// The member expressions are evaluated more than once. Returns the value of the arm, or
+/// an error once `max_wait` passed. A ready member without an arm, like one added by C
+/// code, fails with [`UnhandledSetMember`](crate::FreeRtosError::UnhandledSetMember) and
+/// is left to the caller to take; use [`QueueSet::select()`] to tell those apart.
+///
+/// ```ignore
+/// let mut set = QueueSet::new(5).unwrap();
+/// set.add(&commands).unwrap();
+/// set.add(&shutdown).unwrap();
+///
+/// loop {
+///     let keep_running = select!(set, Ticks::milliseconds(100), {
+///         command = commands => handle(command),
+///         () = shutdown => false,
+///     });
+///     if let Ok(false) = keep_running {
+///         break;
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! select {
+    ($set:expr, $max_wait:expr, { $($pat:pat = $member:expr => $body:expr),+ $(,)? }) => {
+        match $set.select($max_wait) {
+            Ok(ready) => {
+                $(
+                    if ready.is(&$member) {
+                        match $crate::QueueSetMember::take_ready(&$member) {
+                            Ok(taken) => {
+                                let $pat = taken;
+                                Ok($body)
+                            }
+                            Err(e) => Err(e),
+                        }
+                    } else
+                )+
+                {
+                    Err($crate::FreeRtosError::UnhandledSetMember)
+                }
+            }
+            Err(e) => Err(e),
+        }
+    };
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::mutex::Mutex;
+    use crate::queue::Queue;
+    use crate::semaphore::Semaphore;
+    use crate::task::*;
+
+    #[test]
+    fn select_runs_the_arm_of_the_ready_member() {
+        let kernel = Kernel::new();
+        let queue: &'static Queue<u32> = Box::leak(Box::new(Queue::new(2).unwrap()));
+        let semaphore: &'static Semaphore = Box::leak(Box::new(Semaphore::new_binary().unwrap()));
+        let out = Arc::new(Queue::new(3).unwrap());
+
+        let o = out.clone();
+        Task::new()
+            .start(move || {
+                let mut set = QueueSet::new(3).unwrap();
+                set.add(queue).unwrap();
+                set.add(semaphore).unwrap();
+                for _ in 0..3 {
+                    let selected = crate::select!(set, Ticks::new(5), {
+                        item = *queue => item,
+                        () = *semaphore => 100,
+                    });
+                    o.send(selected, Ticks::zero()).unwrap();
+                }
+            })
+            .unwrap();
+
+        kernel.run();
+        semaphore.give().unwrap();
+        queue.send(7, Ticks::zero()).unwrap();
+        kernel.advance_ticks(10);
+        assert_eq!(out.receive(Ticks::zero()), Ok(Ok(100)));
+        assert_eq!(out.receive(Ticks::zero()), Ok(Ok(7)));
+        assert_eq!(out.receive(Ticks::zero()), Ok(Err(FreeRtosError::Timeout)));
+    }
+
+    #[test]
+    fn member_without_an_arm_fails_the_select() {
+        let _kernel = Kernel::new();
+        let queue: &'static Queue<u32> = Box::leak(Box::new(Queue::new(1).unwrap()));
+        let semaphore: &'static Semaphore = Box::leak(Box::new(Semaphore::new_binary().unwrap()));
+
+        let mut set = QueueSet::new(2).unwrap();
+        set.add(queue).unwrap();
+        set.add(semaphore).unwrap();
+        semaphore.give().unwrap();
+        let selected = crate::select!(set, Ticks::zero(), { item = *queue => item });
+        assert_eq!(selected, Err(FreeRtosError::UnhandledSetMember));
+        assert!(semaphore.take(Ticks::zero()).is_ok());
+    }
+
+    #[test]
+    fn mutex_is_added_while_locked() {
+        let kernel = Kernel::new();
+        let mutex: &'static Mutex<u32> = Box::leak(Box::new(Mutex::new(5).unwrap()));
+        let out = Arc::new(Queue::new(2).unwrap());
+
+        let o = out.clone();
+        Task::new()
+            .start(move || {
+                let mut set = QueueSet::new(1).unwrap();
+                o.send(set.add(mutex).is_err(), Ticks::zero()).unwrap();
+                let guard = mutex.lock(Ticks::zero()).unwrap();
+                set.add(mutex).unwrap();
+                drop(guard);
+                let locked = crate::select!(set, Ticks::zero(), { guard = *mutex => *guard });
+                o.send(locked == Ok(5), Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(true));
+        assert_eq!(out.receive(Ticks::zero()), Ok(true));
+    }
+}
@@ -0,0 +1,215 @@
+use core::marker::PhantomData;
+use core::mem;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::base::*;
+use crate::join::JoinHandle;
+use crate::prelude::*;
+use crate::task::*;
+use crate::units::*;
+
+/// The notification index scopes wait on, reserved the first time it's needed.
+fn scope_index() -> Result<NotificationIndex, FreeRtosError> {
+    static INDEX: AtomicU32 = AtomicU32::new(u32::MAX);
+    NotificationIndex::reserve_once(&INDEX).ok_or(FreeRtosError::NoNotificationIndex)
+}
+
+/// Create a scope for spawning tasks that borrow from the current task. Every task spawned
+/// in it has finished once `scope` returns. Must be called by a task.
+///
+/// Waiting uses a notification index reserved with [`NotificationIndex::reserve()`] on
+/// the calling task. Fails with [`FreeRtosError::NoNotificationIndex`] without calling `f`
+/// if none is left. Deleting a task of the scope while it runs makes the scope wait
+/// forever.
+///
+/// ```ignore
+/// let mut buffer = [0u8; 64];
+/// let (left, right) = buffer.split_at_mut(32);
+/// freertos::scope(|s| {
+///     s.spawn(|| left.fill(1)).unwrap();
+///     s.spawn(|| right.fill(2)).unwrap();
+/// })
+/// .unwrap();
+/// ```
+pub fn scope<'env, F, T>(f: F) -> Result<T, FreeRtosError>
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+{
+    let scope = Scope {
+        owner: Task::current().into_raw(),
+        index: scope_index()?,
+        running: AtomicUsize::new(1),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+
+    // Waits even if `f` unwinds.
+    let _wait = WaitOnDrop(&scope);
+    Ok(f(&scope))
+}
+
+/// A scope to spawn tasks in, see [`scope()`].
+pub struct Scope<'scope, 'env: 'scope> {
+    owner: TaskHandle,
+    /// The notification index the owner waits on.
+    index: NotificationIndex,
+    /// The tasks that haven't finished yet, plus one for the owner until it waits.
+    running: AtomicUsize,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+unsafe impl<'scope, 'env> Sync for Scope<'scope, 'env> {}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Spawn a task that can borrow anything that outlives the scope, with the defaults of
+    /// [`Task::new()`].
+    pub fn spawn<F, R>(&'scope self, func: F) -> Result<JoinHandle<R>, FreeRtosError>
+    where
+        F: FnOnce() -> R + Send + 'scope,
+        R: Send + 'scope,
+    {
+        self.spawn_with(&Task::new(), func)
+    }
+
+    pub(crate) fn spawn_with<F, R>(
+        &'scope self,
+        builder: &TaskBuilder,
+        func: F,
+    ) -> Result<JoinHandle<R>, FreeRtosError>
+    where
+        F: FnOnce() -> R + Send + 'scope,
+        R: Send + 'scope,
+    {
+        unsafe {
+            // The scope waits for the task, so it outlives everything borrowed.
+            let (packet, body) = JoinHandle::task_body(func)?;
+            self.running.fetch_add(1, Ordering::Relaxed);
+            let child = Child {
+                body,
+                exit: ScopeExit(self),
+            };
+            let child: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+                let Child { body, exit } = child;
+                body();
+                drop(exit);
+            });
+            // SAFETY: only the lifetime changes. The scope waits until `exit` is dropped,
+            // and `Task::delete()` must not be used on the task.
+            let child = mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, BoxedClosure>(child);
+
+            // Failing to start drops `child`, which counts the task as finished.
+            let task = builder.start(child)?;
+            Ok(JoinHandle::from_parts(task, packet))
+        }
+    }
+
+    fn wait(&self) {
+        // Whoever drops `running` to zero is the last one to touch the scope. If it is a
+        // task, it notifies the owner once after that, and the owner waits for it.
+        if self.running.fetch_sub(1, Ordering::AcqRel) == 1 {
+            return;
+        }
+        let owner = Task::from_raw(self.owner);
+        loop {
+            owner.take_notification_indexed(self.index, true, Ticks::infinite());
+            if self.running.load(Ordering::Acquire) == 0 {
+                return;
+            }
+        }
+    }
+}
+
+/// The function of a scoped task and what tells the scope that it finished, dropped in
+/// this order even if the task never ran.
+struct Child<'scope, 'env, F> {
+    body: F,
+    exit: ScopeExit<'scope, 'env>,
+}
+
+struct ScopeExit<'scope, 'env>(&'scope Scope<'scope, 'env>);
+
+impl<'scope, 'env> Drop for ScopeExit<'scope, 'env> {
+    /// The scope can be gone as soon as `running` drops to zero, but the owner waits for
+    /// the notification.
+    fn drop(&mut self) {
+        let owner = Task::from_raw(self.0.owner);
+        let index = self.0.index;
+        if self.0.running.fetch_sub(1, Ordering::Release) == 1 {
+            owner.notify_indexed(index, TaskNotification::Increment);
+        }
+    }
+}
+
+struct WaitOnDrop<'a, 'scope, 'env>(&'a Scope<'scope, 'env>);
+
+impl<'a, 'scope, 'env> Drop for WaitOnDrop<'a, 'scope, 'env> {
+    fn drop(&mut self) {
+        self.0.wait();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::queue::Queue;
+
+    #[test]
+    fn scope_waits_for_its_tasks() {
+        let kernel = Kernel::new();
+        let out = Arc::new(Queue::new(1).unwrap());
+
+        let o = out.clone();
+        Task::new()
+            .priority(TaskPriority(2))
+            .start(move || {
+                let mut buffer = [0; 8];
+                let (left, right) = buffer.split_at_mut(4);
+                let filled = scope(|s| {
+                    s.spawn(|| {
+                        CurrentTask::delay(Ticks::new(3));
+                        left.fill(1);
+                    })
+                    .unwrap();
+                    let handle = Task::new()
+                        .spawn_scoped(s, || {
+                            right.fill(2);
+                            right.len()
+                        })
+                        .unwrap();
+                    handle.join(Ticks::infinite()).unwrap()
+                })
+                .unwrap();
+                o.send(filled as u32 + buffer.iter().sum::<u32>(), Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.advance_ticks(2);
+        assert!(out.receive(Ticks::zero()).is_err());
+        kernel.advance_ticks(1);
+        assert_eq!(out.receive(Ticks::zero()), Ok(4 + 12));
+    }
+
+    #[test]
+    fn scope_whose_tasks_finished_returns_at_once() {
+        let kernel = Kernel::new();
+        let out = Arc::new(Queue::new(1).unwrap());
+
+        let o = out.clone();
+        Task::new()
+            .start(move || {
+                let mut count = 0;
+                scope(|_| {}).unwrap();
+                scope(|s| {
+                    s.spawn(|| count += 1).unwrap().join(Ticks::infinite()).unwrap();
+                })
+                .unwrap();
+                o.send(count, Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(1));
+    }
+}
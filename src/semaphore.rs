@@ -1,6 +1,8 @@
 use crate::InterruptContext;
 use crate::base::*;
 use crate::glue;
+use crate::queue::StaticQueueBuffer;
+use crate::queue_set::QueueSetMember;
 use crate::units::*;
 
 /// A counting or binary semaphore
@@ -28,7 +30,7 @@ impl Semaphore {
     /// Create a new counting semaphore
     pub fn new_counting(max: u32, initial: u32) -> Result<Semaphore, FreeRtosError> {
         unsafe {
-            match glue::create_counting_semaphore(max, initial) {
+            match glue::create_counting_semaphore(max as UBaseType, initial as UBaseType) {
                 Some(semaphore) => Ok(Semaphore {
                     semaphore,
                 }),
@@ -37,18 +39,43 @@ impl Semaphore {
         }
     }
 
-    /// Lock this semaphore in a RAII fashion
-    pub fn lock(&self, max_wait: impl Into<Ticks>) -> Result<SemaphoreGuard, FreeRtosError> {
+    /// Create a new binary semaphore without allocating
+    pub fn new_binary_static(buffer: &'static mut StaticQueueBuffer) -> Result<Semaphore, FreeRtosError> {
         unsafe {
-            if glue::take_mutex(self.semaphore, max_wait.into().ticks) {
-                Ok(SemaphoreGuard {
-                    semaphore: self.semaphore,
-                })
-            } else {
-                Err(FreeRtosError::Timeout)
+            match glue::create_binary_semaphore_static(buffer.as_mut_ptr()) {
+                Some(semaphore) => Ok(Semaphore {
+                    semaphore,
+                }),
+                None => Err(FreeRtosError::OutOfMemory),
             }
         }
     }
+
+    /// Create a new counting semaphore without allocating
+    pub fn new_counting_static(
+        max: u32,
+        initial: u32,
+        buffer: &'static mut StaticQueueBuffer,
+    ) -> Result<Semaphore, FreeRtosError> {
+        unsafe {
+            match glue::create_counting_semaphore_static(
+                max as UBaseType,
+                initial as UBaseType,
+                buffer.as_mut_ptr(),
+            ) {
+                Some(semaphore) => Ok(Semaphore {
+                    semaphore,
+                }),
+                None => Err(FreeRtosError::OutOfMemory),
+            }
+        }
+    }
+
+    /// Lock this semaphore in a RAII fashion
+    pub fn lock(&self, max_wait: impl Into<Ticks>) -> Result<SemaphoreGuard<'_>, FreeRtosError> {
+        self.take(max_wait)?;
+        Ok(SemaphoreGuard { semaphore: self })
+    }
     
     /// Give this semaphore
     pub fn give(&self) -> Result<(), FreeRtosError> {
@@ -84,6 +111,70 @@ impl Semaphore {
             }
         }
     }
+
+    /// Take this semaphore from an interrupt context, without waiting
+    pub fn take_from_isr(&self, interrupt_context: &mut InterruptContext) -> Result<(), FreeRtosError> {
+        unsafe {
+            if glue::take_semaphore_isr(self.semaphore, interrupt_context.get_task_field_mut()) {
+                Ok(())
+            } else {
+                Err(FreeRtosError::Timeout)
+            }
+        }
+    }
+
+    /// The current count, 1 or 0 for a binary semaphore
+    pub fn get_count(&self) -> u32 {
+        unsafe { glue::get_semaphore_count(self.semaphore) as u32 }
+    }
+
+    /// Give a counting semaphore `n` times, for example to return a budget of resources.
+    /// Stops at the first give that fails because the maximum count was reached.
+    ///
+    /// Returns how often the semaphore was given, `n` unless the maximum count was reached.
+    pub fn give_n(&self, n: u32) -> u32 {
+        (0..n).take_while(|_| self.give().is_ok()).count() as u32
+    }
+
+    /// Take a counting semaphore `n` times, waiting at most `max_wait` for all of them
+    /// together. On a timeout the ones already taken are given back.
+    ///
+    /// This isn't atomic: the semaphore is taken one count at a time, so other tasks
+    /// can take or give it in between, and two tasks taking several counts each can
+    /// starve one another until one of them times out.
+    pub fn take_n(&self, n: u32, max_wait: impl Into<Ticks>) -> Result<(), FreeRtosError> {
+        let max_wait = max_wait.into().ticks;
+        let start = unsafe { glue::task_get_tick_count() };
+
+        for taken in 0..n {
+            let wait = if max_wait == MAX_DELAY {
+                MAX_DELAY
+            } else {
+                let elapsed = unsafe { glue::task_get_tick_count() }.wrapping_sub(start);
+                max_wait.saturating_sub(elapsed)
+            };
+
+            if let Err(e) = self.take(Ticks::new(wait)) {
+                for _ in 0..taken {
+                    self.give().ok();
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> QueueSetMember<'a> for Semaphore {
+    type Ready = ();
+
+    fn queue_handle(&self) -> QueueHandle {
+        self.semaphore
+    }
+
+    fn take_ready(&'a self) -> Result<(), FreeRtosError> {
+        self.take(Ticks::zero())
+    }
 }
 
 impl Drop for Semaphore {
@@ -95,14 +186,70 @@ impl Drop for Semaphore {
 }
 
 /// Holds the lock to the semaphore until we are dropped
-pub struct SemaphoreGuard {
-    semaphore: QueueHandle,
+pub struct SemaphoreGuard<'a> {
+    semaphore: &'a Semaphore,
 }
 
-impl Drop for SemaphoreGuard {
+impl<'a> Drop for SemaphoreGuard<'a> {
     fn drop(&mut self) {
-        unsafe {
-            glue::give_mutex(self.semaphore);
+        self.semaphore.give().ok();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::prelude::*;
+    use crate::queue::Queue;
+    use crate::task::*;
+
+    #[test]
+    fn guard_and_counts() {
+        let _kernel = Kernel::new();
+        let semaphore = Semaphore::new_counting(5, 5).unwrap();
+
+        {
+            let _guard = semaphore.lock(Ticks::zero()).unwrap();
+            assert_eq!(semaphore.get_count(), 4);
         }
+        assert_eq!(semaphore.get_count(), 5);
+        semaphore.take_n(3, Ticks::zero()).unwrap();
+        assert_eq!(semaphore.get_count(), 2);
+        assert_eq!(semaphore.take_n(3, Ticks::zero()), Err(FreeRtosError::Timeout));
+        assert_eq!(semaphore.get_count(), 2);
+        assert_eq!(semaphore.give_n(4), 3);
+        assert_eq!(semaphore.get_count(), 5);
+
+        let mut context = InterruptContext::new();
+        semaphore.take_from_isr(&mut context).unwrap();
+        drop(context);
+        assert_eq!(semaphore.get_count(), 4);
+    }
+
+    #[test]
+    fn take_n_waits_for_all_counts() {
+        let kernel = Kernel::new();
+        let semaphore = Arc::new(Semaphore::new_counting(5, 1).unwrap());
+        let out = Arc::new(Queue::new(2).unwrap());
+
+        let (s, o) = (semaphore.clone(), out.clone());
+        Task::new()
+            .start(move || {
+                o.send(s.take_n(3, Ticks::new(10)).is_ok(), Ticks::zero()).unwrap();
+                o.send(s.take_n(3, Ticks::new(10)).is_ok(), Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.advance_ticks(5);
+        assert_eq!(semaphore.give_n(1), 1);
+        kernel.advance_ticks(5);
+        assert_eq!(out.receive(Ticks::zero()), Ok(false));
+        // The two counts given back were taken again by the second `take_n`.
+        assert_eq!(semaphore.get_count(), 0);
+        assert_eq!(semaphore.give_n(1), 1);
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(true));
+        assert_eq!(semaphore.get_count(), 0);
     }
 }
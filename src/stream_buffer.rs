@@ -0,0 +1,569 @@
+use crate::base::*;
+use crate::glue;
+use crate::isr::*;
+use crate::prelude::*;
+use crate::units::*;
+
+unsafe impl Send for StreamBuffer {}
+
+/// A buffer of bytes, written by a single task or interrupt and read by a single task
+/// or interrupt.
+///
+/// A blocked reader is woken up once at least the trigger level of bytes is available.
+/// The buffer can't be shared, [`split()`](Self::split) it to write and read from
+/// different tasks. An interrupt can get its half through an
+/// [`ExclusiveData`](crate::ExclusiveData).
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct StreamBuffer {
+    handle: StreamBufferHandle,
+}
+
+impl StreamBuffer {
+    /// Create a new stream buffer holding up to `size` bytes.
+    pub fn new(size: usize, trigger_level: usize) -> Result<StreamBuffer, FreeRtosError> {
+        StreamBuffer::create(size, trigger_level, false)
+    }
+
+    fn create(
+        size: usize,
+        trigger_level: usize,
+        is_message_buffer: bool,
+    ) -> Result<StreamBuffer, FreeRtosError> {
+        if size == 0 {
+            return Err(FreeRtosError::InvalidQueueSize);
+        }
+
+        match unsafe { glue::stream_buffer_create(size, trigger_level, is_message_buffer) } {
+            Some(handle) => Ok(StreamBuffer { handle }),
+            None => Err(FreeRtosError::OutOfMemory),
+        }
+    }
+
+    /// Write `data`, waiting for enough space for all of it. Once the time is up as much
+    /// as fits is written. Returns the number of bytes written.
+    pub fn send(&self, data: &[u8], max_wait: impl Into<Ticks>) -> Result<usize, FreeRtosError> {
+        let sent = unsafe {
+            glue::stream_buffer_send(
+                self.handle,
+                data.as_ptr() as *const _,
+                data.len(),
+                max_wait.into().ticks,
+            )
+        };
+
+        if sent == 0 && !data.is_empty() {
+            Err(FreeRtosError::QueueSendTimeout)
+        } else {
+            Ok(sent)
+        }
+    }
+
+    /// Write as much of `data` as fits, from an interrupt. Returns the number of bytes
+    /// written.
+    pub fn send_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        data: &[u8],
+    ) -> Result<usize, FreeRtosError> {
+        let sent = unsafe {
+            glue::stream_buffer_send_isr(
+                self.handle,
+                data.as_ptr() as *const _,
+                data.len(),
+                context.get_task_field_mut(),
+            )
+        };
+
+        if sent == 0 && !data.is_empty() {
+            Err(FreeRtosError::QueueFull)
+        } else {
+            Ok(sent)
+        }
+    }
+
+    /// Wait for bytes to be available and read up to `buf.len()` of them. Returns the
+    /// number of bytes read.
+    pub fn receive(
+        &self,
+        buf: &mut [u8],
+        max_wait: impl Into<Ticks>,
+    ) -> Result<usize, FreeRtosError> {
+        let received = unsafe {
+            glue::stream_buffer_receive(
+                self.handle,
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+                max_wait.into().ticks,
+            )
+        };
+
+        if received == 0 && !buf.is_empty() {
+            Err(FreeRtosError::QueueReceiveTimeout)
+        } else {
+            Ok(received)
+        }
+    }
+
+    /// Read up to `buf.len()` bytes from an interrupt. Returns the number of bytes read.
+    pub fn receive_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        buf: &mut [u8],
+    ) -> Result<usize, FreeRtosError> {
+        let received = unsafe {
+            glue::stream_buffer_receive_isr(
+                self.handle,
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+                context.get_task_field_mut(),
+            )
+        };
+
+        if received == 0 && !buf.is_empty() {
+            Err(FreeRtosError::QueueReceiveTimeout)
+        } else {
+            Ok(received)
+        }
+    }
+
+    /// Change how many bytes have to be available before a blocked reader is woken up.
+    /// Fails if the level is larger than the buffer.
+    pub fn set_trigger_level(&self, trigger_level: usize) -> Result<(), FreeRtosError> {
+        if unsafe { glue::stream_buffer_set_trigger_level(self.handle, trigger_level) } {
+            Ok(())
+        } else {
+            Err(FreeRtosError::InvalidQueueSize)
+        }
+    }
+
+    /// The number of bytes that can be read.
+    pub fn bytes_available(&self) -> usize {
+        unsafe { glue::stream_buffer_bytes_available(self.handle) }
+    }
+
+    /// The number of bytes that can be written.
+    pub fn spaces_available(&self) -> usize {
+        unsafe { glue::stream_buffer_spaces_available(self.handle) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unsafe { glue::stream_buffer_is_empty(self.handle) }
+    }
+
+    pub fn is_full(&self) -> bool {
+        unsafe { glue::stream_buffer_is_full(self.handle) }
+    }
+
+    /// Discard the contents. Only possible while no task is blocked on the buffer,
+    /// returns whether it was reset.
+    pub fn reset(&self) -> bool {
+        unsafe { glue::stream_buffer_reset(self.handle) }
+    }
+
+    /// Split into the writing and the reading half, each can be moved to another task but
+    /// not shared. The buffer is deleted once both are dropped.
+    pub fn split(self) -> (StreamSender, StreamReceiver) {
+        // Not shared, each half only uses its own side of the buffer.
+        #[allow(clippy::arc_with_non_send_sync)]
+        let buffer = Arc::new(self);
+        (
+            StreamSender {
+                buffer: buffer.clone(),
+            },
+            StreamReceiver { buffer },
+        )
+    }
+}
+
+// Only the half holds its side of the buffer, the reference count is atomic.
+unsafe impl Send for StreamSender {}
+unsafe impl Send for StreamReceiver {}
+
+/// The writing half of a [`StreamBuffer`].
+#[derive(Debug)]
+pub struct StreamSender {
+    buffer: Arc<StreamBuffer>,
+}
+
+impl StreamSender {
+    /// See [`StreamBuffer::send()`].
+    pub fn send(&self, data: &[u8], max_wait: impl Into<Ticks>) -> Result<usize, FreeRtosError> {
+        self.buffer.send(data, max_wait)
+    }
+
+    /// See [`StreamBuffer::send_from_isr()`].
+    pub fn send_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        data: &[u8],
+    ) -> Result<usize, FreeRtosError> {
+        self.buffer.send_from_isr(context, data)
+    }
+
+    /// The number of bytes that can be written.
+    pub fn spaces_available(&self) -> usize {
+        self.buffer.spaces_available()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.buffer.is_full()
+    }
+}
+
+/// The reading half of a [`StreamBuffer`].
+#[derive(Debug)]
+pub struct StreamReceiver {
+    buffer: Arc<StreamBuffer>,
+}
+
+impl StreamReceiver {
+    /// See [`StreamBuffer::receive()`].
+    pub fn receive(
+        &self,
+        buf: &mut [u8],
+        max_wait: impl Into<Ticks>,
+    ) -> Result<usize, FreeRtosError> {
+        self.buffer.receive(buf, max_wait)
+    }
+
+    /// See [`StreamBuffer::receive_from_isr()`].
+    pub fn receive_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        buf: &mut [u8],
+    ) -> Result<usize, FreeRtosError> {
+        self.buffer.receive_from_isr(context, buf)
+    }
+
+    /// See [`StreamBuffer::set_trigger_level()`].
+    pub fn set_trigger_level(&self, trigger_level: usize) -> Result<(), FreeRtosError> {
+        self.buffer.set_trigger_level(trigger_level)
+    }
+
+    /// The number of bytes that can be read.
+    pub fn bytes_available(&self) -> usize {
+        self.buffer.bytes_available()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+impl Drop for StreamBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            glue::stream_buffer_delete(self.handle);
+        }
+    }
+}
+
+/// A buffer of variable length messages, written by a single task or interrupt and read
+/// by a single task or interrupt.
+///
+/// Every message also takes up the space of a `usize` for its length. Like a
+/// [`StreamBuffer`] it can't be shared, [`split()`](Self::split) it instead.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct MessageBuffer {
+    buffer: StreamBuffer,
+}
+
+impl MessageBuffer {
+    /// Create a new message buffer holding up to `size` bytes, including the lengths.
+    pub fn new(size: usize) -> Result<MessageBuffer, FreeRtosError> {
+        Ok(MessageBuffer {
+            buffer: StreamBuffer::create(size, 0, true)?,
+        })
+    }
+
+    /// Write a message, waiting for enough space for it.
+    pub fn send(&self, message: &[u8], max_wait: impl Into<Ticks>) -> Result<(), FreeRtosError> {
+        let sent = unsafe {
+            glue::stream_buffer_send(
+                self.buffer.handle,
+                message.as_ptr() as *const _,
+                message.len(),
+                max_wait.into().ticks,
+            )
+        };
+
+        if sent == message.len() {
+            Ok(())
+        } else {
+            Err(FreeRtosError::QueueSendTimeout)
+        }
+    }
+
+    /// Write a message from an interrupt.
+    pub fn send_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        message: &[u8],
+    ) -> Result<(), FreeRtosError> {
+        let sent = unsafe {
+            glue::stream_buffer_send_isr(
+                self.buffer.handle,
+                message.as_ptr() as *const _,
+                message.len(),
+                context.get_task_field_mut(),
+            )
+        };
+
+        if sent == message.len() {
+            Ok(())
+        } else {
+            Err(FreeRtosError::QueueFull)
+        }
+    }
+
+    /// Wait for a message and copy it into `buf`. Returns its length.
+    ///
+    /// A message that doesn't fit into `buf` is left in the buffer and this fails, see
+    /// [`next_message_len()`](Self::next_message_len).
+    pub fn receive(
+        &self,
+        buf: &mut [u8],
+        max_wait: impl Into<Ticks>,
+    ) -> Result<usize, FreeRtosError> {
+        let received = unsafe {
+            glue::stream_buffer_receive(
+                self.buffer.handle,
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+                max_wait.into().ticks,
+            )
+        };
+
+        if received == 0 && self.next_message_len() > buf.len() {
+            Err(FreeRtosError::InvalidQueueSize)
+        } else if received == 0 {
+            Err(FreeRtosError::QueueReceiveTimeout)
+        } else {
+            Ok(received)
+        }
+    }
+
+    /// Copy a message into `buf` from an interrupt. Returns its length.
+    pub fn receive_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        buf: &mut [u8],
+    ) -> Result<usize, FreeRtosError> {
+        let received = unsafe {
+            glue::stream_buffer_receive_isr(
+                self.buffer.handle,
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+                context.get_task_field_mut(),
+            )
+        };
+
+        if received == 0 && self.next_message_len() > buf.len() {
+            Err(FreeRtosError::InvalidQueueSize)
+        } else if received == 0 {
+            Err(FreeRtosError::QueueReceiveTimeout)
+        } else {
+            Ok(received)
+        }
+    }
+
+    /// The length of the next message, 0 if there is none.
+    pub fn next_message_len(&self) -> usize {
+        unsafe { glue::message_buffer_next_length(self.buffer.handle) }
+    }
+
+    /// The number of bytes that can be written, a message also needs the space for its
+    /// length.
+    pub fn spaces_available(&self) -> usize {
+        self.buffer.spaces_available()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.buffer.is_full()
+    }
+
+    /// Discard all messages. Only possible while no task is blocked on the buffer,
+    /// returns whether it was reset.
+    pub fn reset(&self) -> bool {
+        self.buffer.reset()
+    }
+
+    /// Split into the writing and the reading half, each can be moved to another task but
+    /// not shared. The buffer is deleted once both are dropped.
+    pub fn split(self) -> (MessageSender, MessageReceiver) {
+        // Not shared, each half only uses its own side of the buffer.
+        #[allow(clippy::arc_with_non_send_sync)]
+        let buffer = Arc::new(self);
+        (
+            MessageSender {
+                buffer: buffer.clone(),
+            },
+            MessageReceiver { buffer },
+        )
+    }
+}
+
+unsafe impl Send for MessageSender {}
+unsafe impl Send for MessageReceiver {}
+
+/// The writing half of a [`MessageBuffer`].
+#[derive(Debug)]
+pub struct MessageSender {
+    buffer: Arc<MessageBuffer>,
+}
+
+impl MessageSender {
+    /// See [`MessageBuffer::send()`].
+    pub fn send(&self, message: &[u8], max_wait: impl Into<Ticks>) -> Result<(), FreeRtosError> {
+        self.buffer.send(message, max_wait)
+    }
+
+    /// See [`MessageBuffer::send_from_isr()`].
+    pub fn send_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        message: &[u8],
+    ) -> Result<(), FreeRtosError> {
+        self.buffer.send_from_isr(context, message)
+    }
+
+    /// The number of bytes that can be written, a message also needs the space for its
+    /// length.
+    pub fn spaces_available(&self) -> usize {
+        self.buffer.spaces_available()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.buffer.is_full()
+    }
+}
+
+/// The reading half of a [`MessageBuffer`].
+#[derive(Debug)]
+pub struct MessageReceiver {
+    buffer: Arc<MessageBuffer>,
+}
+
+impl MessageReceiver {
+    /// See [`MessageBuffer::receive()`].
+    pub fn receive(
+        &self,
+        buf: &mut [u8],
+        max_wait: impl Into<Ticks>,
+    ) -> Result<usize, FreeRtosError> {
+        self.buffer.receive(buf, max_wait)
+    }
+
+    /// See [`MessageBuffer::receive_from_isr()`].
+    pub fn receive_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        buf: &mut [u8],
+    ) -> Result<usize, FreeRtosError> {
+        self.buffer.receive_from_isr(context, buf)
+    }
+
+    /// The length of the next message, 0 if there is none.
+    pub fn next_message_len(&self) -> usize {
+        self.buffer.next_message_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::critical::ExclusiveData;
+    use crate::mock::Kernel;
+    use crate::queue::Queue;
+    use crate::task::*;
+
+    #[test]
+    fn stream_reader_waits_for_trigger_level() {
+        let kernel = Kernel::new();
+        let (stream, reader) = StreamBuffer::new(8, 4).unwrap().split();
+        let out = Arc::new(Queue::new(2).unwrap());
+
+        let o = out.clone();
+        Task::new()
+            .start(move || {
+                let mut buf = [0; 16];
+                o.send(reader.receive(&mut buf, Ticks::infinite()), Ticks::zero()).unwrap();
+                o.send(reader.receive(&mut buf, Ticks::new(2)), Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.run();
+        assert_eq!(stream.send(&[1, 2], Ticks::zero()), Ok(2));
+        kernel.run();
+        assert!(out.receive(Ticks::zero()).is_err());
+        assert_eq!(stream.send(&[3, 4, 5, 6, 7, 8, 9], Ticks::zero()), Ok(6));
+        assert!(stream.is_full());
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(Ok(8)));
+        assert_eq!(stream.spaces_available(), 8);
+        kernel.advance_ticks(2);
+        assert_eq!(
+            out.receive(Ticks::zero()),
+            Ok(Err(FreeRtosError::QueueReceiveTimeout))
+        );
+    }
+
+    #[test]
+    fn message_too_long_for_the_buffer_stays() {
+        let _kernel = Kernel::new();
+        let messages = MessageBuffer::new(32).unwrap();
+
+        messages.send(&[9; 5], Ticks::zero()).unwrap();
+        assert_eq!(messages.next_message_len(), 5);
+        let mut buf = [0; 8];
+        assert_eq!(
+            messages.receive(&mut buf[..3], Ticks::zero()),
+            Err(FreeRtosError::InvalidQueueSize)
+        );
+        assert_eq!(messages.receive(&mut buf, Ticks::zero()), Ok(5));
+        assert_eq!(buf[..5], [9; 5]);
+        assert_eq!(
+            messages.receive(&mut buf, Ticks::zero()),
+            Err(FreeRtosError::QueueReceiveTimeout)
+        );
+        assert!(messages.send(&[0; 30], Ticks::zero()).is_err());
+    }
+
+    #[test]
+    fn message_halves_from_an_interrupt_to_a_task() {
+        let kernel = Kernel::new();
+        let (sender, receiver) = MessageBuffer::new(32).unwrap().split();
+        let sender = ExclusiveData::new(sender);
+        let out = Arc::new(Queue::new(1).unwrap());
+
+        let o = out.clone();
+        Task::new()
+            .start(move || {
+                let mut buf = [0; 8];
+                let len = receiver.receive(&mut buf, Ticks::infinite()).unwrap();
+                o.send((len, buf[0]), Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.run();
+        let mut context = InterruptContext::new();
+        sender
+            .lock_from_isr(&mut context)
+            .unwrap()
+            .send_from_isr(&mut context, &[7; 3])
+            .unwrap();
+        drop(context);
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok((3, 7)));
+    }
+}
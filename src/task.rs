@@ -1,9 +1,17 @@
 use core::fmt;
 use core::mem;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
 
 use crate::base::*;
+use crate::critical::{CriticalSection, SchedulerLock};
 use crate::glue;
 use crate::isr::*;
+use crate::join::JoinHandle;
+use crate::scope::Scope;
+#[cfg(feature = "thread-local-storage")]
+use crate::task_local::{drop_task_locals, take_task_locals};
 use crate::prelude::*;
 use crate::units::*;
 use crate::utils::*;
@@ -16,8 +24,56 @@ pub struct Task {
     task_handle: TaskHandle,
 }
 
+/// The thread local storage pointer that holds a reference to the closure of a task started
+/// with [`TaskBuilder::start()`] until the task returns. Reserved for this crate, set with
+/// `FREERTOS_RS_CLOSURE_TLS_INDEX` at build time. Defaults to 0, or 1 on ESP-IDF where
+/// pointer 0 belongs to pthread.
+#[cfg(feature = "thread-local-storage")]
+pub(crate) const CLOSURE_TLS_INDEX: BaseType = tls_index(
+    option_env!("FREERTOS_RS_CLOSURE_TLS_INDEX"),
+    if cfg!(feature = "esp-idf") { 1 } else { 0 },
+);
+
+#[cfg(feature = "thread-local-storage")]
+const _: () = assert!(
+    CLOSURE_TLS_INDEX < glue::NUM_TLS_POINTERS,
+    "TaskBuilder::start() keeps a closure in a thread local storage pointer, \
+     FREERTOS_RS_CLOSURE_TLS_INDEX must be below configNUM_THREAD_LOCAL_STORAGE_POINTERS. \
+     Disable the `thread-local-storage` feature if the kernel has no pointers to spare."
+);
+
+pub(crate) type BoxedClosure = Box<dyn FnOnce() + Send>;
+
+/// In [`CLOSURE_TLS_INDEX`] of a task started with [`TaskBuilder::start()`] that runs before
+/// its spawner stored its closure cell.
+#[cfg(feature = "thread-local-storage")]
+const CLOSURE_PENDING: *mut c_void = ptr::NonNull::dangling().as_ptr();
+
+/// The closure of a task started with [`TaskBuilder::start()`]. The task gets it through
+/// its argument and [`Task::delete()`] through the thread local storage pointer, whoever
+/// takes it first owns it.
+struct ClosureCell(AtomicPtr<BoxedClosure>);
+
+impl ClosureCell {
+    fn take(&self) -> Option<Box<BoxedClosure>> {
+        let closure = self.0.swap(ptr::null_mut(), Ordering::AcqRel);
+        if closure.is_null() {
+            None
+        } else {
+            Some(unsafe { Box::from_raw(closure) })
+        }
+    }
+}
+
+impl Drop for ClosureCell {
+    fn drop(&mut self) {
+        drop(self.take());
+    }
+}
+
 /// Task's execution priority. Low priority numbers denote low priority tasks.
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[repr(transparent)]
 pub struct TaskPriority(pub u8);
 
@@ -63,6 +119,127 @@ impl TaskNotification {
     }
 }
 
+/// An index into the notification array every task has, bounded by
+/// `configTASK_NOTIFICATION_ARRAY_ENTRIES`.
+///
+/// Index 0 is used by the functions without an index and by the kernel's stream and
+/// message buffers. Components that need a notification value of their own should
+/// [`reserve()`](Self::reserve) one, so two of them never end up sharing it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NotificationIndex(UBaseType);
+
+/// One bit per index, index 0 is always taken.
+static RESERVED_NOTIFICATION_INDICES: AtomicU32 = AtomicU32::new(1);
+
+impl NotificationIndex {
+    /// The index used by [`Task::notify()`] and the other functions without an index.
+    pub const DEFAULT: NotificationIndex = NotificationIndex(0);
+
+    /// Reserve an index no other caller of `reserve()` or
+    /// [`reserve_at()`](Self::reserve_at) gets. Returns `None` once all of them are taken.
+    pub fn reserve() -> Option<NotificationIndex> {
+        (1..Self::count()).find_map(Self::reserve_at)
+    }
+
+    /// Reserve the given index, if it exists and isn't taken yet.
+    pub fn reserve_at(index: UBaseType) -> Option<NotificationIndex> {
+        if index >= Self::count() {
+            return None;
+        }
+
+        let bit = 1 << index;
+        let previous = RESERVED_NOTIFICATION_INDICES.fetch_or(bit, Ordering::AcqRel);
+        if previous & bit == 0 {
+            Some(NotificationIndex(index))
+        } else {
+            None
+        }
+    }
+
+    /// The index stored in `slot`, reserved by the first caller. `None` if none was left
+    /// to reserve, the next caller tries again.
+    pub(crate) fn reserve_once(slot: &AtomicU32) -> Option<NotificationIndex> {
+        let index = slot.load(Ordering::Relaxed);
+        if index != u32::MAX {
+            return Some(NotificationIndex(index as UBaseType));
+        }
+
+        let _critical = CriticalSection::enter();
+        let index = slot.load(Ordering::Relaxed);
+        if index != u32::MAX {
+            return Some(NotificationIndex(index as UBaseType));
+        }
+        let reserved = Self::reserve()?;
+        slot.store(reserved.0 as _, Ordering::Relaxed);
+        Some(reserved)
+    }
+
+    /// The number of notification values every task has.
+    pub fn count() -> UBaseType {
+        glue::NOTIFICATION_ARRAY_ENTRIES.min(32)
+    }
+
+    pub fn index(&self) -> UBaseType {
+        self.0
+    }
+}
+
+/// The name of a task, copied out of the kernel without allocating.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct TaskName {
+    name: [u8; glue::MAX_TASK_NAME_LEN],
+    len: usize,
+}
+
+impl TaskName {
+    pub(crate) unsafe fn from_task(task: TaskHandle) -> TaskName {
+        Self::from_c_name(glue::task_get_name(task))
+    }
+
+    unsafe fn from_c_name(c_name: *const c_char) -> TaskName {
+        let bytes = str_from_c_string(&c_name).as_bytes();
+        let bytes = &bytes[..bytes.len().min(glue::MAX_TASK_NAME_LEN)];
+
+        // The kernel truncates names by bytes, which can split a character.
+        let len = match core::str::from_utf8(bytes) {
+            Ok(_) => bytes.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        let mut name = [0; glue::MAX_TASK_NAME_LEN];
+        name[..len].copy_from_slice(&bytes[..len]);
+        TaskName { name, len }
+    }
+
+    pub fn as_str(&self) -> &str {
+        unsafe { core::str::from_utf8_unchecked(&self.name[..self.len]) }
+    }
+}
+
+impl fmt::Debug for TaskName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for TaskName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl AsRef<str> for TaskName {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TaskName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 impl TaskPriority {
     fn to_freertos(&self) -> UBaseType {
         self.0 as UBaseType
@@ -77,6 +254,16 @@ macro_rules! make_mask {
     };
 }
 
+/// Storage for the control block of a statically allocated task, see
+/// [`TaskBuilder::start_static()`].
+pub struct StaticTaskBuffer(MaybeUninit<StaticTaskFfi>);
+
+impl StaticTaskBuffer {
+    pub const fn new() -> StaticTaskBuffer {
+        StaticTaskBuffer(MaybeUninit::uninit())
+    }
+}
+
 /// Helper for spawning a new task. Instantiate with [`Task::new()`].
 pub struct TaskBuilder {
     name: String,
@@ -113,6 +300,17 @@ impl TaskBuilder {
         self
     }
 
+    #[cfg(feature = "smp")]
+    fn core_affinity_mask(&self) -> UBaseType {
+        self.core_affinity_mask
+    }
+
+    /// Any core, which is the only one.
+    #[cfg(not(feature = "smp"))]
+    fn core_affinity_mask(&self) -> UBaseType {
+        UBaseType::MAX
+    }
+
     /// Start a new task that can't return a value.
     pub fn start<F>(&self, func: F) -> Result<Task, FreeRtosError>
     where
@@ -121,6 +319,78 @@ impl TaskBuilder {
         Task::spawn(&self, func)
     }
 
+    /// Start a new task that returns a value, which can be waited for with the returned
+    /// [`JoinHandle`]. Fails with [`FreeRtosError::NoNotificationIndex`] if no
+    /// notification index is left for joining.
+    pub fn spawn_joinable<F, R>(&self, func: F) -> Result<JoinHandle<R>, FreeRtosError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        JoinHandle::spawn(self, func)
+    }
+
+    /// Start a new task in `scope`, so it can borrow anything that outlives the scope.
+    pub fn spawn_scoped<'scope, 'env, F, R>(
+        &self,
+        scope: &'scope Scope<'scope, 'env>,
+        func: F,
+    ) -> Result<JoinHandle<R>, FreeRtosError>
+    where
+        F: FnOnce() -> R + Send + 'scope,
+        R: Send + 'scope,
+    {
+        scope.spawn_with(self, func)
+    }
+
+    /// Start a new task without allocating, using `tcb` for its control block and `stack`
+    /// as its stack. The stack size set on the builder is ignored, a stack longer than
+    /// `configSTACK_DEPTH_TYPE` can count fails with [`FreeRtosError::InvalidStackSize`].
+    ///
+    /// ```ignore
+    /// static mut TCB: StaticTaskBuffer = StaticTaskBuffer::new();
+    /// static mut STACK: [StackType; 512] = [0; 512];
+    ///
+    /// fn blink() {
+    ///     loop {
+    ///         CurrentTask::delay(Ticks::milliseconds(500));
+    ///     }
+    /// }
+    ///
+    /// let (tcb, stack) = unsafe { (&mut *ptr::addr_of_mut!(TCB), &mut *ptr::addr_of_mut!(STACK)) };
+    /// Task::new().name("blink").start_static(tcb, stack, blink).unwrap();
+    /// ```
+    pub fn start_static(
+        &self,
+        tcb: &'static mut StaticTaskBuffer,
+        stack: &'static mut [StackType],
+        func: fn(),
+    ) -> Result<Task, FreeRtosError> {
+        self.start_static_raw(tcb, stack, Task::static_thread_start, func as *mut c_void)
+    }
+
+    pub fn start_static_raw(
+        &self,
+        tcb: &'static mut StaticTaskBuffer,
+        stack: &'static mut [StackType],
+        func: extern "C" fn(*mut c_void),
+        arg: *mut c_void,
+    ) -> Result<Task, FreeRtosError> {
+        let task_handle = unsafe {
+            glue::create_task_static(
+                func,
+                arg,
+                &self.name,
+                stack,
+                self.priority.to_freertos(),
+                self.core_affinity_mask(),
+                tcb.0.as_mut_ptr(),
+            )?
+        };
+
+        Ok(Task { task_handle })
+    }
+
     pub fn start_raw(
         &self,
         func: extern "C" fn(*mut c_void),
@@ -135,6 +405,7 @@ impl TaskBuilder {
                     &self.name,
                     self.stack_size,
                     self.priority.to_freertos(),
+                    self.core_affinity_mask(),
                     &mut task_handle,
                 )
             };
@@ -143,11 +414,6 @@ impl TaskBuilder {
         };
 
         if success {
-            #[cfg(feature = "smp")]
-            unsafe {
-                glue::set_core_affinity(task_handle, self.core_affinity_mask);
-            }
-
             Ok(Task {
                 task_handle: unsafe { mem::transmute(task_handle) },
             })
@@ -184,18 +450,22 @@ impl Task {
         F: FnOnce() + Send + 'static,
     {
         unsafe {
-            let mut f = Box::new(f);
-            let param_ptr = f.as_mut() as *mut F as *mut _;
+            let f: Box<BoxedClosure> = Box::new(Box::new(f));
+            let cell = Arc::new(ClosureCell(AtomicPtr::new(Box::into_raw(f))));
+            // The task gets its reference through its argument, so it doesn't matter
+            // whether it runs before the other one is stored.
+            let param_ptr = Arc::into_raw(cell.clone()) as *mut c_void;
 
             let (success, task_handle) = {
                 let mut task_handle: MaybeTaskHandle = None;
 
                 let ret = glue::create_task(
-                    Self::boxed_thread_start::<F>,
+                    Self::boxed_thread_start,
                     param_ptr,
                     &builder.name,
                     builder.stack_size,
                     builder.priority.to_freertos(),
+                    builder.core_affinity_mask(),
                     &mut task_handle,
                 );
 
@@ -203,30 +473,194 @@ impl Task {
             };
 
             if success {
-                #[cfg(feature = "smp")]
-                glue::set_core_affinity(task_handle, builder.core_affinity_mask);
-
-                mem::forget(f);
+                #[cfg(feature = "thread-local-storage")]
+                Self::store_closure_cell(task_handle, cell);
                 Ok(Task {
                     task_handle: mem::transmute(task_handle),
                 })
             } else {
+                drop(Arc::from_raw(param_ptr as *const ClosureCell));
                 Err(FreeRtosError::OutOfMemory)
             }
         }
     }
 
-    extern "C" fn boxed_thread_start<F: FnOnce()>(arg: *mut c_void) {
+    extern "C" fn boxed_thread_start(arg: *mut c_void) {
         unsafe {
-            let b = Box::from_raw(arg as *mut F);
-            b();
-            glue::delete_task(None);
+            let cell = Arc::from_raw(arg as *const ClosureCell);
+            let f = cell.take();
+            drop(cell);
+            #[cfg(feature = "thread-local-storage")]
+            Self::mark_started();
+            // Only `Task::delete()` takes the closure first, after it stopped the task.
+            if let Some(f) = f {
+                f();
+            }
+            Self::delete_current();
+        }
+    }
+
+    /// Drop what the crate keeps for the current task and delete it.
+    unsafe fn delete_current() {
+        #[cfg(feature = "thread-local-storage")]
+        {
+            drop_task_locals();
+            drop(Self::release_closure_cell());
+        }
+        glue::delete_task(None);
+    }
+
+    /// Hand the thread local storage pointer its reference to the closure cell of a task
+    /// that was just created.
+    #[cfg(feature = "thread-local-storage")]
+    unsafe fn store_closure_cell(task: MaybeTaskHandle, cell: Arc<ClosureCell>) {
+        let cell = Arc::into_raw(cell) as *mut c_void;
+        let _critical = CriticalSection::enter();
+        glue::task_set_tls_pointer(task, CLOSURE_TLS_INDEX, cell);
+    }
+
+    /// Tell a task deleting itself that its spawner is yet to store its closure cell.
+    #[cfg(feature = "thread-local-storage")]
+    unsafe fn mark_started() {
+        let _critical = CriticalSection::enter();
+        if glue::task_get_tls_pointer(None, CLOSURE_TLS_INDEX).is_null() {
+            glue::task_set_tls_pointer(None, CLOSURE_TLS_INDEX, CLOSURE_PENDING);
+        }
+    }
+
+    /// Take the reference to the closure cell that [`Task::spawn()`] stored in the thread
+    /// local storage pointer of `task`, `None` if there is none.
+    #[cfg(feature = "thread-local-storage")]
+    unsafe fn take_closure_cell(task: MaybeTaskHandle) -> Option<Arc<ClosureCell>> {
+        let _critical = CriticalSection::enter();
+        let cell = glue::task_get_tls_pointer(task, CLOSURE_TLS_INDEX);
+        if cell.is_null() || cell == CLOSURE_PENDING {
+            return None;
+        }
+        glue::task_set_tls_pointer(task, CLOSURE_TLS_INDEX, ptr::null_mut());
+        Some(Arc::from_raw(cell as *const ClosureCell))
+    }
+
+    /// Take the current task's reference to its closure cell before it deletes itself. The
+    /// spawner writes to the task after starting it, so until it did the task waits.
+    #[cfg(feature = "thread-local-storage")]
+    unsafe fn release_closure_cell() -> Option<Arc<ClosureCell>> {
+        while glue::task_get_tls_pointer(None, CLOSURE_TLS_INDEX) == CLOSURE_PENDING {
+            glue::task_delay(1);
+        }
+        Self::take_closure_cell(None)
+    }
+
+    extern "C" fn static_thread_start(arg: *mut c_void) {
+        unsafe {
+            let f: fn() = mem::transmute(arg);
+            f();
+            Self::delete_current();
         }
     }
 
     /// Get the name of the current task.
     pub fn get_name(&self) -> String {
-        unsafe { str_from_c_string(&glue::task_get_name(self.task_handle)).to_owned() }
+        self.name().as_str().to_owned()
+    }
+
+    /// Get the name of the task without allocating.
+    pub fn name(&self) -> TaskName {
+        unsafe { TaskName::from_task(self.task_handle) }
+    }
+
+    /// Suspend the task until it is resumed, no matter what it's doing.
+    pub fn suspend(&self) {
+        unsafe { glue::task_suspend(Some(self.task_handle)) }
+    }
+
+    /// Resume a suspended task.
+    pub fn resume(&self) {
+        unsafe { glue::task_resume(self.task_handle) }
+    }
+
+    /// Resume a suspended task from an interrupt.
+    pub fn resume_from_isr(&self, context: &mut InterruptContext) {
+        unsafe { glue::task_resume_isr(self.task_handle, context.get_task_field_mut()) }
+    }
+
+    /// Delete the task. The task must still exist, it must not have returned from its
+    /// function yet.
+    ///
+    /// Its task locals are dropped. If it was started with [`TaskBuilder::start()`] and
+    /// never ran, its closure is dropped too. Otherwise everything the closure owned is
+    /// leaked, destructors on the task's stack never run.
+    ///
+    /// Another task is suspended first, and the task locals and the closure are taken once
+    /// it stopped running. They are dropped by the calling task.
+    ///
+    /// # Safety
+    ///
+    /// Nothing on the task's stack is dropped, so the task must not rely on that happening
+    /// before its stack goes away. In particular it must not be inside [`scope()`] or be a
+    /// task spawned in one. Another task must not be deleted before the
+    /// [`TaskBuilder::start()`] that created it returned, or be resumed while it is being
+    /// deleted.
+    ///
+    /// [`scope()`]: crate::scope()
+    pub unsafe fn delete(self) {
+        if glue::get_current_task() == Some(self.task_handle) {
+            Self::delete_current();
+            return;
+        }
+
+        glue::task_suspend(Some(self.task_handle));
+        // On SMP the task keeps running on its core until it takes the yield.
+        while self.state() == TaskState::Running {
+            core::hint::spin_loop();
+        }
+        self.drop_leftovers();
+        glue::delete_task(Some(self.task_handle));
+    }
+
+    /// Drop what the crate keeps in the thread local storage pointers of another task,
+    /// which is stopped for good.
+    #[cfg(feature = "thread-local-storage")]
+    unsafe fn drop_leftovers(&self) {
+        if let Some(cell) = Self::take_closure_cell(Some(self.task_handle)) {
+            if let Some(f) = cell.take() {
+                drop(f);
+                // The task never took the closure, so it never dropped its reference.
+                drop(Arc::from_raw(Arc::as_ptr(&cell)));
+            }
+        }
+        drop(take_task_locals(Some(self.task_handle)));
+    }
+
+    /// Nothing is kept without thread local storage.
+    #[cfg(not(feature = "thread-local-storage"))]
+    unsafe fn drop_leftovers(&self) {}
+
+    /// Make the task leave the blocked state, the call it was blocked in fails as if it
+    /// timed out. Returns whether the task was blocked.
+    pub fn abort_delay(&self) -> bool {
+        unsafe { glue::task_abort_delay(self.task_handle) }
+    }
+
+    pub fn priority(&self) -> TaskPriority {
+        TaskPriority(unsafe { glue::task_get_priority(Some(self.task_handle)) } as u8)
+    }
+
+    pub fn set_priority(&self, priority: TaskPriority) {
+        unsafe { glue::task_set_priority(Some(self.task_handle), priority.to_freertos()) }
+    }
+
+    pub fn state(&self) -> TaskState {
+        TaskState::from_freertos(unsafe { glue::task_get_state(self.task_handle) })
+    }
+
+    /// Everything the kernel knows about the task, including its stack high water mark.
+    pub fn info(&self) -> TaskStatus {
+        unsafe {
+            let mut status = MaybeUninit::uninit();
+            glue::task_get_info(self.task_handle, status.as_mut_ptr());
+            TaskStatus::from_freertos(&status.assume_init())
+        }
     }
 
     /// Try to find the task of the current execution context.
@@ -245,9 +679,14 @@ impl Task {
 
     /// Notify this task.
     pub fn notify(&self, notification: TaskNotification) {
+        self.notify_indexed(NotificationIndex::DEFAULT, notification)
+    }
+
+    /// Notify this task through the notification value at `index`.
+    pub fn notify_indexed(&self, index: NotificationIndex, notification: TaskNotification) {
         unsafe {
             let n = notification.to_freertos();
-            glue::task_notify(self.task_handle, n.0, n.1 as _);
+            glue::task_notify(self.task_handle, index.0, n.0, n.1 as _);
         }
     }
 
@@ -256,11 +695,22 @@ impl Task {
         &self,
         context: &mut InterruptContext,
         notification: TaskNotification,
+    ) -> Result<(), FreeRtosError> {
+        self.notify_indexed_from_isr(context, NotificationIndex::DEFAULT, notification)
+    }
+
+    /// Notify this task through the notification value at `index` from an interrupt.
+    pub fn notify_indexed_from_isr(
+        &self,
+        context: &mut InterruptContext,
+        index: NotificationIndex,
+        notification: TaskNotification,
     ) -> Result<(), FreeRtosError> {
         unsafe {
             let (value, action) = notification.to_freertos();
             if glue::task_notify_isr(
                 self.task_handle,
+                index.0,
                 value,
                 action as _,
                 context.get_task_field_mut(),
@@ -274,7 +724,17 @@ impl Task {
 
     /// Take the notification and either clear the notification value or decrement it by one.
     pub fn take_notification(&self, clear: bool, wait_for: impl Into<Ticks>) -> u32 {
-        unsafe { glue::task_notify_take(clear, wait_for.into().ticks) }
+        self.take_notification_indexed(NotificationIndex::DEFAULT, clear, wait_for)
+    }
+
+    /// Take the notification at `index` and either clear its value or decrement it by one.
+    pub fn take_notification_indexed(
+        &self,
+        index: NotificationIndex,
+        clear: bool,
+        wait_for: impl Into<Ticks>,
+    ) -> u32 {
+        unsafe { glue::task_notify_take(index.0, clear, wait_for.into().ticks) }
     }
 
     /// Wait for a notification to be posted.
@@ -283,10 +743,27 @@ impl Task {
         clear_bits_enter: u32,
         clear_bits_exit: u32,
         wait_for: impl Into<Ticks>,
+    ) -> Result<u32, FreeRtosError> {
+        self.wait_for_notification_indexed(
+            NotificationIndex::DEFAULT,
+            clear_bits_enter,
+            clear_bits_exit,
+            wait_for,
+        )
+    }
+
+    /// Wait for a notification to be posted to the notification value at `index`.
+    pub fn wait_for_notification_indexed(
+        &self,
+        index: NotificationIndex,
+        clear_bits_enter: u32,
+        clear_bits_exit: u32,
+        wait_for: impl Into<Ticks>,
     ) -> Result<u32, FreeRtosError> {
         let mut val = 0;
         if unsafe {
             glue::task_notify_wait(
+                index.0,
                 clear_bits_enter,
                 clear_bits_exit,
                 &mut val as *mut _,
@@ -330,6 +807,7 @@ impl CurrentTask {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SchedulerState {
     pub tasks: Vec<TaskStatus>,
     pub total_run_time: u32,
@@ -379,10 +857,14 @@ impl fmt::Display for SchedulerState {
     }
 }
 
-#[derive(Debug)]
-pub struct TaskStatus {
+/// The status of a task. The name is a [`String`], or a [`TaskName`] in a [`TaskSnapshot`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TaskStatus<Name = String> {
+    /// Not serialized, a task handle means nothing outside of the running system.
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub task: Task,
-    pub name: String,
+    pub name: Name,
     pub task_number: UBaseType,
     pub task_state: TaskState,
     pub current_priority: TaskPriority,
@@ -391,6 +873,29 @@ pub struct TaskStatus {
     pub stack_high_water_mark: StackType,
 }
 
+impl TaskStatus {
+    unsafe fn from_freertos(t: &TaskStatusFfi) -> TaskStatus {
+        TaskStatus::with_name(t, str_from_c_string(&t.pcTaskName).to_owned())
+    }
+}
+
+impl<Name> TaskStatus<Name> {
+    unsafe fn with_name(t: &TaskStatusFfi, name: Name) -> TaskStatus<Name> {
+        TaskStatus {
+            task: Task {
+                task_handle: TaskHandle::new_unchecked(t.xHandle as _),
+            },
+            name,
+            task_number: t.xTaskNumber,
+            task_state: TaskState::from_freertos(t.eCurrentState),
+            current_priority: TaskPriority(t.uxCurrentPriority as u8),
+            base_priority: TaskPriority(t.uxBasePriority as u8),
+            run_time_counter: t.ulRunTimeCounter,
+            stack_high_water_mark: t.usStackHighWaterMark as StackType,
+        }
+    }
+}
+
 pub fn start_scheduler() -> ! {
     unsafe {
         glue::start_scheduler();
@@ -409,34 +914,28 @@ pub fn get_number_of_tasks() -> usize {
     unsafe { glue::get_number_of_tasks() as usize }
 }
 
+/// Get the status of all tasks. With `tasks_len`, at most that many tasks are expected and
+/// none are returned if there are more. Otherwise this retries if tasks are created while
+/// it runs.
 pub fn get_all_tasks(tasks_len: Option<usize>) -> SchedulerState {
-    let tasks_len = tasks_len.unwrap_or(get_number_of_tasks());
-    let mut tasks = Vec::with_capacity(tasks_len as usize);
+    let mut tasks = Vec::new();
     let mut total_run_time = 0;
 
-    unsafe {
-        let filled = glue::get_system_state(
-            tasks.as_mut_ptr(),
-            tasks_len as UBaseType,
-            &mut total_run_time,
-        );
-        tasks.set_len(filled as usize);
+    loop {
+        let len = tasks_len.unwrap_or_else(get_number_of_tasks);
+        tasks.reserve(len);
+        let filled = unsafe {
+            glue::get_system_state(tasks.as_mut_ptr(), len as UBaseType, &mut total_run_time)
+        };
+        if filled > 0 || tasks_len.is_some() || get_number_of_tasks() == 0 {
+            unsafe { tasks.set_len(filled as usize) };
+            break;
+        }
     }
 
     let tasks = tasks
-        .into_iter()
-        .map(|t| TaskStatus {
-            task: Task {
-                task_handle: unsafe { TaskHandle::new_unchecked(t.xHandle as _) },
-            },
-            name: unsafe { str_from_c_string(&t.pcTaskName) }.to_owned(),
-            task_number: t.xTaskNumber,
-            task_state: unsafe { mem::transmute(t.eCurrentState as u8) },
-            current_priority: TaskPriority(t.uxCurrentPriority as u8),
-            base_priority: TaskPriority(t.uxBasePriority as u8),
-            run_time_counter: t.ulRunTimeCounter,
-            stack_high_water_mark: t.usStackHighWaterMark as u32,
-        })
+        .iter()
+        .map(|t| unsafe { TaskStatus::from_freertos(t) })
         .collect();
 
     SchedulerState {
@@ -444,3 +943,301 @@ pub fn get_all_tasks(tasks_len: Option<usize>) -> SchedulerState {
         total_run_time: total_run_time,
     }
 }
+
+/// The status of up to `N` tasks, taken without allocating. Can live in a `static`, so the
+/// state can still be dumped when memory ran out, or from the stack overflow hook with
+/// [`take_from_hook()`](Self::take_from_hook).
+///
+/// The kernel fills in nothing at all when there are more than `N` tasks. The snapshot then
+/// holds the tasks it can find without the kernel's task lists, the running task and the
+/// idle tasks, and [`omitted()`](Self::omitted) counts the others.
+///
+/// ```ignore
+/// static mut SNAPSHOT: TaskSnapshot<16> = TaskSnapshot::new();
+///
+/// let snapshot = unsafe { &mut *core::ptr::addr_of_mut!(SNAPSHOT) };
+/// for task in snapshot.take() {
+///     log::error!("{}: {:?}", task.name, task.task_state);
+/// }
+/// if snapshot.omitted() > 0 {
+///     log::error!("and {} more tasks", snapshot.omitted());
+/// }
+/// ```
+pub struct TaskSnapshot<const N: usize> {
+    raw: [MaybeUninit<TaskStatusFfi>; N],
+    tasks: [MaybeUninit<TaskStatus<TaskName>>; N],
+    len: usize,
+    omitted: usize,
+    total_run_time: u32,
+}
+
+impl<const N: usize> TaskSnapshot<N> {
+    pub const fn new() -> TaskSnapshot<N> {
+        unsafe {
+            TaskSnapshot {
+                raw: MaybeUninit::uninit().assume_init(),
+                tasks: MaybeUninit::uninit().assume_init(),
+                len: 0,
+                omitted: 0,
+                total_run_time: 0,
+            }
+        }
+    }
+
+    /// Replace the snapshot with the current status of the tasks, from a task. Suspends
+    /// the scheduler meanwhile.
+    pub fn take(&mut self) -> &[TaskStatus<TaskName>] {
+        // Deleted tasks can't be freed before their names are copied.
+        let _lock = SchedulerLock::lock();
+        loop {
+            let tasks = get_number_of_tasks();
+            if tasks > N {
+                return self.take_reachable(tasks);
+            }
+
+            let filled = unsafe {
+                glue::get_system_state(
+                    self.raw.as_mut_ptr() as *mut TaskStatusFfi,
+                    N as UBaseType,
+                    &mut self.total_run_time,
+                )
+            };
+            if filled > 0 || tasks == 0 {
+                return self.convert(filled as usize, 0);
+            }
+            // Otherwise another core created tasks since, try again.
+        }
+    }
+
+    /// Replace the snapshot with the running task and the idle tasks, and count the others.
+    /// For the stack overflow hook and other places where the scheduler can't be
+    /// suspended: neither this nor the kernel functions it calls do.
+    pub fn take_from_hook(&mut self) -> &[TaskStatus<TaskName>] {
+        self.take_reachable(get_number_of_tasks())
+    }
+
+    /// The tasks whose handles are known without the kernel's task lists.
+    fn take_reachable(&mut self, tasks: usize) -> &[TaskStatus<TaskName>] {
+        let current = unsafe { glue::get_current_task() };
+        let idle = (0..glue::NUMBER_OF_CORES)
+            .map(|core| unsafe { glue::get_idle_task_handle(core as UBaseType) });
+
+        let mut filled = 0;
+        for handle in core::iter::once(current).chain(idle).flatten() {
+            let known = self.raw[..filled]
+                .iter()
+                .any(|raw| ptr::eq(unsafe { raw.assume_init_ref().xHandle }, handle.as_ptr()));
+            if filled == N || known {
+                continue;
+            }
+            // The running task is reported as running, the idle tasks never block.
+            unsafe { glue::task_get_info_ready(handle, self.raw[filled].as_mut_ptr()) };
+            filled += 1;
+        }
+
+        self.total_run_time = 0;
+        self.convert(filled, tasks.saturating_sub(filled))
+    }
+
+    fn convert(&mut self, filled: usize, omitted: usize) -> &[TaskStatus<TaskName>] {
+        for (raw, task) in self.raw[..filled].iter().zip(self.tasks.iter_mut()) {
+            unsafe {
+                let raw = raw.assume_init_ref();
+                task.write(TaskStatus::with_name(raw, TaskName::from_c_name(raw.pcTaskName)));
+            }
+        }
+        self.len = filled;
+        self.omitted = omitted;
+        self.tasks()
+    }
+
+    /// The tasks of the last snapshot.
+    pub fn tasks(&self) -> &[TaskStatus<TaskName>] {
+        unsafe { &*(&self.tasks[..self.len] as *const _ as *const [TaskStatus<TaskName>]) }
+    }
+
+    /// How many tasks the last snapshot left out, because they didn't fit or couldn't be
+    /// found from the hook.
+    pub fn omitted(&self) -> usize {
+        self.omitted
+    }
+
+    /// The total run time when the snapshot was taken, 0 if it didn't hold every task.
+    pub fn total_run_time(&self) -> u32 {
+        self.total_run_time
+    }
+}
+
+impl<const N: usize> Default for TaskSnapshot<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::queue::Queue;
+
+    #[test]
+    fn static_task_runs() {
+        static RUNS: AtomicU32 = AtomicU32::new(0);
+        fn body() {
+            CurrentTask::delay(Ticks::new(2));
+            RUNS.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let kernel = Kernel::new();
+        let tcb = Box::leak(Box::new(StaticTaskBuffer::new()));
+        let stack = Box::leak(Box::new([0; 256]));
+        let task = Task::new().name("static").start_static(tcb, stack, body).unwrap();
+
+        assert_eq!(task.name().as_str(), "static");
+        kernel.advance_ticks(1);
+        assert_eq!(RUNS.load(Ordering::SeqCst), 0);
+        kernel.advance_ticks(1);
+        assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn static_stack_must_fit_the_depth_type() {
+        fn body() {}
+
+        let _kernel = Kernel::new();
+        let tcb = Box::leak(Box::new(StaticTaskBuffer::new()));
+        let stack = Box::leak(vec![0; u16::MAX as usize + 1].into_boxed_slice());
+        assert_eq!(
+            Task::new().start_static(tcb, stack, body).err(),
+            Some(FreeRtosError::InvalidStackSize)
+        );
+    }
+
+    #[test]
+    fn indexed_notifications_are_separate() {
+        let kernel = Kernel::new();
+        let index = NotificationIndex::reserve().unwrap();
+        assert_ne!(index, NotificationIndex::DEFAULT);
+        assert_eq!(NotificationIndex::reserve_at(index.index()), None);
+        assert_eq!(NotificationIndex::reserve_at(NotificationIndex::count()), None);
+
+        let out = Arc::new(Queue::new(2).unwrap());
+        let o = out.clone();
+        let task = Task::new()
+            .start(move || {
+                let value = Task::current()
+                    .wait_for_notification_indexed(index, 0, u32::MAX, Ticks::infinite())
+                    .unwrap();
+                o.send(value, Ticks::zero()).unwrap();
+                let taken = Task::current().take_notification(true, Ticks::zero());
+                o.send(taken, Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.run();
+        task.notify(TaskNotification::SetBits(1));
+        kernel.run();
+        assert!(out.receive(Ticks::zero()).is_err());
+        task.notify_indexed(index, TaskNotification::SetBits(6));
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(6));
+        assert_eq!(out.receive(Ticks::zero()), Ok(1));
+    }
+
+    #[test]
+    fn suspend_resume_and_priority() {
+        let kernel = Kernel::new();
+        let out = Arc::new(Queue::new(2).unwrap());
+
+        let o = out.clone();
+        let task = Task::new()
+            .name("life")
+            .priority(TaskPriority(2))
+            .start(move || {
+                CurrentTask::delay(Ticks::new(100));
+                o.send(get_tick_count(), Ticks::zero()).unwrap();
+                CurrentTask::delay(Ticks::new(5));
+                o.send(get_tick_count(), Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.run();
+        assert_eq!(task.state(), TaskState::Blocked);
+        assert_eq!(task.priority().0, 2);
+        task.set_priority(TaskPriority(4));
+        assert_eq!(task.info().current_priority.0, 4);
+        assert_eq!(task.info().name, "life");
+        assert!(task.abort_delay());
+        kernel.advance_ticks(1);
+        assert_eq!(out.receive(Ticks::zero()), Ok(0));
+
+        task.suspend();
+        assert_eq!(task.state(), TaskState::Suspended);
+        kernel.advance_ticks(10);
+        assert!(out.receive(Ticks::zero()).is_err());
+        task.resume();
+        kernel.run();
+        assert_eq!(out.receive(Ticks::zero()), Ok(11));
+    }
+
+    #[test]
+    #[cfg(feature = "thread-local-storage")]
+    fn deleting_a_task_before_it_runs_drops_its_closure() {
+        let kernel = Kernel::new();
+        let token = Arc::new(());
+
+        let held = token.clone();
+        let task = Task::new().start(move || drop(held)).unwrap();
+        assert_eq!(Arc::strong_count(&token), 2);
+        unsafe { task.delete() };
+        assert_eq!(Arc::strong_count(&token), 1);
+        kernel.run();
+    }
+
+    #[test]
+    fn snapshot_keeps_the_running_task_when_the_tasks_dont_fit() {
+        fn names(tasks: &[TaskStatus<TaskName>]) -> [Option<TaskName>; 2] {
+            let mut names = [None; 2];
+            for (name, task) in names.iter_mut().zip(tasks) {
+                *name = Some(task.name);
+            }
+            names
+        }
+
+        let kernel = Kernel::new();
+        let out = Arc::new(Queue::new(3).unwrap());
+
+        Task::new()
+            .name("first")
+            .start(|| CurrentTask::delay(Ticks::infinite()))
+            .unwrap();
+        let o = out.clone();
+        Task::new()
+            .name("second")
+            .start(move || {
+                let mut small = TaskSnapshot::<1>::new();
+                o.send((names(small.take()), small.omitted()), Ticks::zero()).unwrap();
+                let mut snapshot = TaskSnapshot::<2>::new();
+                o.send((names(snapshot.take()), snapshot.omitted()), Ticks::zero()).unwrap();
+                let hook = names(snapshot.take_from_hook());
+                o.send((hook, snapshot.omitted()), Ticks::zero()).unwrap();
+            })
+            .unwrap();
+
+        kernel.run();
+        let received: Vec<_> = core::iter::from_fn(|| out.receive(Ticks::zero()).ok())
+            .map(|(names, omitted)| (names.map(|n| n.map(|n| n.to_string())), omitted))
+            .collect();
+        let name = |n: &str| Some(n.to_string());
+        assert_eq!(
+            received,
+            [
+                ([name("second"), None], 1),
+                ([name("first"), name("second")], 0),
+                ([name("second"), None], 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use core::any::Any;
+use core::ptr;
+
+use crate::base::*;
+use crate::critical::CriticalSection;
+use crate::glue;
+use crate::prelude::*;
+use crate::task::{BoxedClosure, CLOSURE_TLS_INDEX};
+use crate::utils::tls_index;
+
+/// The thread local storage pointer that holds the task locals of a task. Reserved for
+/// this crate, set with `FREERTOS_RS_LOCALS_TLS_INDEX` at build time. Defaults to the
+/// pointer after [`CLOSURE_TLS_INDEX`].
+const LOCALS_TLS_INDEX: BaseType = tls_index(
+    option_env!("FREERTOS_RS_LOCALS_TLS_INDEX"),
+    CLOSURE_TLS_INDEX + 1,
+);
+
+const _: () = assert!(
+    LOCALS_TLS_INDEX < glue::NUM_TLS_POINTERS,
+    "task locals need a second thread local storage pointer next to the closures, \
+     FREERTOS_RS_LOCALS_TLS_INDEX must be below configNUM_THREAD_LOCAL_STORAGE_POINTERS. \
+     Disable the `thread-local-storage` feature if the kernel has no pointers to spare."
+);
+const _: () = assert!(
+    LOCALS_TLS_INDEX != CLOSURE_TLS_INDEX,
+    "FREERTOS_RS_LOCALS_TLS_INDEX must differ from FREERTOS_RS_CLOSURE_TLS_INDEX"
+);
+
+/// The value of one [`TaskLocal`], keyed by its address. The entries of a task form a list
+/// that only grows at its head, so a task stopped by [`Task::delete()`] can't leave it
+/// half updated.
+///
+/// [`Task::delete()`]: crate::Task::delete
+struct Entry {
+    key: usize,
+    value: Box<dyn Any>,
+    next: Option<Box<Entry>>,
+}
+
+/// Everything a task keeps in its thread local storage pointer for task locals.
+pub(crate) struct Locals {
+    entries: Option<Box<Entry>>,
+    /// Dropped without being called if the task is deleted, see [`store_on_delete()`].
+    on_delete: Option<BoxedClosure>,
+}
+
+impl Locals {
+    fn new() -> Self {
+        Locals {
+            entries: None,
+            on_delete: None,
+        }
+    }
+}
+
+/// Declare task local variables, every task gets its own value, created the first time
+/// the task accesses it and dropped when the task returns or is deleted.
+///
+/// ```ignore
+/// task_local! {
+///     static REQUEST_ID: Cell<u32> = Cell::new(0);
+/// }
+///
+/// REQUEST_ID.with(|id| id.set(42));
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::TaskLocal<$t> = {
+                fn init() -> $t {
+                    $init
+                }
+                $crate::TaskLocal::new(init)
+            };
+        )*
+    };
+}
+
+/// A task local variable, declared with [`task_local!`].
+///
+/// All values of a task are kept in a single thread local storage pointer. Tasks deleted
+/// with `vTaskDelete` from C code leak them, unless the `tls-deletion-callbacks` feature
+/// is enabled.
+pub struct TaskLocal<T: 'static> {
+    init: fn() -> T,
+}
+
+impl<T: 'static> TaskLocal<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> TaskLocal<T> {
+        TaskLocal { init }
+    }
+
+    /// Call `f` with the current task's value. Panics outside of a task.
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let value = unsafe { self.get_or_init() };
+        f(unsafe { &*value })
+    }
+
+    /// The value stays where it is until the task exits, even when more are added.
+    unsafe fn get_or_init(&'static self) -> *const T {
+        let key = self as *const _ as usize;
+        let mut entry = (*current_locals()).entries.as_deref();
+        while let Some(e) = entry {
+            if e.key == key {
+                return e.value.downcast_ref::<T>().unwrap();
+            }
+            entry = e.next.as_deref();
+        }
+
+        // Initializing can access other task locals, so no borrow is held meanwhile.
+        let value: Box<dyn Any> = Box::new((self.init)());
+        let ptr = value.downcast_ref::<T>().unwrap() as *const T;
+        let mut entry = Box::new(Entry {
+            key,
+            value,
+            next: None,
+        });
+
+        let locals = current_locals();
+        let _critical = CriticalSection::enter();
+        entry.next = (*locals).entries.take();
+        (*locals).entries = Some(entry);
+        ptr
+    }
+}
+
+/// The task locals of the current task, created if it has none yet.
+unsafe fn current_locals() -> *mut Locals {
+    assert!(
+        glue::get_current_task().is_some(),
+        "task locals can only be used by tasks"
+    );
+
+    let locals = glue::task_get_tls_pointer(None, LOCALS_TLS_INDEX) as *mut Locals;
+    if !locals.is_null() {
+        return locals;
+    }
+
+    let locals = Box::into_raw(Box::new(Locals::new()));
+    #[cfg(not(feature = "tls-deletion-callbacks"))]
+    glue::task_set_tls_pointer(None, LOCALS_TLS_INDEX, locals as *mut c_void);
+    #[cfg(feature = "tls-deletion-callbacks")]
+    glue::task_set_tls_pointer_with_callback(
+        None,
+        LOCALS_TLS_INDEX,
+        locals as *mut c_void,
+        delete_callback,
+    );
+    locals
+}
+
+/// Drop the task locals of the current task. Values dropped meanwhile can create new ones,
+/// so this repeats until none are left.
+pub(crate) unsafe fn drop_task_locals() {
+    while let Some(locals) = take_task_locals(None) {
+        drop(locals);
+    }
+}
+
+/// Take the task locals of `task`, or the current task if it's `None`. Another task must
+/// be stopped for good before its locals are taken, see [`Task::delete()`].
+///
+/// [`Task::delete()`]: crate::Task::delete
+pub(crate) unsafe fn take_task_locals(task: MaybeTaskHandle) -> Option<Box<Locals>> {
+    let _critical = CriticalSection::enter();
+    let locals = glue::task_get_tls_pointer(task, LOCALS_TLS_INDEX) as *mut Locals;
+    if locals.is_null() {
+        return None;
+    }
+    glue::task_set_tls_pointer(task, LOCALS_TLS_INDEX, ptr::null_mut());
+    Some(Box::from_raw(locals))
+}
+
+/// Leave `closure` with the current task's locals, to be dropped without being called if
+/// the task is deleted before it takes it back with [`take_on_delete()`].
+pub(crate) unsafe fn store_on_delete(closure: BoxedClosure) {
+    let locals = current_locals();
+    let _critical = CriticalSection::enter();
+    (*locals).on_delete = Some(closure);
+}
+
+/// Take back the closure stored with [`store_on_delete()`].
+pub(crate) unsafe fn take_on_delete() -> Option<BoxedClosure> {
+    let _critical = CriticalSection::enter();
+    let locals = glue::task_get_tls_pointer(None, LOCALS_TLS_INDEX) as *mut Locals;
+    if locals.is_null() {
+        return None;
+    }
+    (*locals).on_delete.take()
+}
+
+/// Called by the kernel when it frees a task that still has task locals.
+#[cfg(feature = "tls-deletion-callbacks")]
+unsafe extern "C" fn delete_callback(_index: i32, locals: *mut c_void) {
+    if !locals.is_null() {
+        drop(Box::from_raw(locals as *mut Locals));
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use core::cell::{Cell, RefCell};
+
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::queue::Queue;
+    use crate::task::*;
+    use crate::units::Ticks;
+
+    /// Sends its number once it is dropped.
+    struct Dropped(Arc<Queue<u32>>, u32);
+
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            self.0.send(self.1, Ticks::zero()).unwrap();
+        }
+    }
+
+    task_local! {
+        static COUNTER: Cell<u32> = Cell::new(0);
+        static DROPPED: RefCell<Option<Dropped>> = RefCell::new(None);
+    }
+
+    #[test]
+    fn every_task_has_its_own_values() {
+        let kernel = Kernel::new();
+        let out = Arc::new(Queue::new(4).unwrap());
+
+        let mut tasks = Vec::new();
+        for n in 1..=2 {
+            let o = out.clone();
+            let task = Task::new()
+                .start(move || {
+                    for _ in 0..n {
+                        COUNTER.with(|c| c.set(c.get() + 1));
+                        CurrentTask::delay(Ticks::new(1));
+                    }
+                    DROPPED.with(|d| *d.borrow_mut() = Some(Dropped(o.clone(), 100 + n)));
+                    o.send(COUNTER.with(|c| c.get()), Ticks::zero()).unwrap();
+                    if n == 2 {
+                        CurrentTask::delay(Ticks::infinite());
+                    }
+                })
+                .unwrap();
+            tasks.push(task);
+        }
+
+        kernel.advance_ticks(5);
+        assert_eq!(out.receive(Ticks::zero()), Ok(1));
+        assert_eq!(out.receive(Ticks::zero()), Ok(101));
+        assert_eq!(out.receive(Ticks::zero()), Ok(2));
+        assert!(out.receive(Ticks::zero()).is_err());
+
+        // Deleting the task drops its values too.
+        unsafe { tasks.pop().unwrap().delete() };
+        assert_eq!(out.receive(Ticks::zero()), Ok(102));
+    }
+}
@@ -1,4 +1,5 @@
 use core::mem;
+use core::mem::MaybeUninit;
 
 use crate::base::*;
 use crate::glue;
@@ -15,7 +16,45 @@ unsafe impl Sync for Timer {}
 /// for that queue to get unblocked.
 pub struct Timer {
     handle: TimerHandle,
-    destructor: Option<fn(usize)>,
+    /// Whether dropping the timer deletes it.
+    delete_on_drop: bool,
+    /// Frees what the ID of the timer points to, called by the timer daemon task once it
+    /// has deleted the timer.
+    free_state: Option<PendedFunction>,
+}
+
+type PendedFunction = extern "C" fn(*mut c_void, u32);
+
+/// Storage for the kernel's bookkeeping of a statically allocated timer and its name, see
+/// [`TimerBuilder::create_static()`].
+pub struct StaticTimerBuffer {
+    timer: MaybeUninit<StaticTimerFfi>,
+    name: TimerName,
+}
+
+impl StaticTimerBuffer {
+    pub const fn new() -> StaticTimerBuffer {
+        StaticTimerBuffer {
+            timer: MaybeUninit::uninit(),
+            name: [0; glue::MAX_TASK_NAME_LEN],
+        }
+    }
+}
+
+/// The kernel only keeps a pointer to the name of a timer, so it's stored along with the
+/// timer. Truncated to the length of a task name.
+type TimerName = [u8; glue::MAX_TASK_NAME_LEN];
+
+fn copy_name(name: &str, buf: &mut TimerName) {
+    let size = (buf.len() - 1).min(name.len());
+    buf[..size].copy_from_slice(&name.as_bytes()[..size]);
+    buf[size] = 0;
+}
+
+/// What the ID of a timer created with [`TimerBuilder::create()`] points to.
+struct TimerState<F> {
+    name: TimerName,
+    callback: F,
 }
 
 /// Helper builder for a new software timer.
@@ -59,6 +98,35 @@ impl TimerBuilder {
             callback,
         )
     }
+
+    /// Try to create the new timer without allocating. The callback is a plain function,
+    /// as there is nowhere to keep a closure.
+    ///
+    /// Note that the newly created timer must be started.
+    pub fn create_static(
+        &self,
+        buffer: &'static mut StaticTimerBuffer,
+        callback: fn(Timer),
+    ) -> Result<Timer, FreeRtosError> {
+        copy_name(&self.name, &mut buffer.name);
+        unsafe {
+            match glue::timer_create_static(
+                buffer.name.as_ptr() as _,
+                self.period.ticks,
+                self.auto_reload,
+                callback as usize,
+                Timer::static_timer_callback,
+                buffer.timer.as_mut_ptr(),
+            ) {
+                Some(h) => Ok(Timer {
+                    handle: h,
+                    delete_on_drop: true,
+                    free_state: None,
+                }),
+                None => Err(FreeRtosError::OutOfMemory),
+            }
+        }
+    }
 }
 
 impl Timer {
@@ -82,21 +150,26 @@ impl Timer {
         F: Send + 'static,
     {
         unsafe {
-            let mut f = Box::new(callback);
-            let param_ptr = f.as_mut() as *mut _ as usize;
+            let mut state = Box::new(TimerState {
+                name: [0; glue::MAX_TASK_NAME_LEN],
+                callback,
+            });
+            copy_name(name, &mut state.name);
+            let param_ptr = state.as_mut() as *mut _ as usize;
 
             match glue::timer_create(
-                name,
+                state.name.as_ptr() as _,
                 period_ticks,
                 auto_reload,
                 param_ptr,
                 Self::timer_callback::<F>,
             ) {
                 Some(h) => {
-                    mem::forget(f);
+                    mem::forget(state);
                     Ok(Timer {
                         handle: h,
-                        destructor: Some(Self::timer_destructor::<F>),
+                        delete_on_drop: true,
+                        free_state: Some(Self::free_state::<F>),
                     })
                 }
                 None => Err(FreeRtosError::OutOfMemory),
@@ -108,16 +181,30 @@ impl Timer {
         unsafe {
             let timer = Timer {
                 handle,
-                destructor: None,
+                delete_on_drop: false,
+                free_state: None,
+            };
+
+            let state = timer.get_id().unwrap() as *mut TimerState<F>;
+            ((*state).callback)(timer);
+        }
+    }
+
+    extern "C" fn static_timer_callback(handle: TimerHandle) {
+        unsafe {
+            let timer = Timer {
+                handle,
+                delete_on_drop: false,
+                free_state: None,
             };
 
-            let callback_ptr: *mut F = mem::transmute(timer.get_id().unwrap());
-            (*callback_ptr)(timer);
+            let callback: fn(Timer) = mem::transmute(timer.get_id().unwrap());
+            callback(timer);
         }
     }
 
-    fn timer_destructor<F: FnMut(Timer)>(callback_ptr: usize) {
-        unsafe { drop(Box::from_raw(callback_ptr as *mut F)) }
+    extern "C" fn free_state<F: FnMut(Timer)>(state: *mut c_void, _: u32) {
+        unsafe { drop(Box::from_raw(state as *mut TimerState<F>)) }
     }
 
     /// Start the timer.
@@ -166,7 +253,7 @@ impl Timer {
     ///
     /// Can be used for timers that will never be changed and don't need to stay in scope.
     pub unsafe fn detach(mut self) {
-        self.destructor = None;
+        self.delete_on_drop = false;
     }
 
     fn get_id(&self) -> Result<usize, FreeRtosError> {
@@ -176,16 +263,89 @@ impl Timer {
 
 impl Drop for Timer {
     fn drop(&mut self) {
-        if let Some(destructor) = self.destructor {
-            unsafe {
-                if let Ok(callback_ptr) = self.get_id() {
-                    // free the memory
-                    destructor(callback_ptr);
-                }
+        if !self.delete_on_drop {
+            return;
+        }
 
-                // todo: configurable timeout?
-                glue::timer_delete(self.handle, Ticks::milliseconds(1000).ticks);
+        unsafe {
+            let state = glue::timer_get_id(self.handle);
+            // todo: configurable timeout?
+            let block_time = Ticks::milliseconds(1000).ticks;
+
+            // The kernel uses the name and the callback until the daemon task has processed
+            // the delete, the call pended after it runs once it has. If either can't be
+            // queued, the state is leaked rather than freed under a live timer.
+            if !glue::timer_delete(self.handle, block_time) {
+                return;
+            }
+            if let Some(free_state) = self.free_state {
+                glue::timer_pend_function_call(free_state, state as *mut c_void, block_time);
             }
         }
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::queue::Queue;
+    use crate::task::get_tick_count;
+
+    #[test]
+    fn closure_timer_reloads_until_dropped() {
+        let kernel = Kernel::new();
+        let hits = Arc::new(Queue::new(10).unwrap());
+
+        let h = hits.clone();
+        let timer = Timer::new(Ticks::new(3))
+            .set_name("a timer name longer than any task name")
+            .create(move |_| h.send(get_tick_count(), Ticks::zero()).unwrap())
+            .unwrap();
+        timer.start(Ticks::zero()).unwrap();
+
+        kernel.advance_ticks(10);
+        drop(timer);
+        kernel.advance_ticks(10);
+        let ticks: Vec<_> = core::iter::from_fn(|| hits.receive(Ticks::zero()).ok()).collect();
+        assert_eq!(ticks, [3, 6, 9]);
+    }
+
+    #[test]
+    fn dropping_the_timer_frees_its_closure() {
+        let _kernel = Kernel::new();
+        let token = Arc::new(());
+
+        let t = token.clone();
+        let timer = Timer::new(Ticks::new(3))
+            .create(move |_| drop(t.clone()))
+            .unwrap();
+        assert_eq!(Arc::strong_count(&token), 2);
+        drop(timer);
+        assert_eq!(Arc::strong_count(&token), 1);
+    }
+
+    #[test]
+    fn static_timer_fires_once() {
+        static HITS: AtomicU32 = AtomicU32::new(0);
+        fn on_timer(_timer: Timer) {
+            HITS.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let kernel = Kernel::new();
+        let buffer = Box::leak(Box::new(StaticTimerBuffer::new()));
+        let timer = Timer::new(Ticks::new(2))
+            .set_name("static")
+            .set_auto_reload(false)
+            .create_static(buffer, on_timer)
+            .unwrap();
+        timer.start(Ticks::zero()).unwrap();
+
+        kernel.advance_ticks(1);
+        assert_eq!(HITS.load(Ordering::SeqCst), 0);
+        kernel.advance_ticks(10);
+        assert_eq!(HITS.load(Ordering::SeqCst), 1);
+    }
+}
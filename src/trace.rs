@@ -0,0 +1,543 @@
+//! Records context switches and queue operations into a RAM ring buffer, and turns them
+//! into a Chrome trace that [Perfetto](https://ui.perfetto.dev) can show as a timeline.
+//!
+//! The kernel reports them through the trace macros of `FreeRTOSConfig.h`, which have to
+//! call `freertos_rs_trace`. The configuration of `freertos-posix-sys` defines them when the
+//! `trace` feature is enabled, other configurations need the same lines:
+//!
+//! ```c
+//! extern void freertos_rs_trace( unsigned char ucEvent, void * pvObject );
+//! #define traceTASK_SWITCHED_IN()                      freertos_rs_trace( 0, pxCurrentTCB )
+//! #define traceTASK_SWITCHED_OUT()                     freertos_rs_trace( 1, pxCurrentTCB )
+//! #define traceQUEUE_SEND( pxQueue )                   freertos_rs_trace( 2, pxQueue )
+//! #define traceQUEUE_SEND_FROM_ISR( pxQueue )          freertos_rs_trace( 3, pxQueue )
+//! #define traceQUEUE_RECEIVE( pxQueue )                freertos_rs_trace( 4, pxQueue )
+//! #define traceQUEUE_RECEIVE_FROM_ISR( pxQueue )       freertos_rs_trace( 5, pxQueue )
+//! #define traceBLOCKING_ON_QUEUE_SEND( pxQueue )       freertos_rs_trace( 6, pxQueue )
+//! #define traceBLOCKING_ON_QUEUE_RECEIVE( pxQueue )    freertos_rs_trace( 7, pxQueue )
+//! #define traceTASK_DELAY()                            freertos_rs_trace( 8, pxCurrentTCB )
+//! #define traceTASK_DELAY_UNTIL( xTimeToWake )         freertos_rs_trace( 9, pxCurrentTCB )
+//! ```
+//!
+//! Semaphores and mutexes are queues to the kernel, giving and taking them shows up as
+//! sending and receiving.
+//!
+//! ```ignore
+//! static TRACE: TraceBuffer<1024> = TraceBuffer::new();
+//!
+//! trace::start(&TRACE);
+//! // ...
+//! trace::stop();
+//!
+//! let state = get_all_tasks(None);
+//! let names: Vec<_> = state
+//!     .tasks
+//!     .iter()
+//!     .map(|t| (t.task.clone().into_raw().as_ptr() as usize, t.name.as_str()))
+//!     .collect();
+//! let mut json = String::new();
+//! trace::write_chrome_trace(&mut json, &TRACE.events(), TICK_RATE_HZ, &names).unwrap();
+//! ```
+
+use alloc::collections::BTreeMap;
+use core::fmt::{self, Write};
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicPtr, AtomicU32, AtomicU8, AtomicUsize, Ordering};
+
+use crate::base::*;
+use crate::export::write_json_string;
+use crate::glue;
+use crate::prelude::*;
+
+/// What happened, the values are the ones the trace macros pass to `freertos_rs_trace`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EventKind {
+    TaskSwitchedIn = 0,
+    TaskSwitchedOut = 1,
+    QueueSend = 2,
+    QueueSendFromIsr = 3,
+    QueueReceive = 4,
+    QueueReceiveFromIsr = 5,
+    BlockingOnQueueSend = 6,
+    BlockingOnQueueReceive = 7,
+    TaskDelay = 8,
+    TaskDelayUntil = 9,
+}
+
+impl EventKind {
+    fn from_u8(kind: u8) -> Option<EventKind> {
+        Some(match kind {
+            0 => EventKind::TaskSwitchedIn,
+            1 => EventKind::TaskSwitchedOut,
+            2 => EventKind::QueueSend,
+            3 => EventKind::QueueSendFromIsr,
+            4 => EventKind::QueueReceive,
+            5 => EventKind::QueueReceiveFromIsr,
+            6 => EventKind::BlockingOnQueueSend,
+            7 => EventKind::BlockingOnQueueReceive,
+            8 => EventKind::TaskDelay,
+            9 => EventKind::TaskDelayUntil,
+            _ => return None,
+        })
+    }
+
+    /// Whether the kernel passes the task instead of a queue.
+    fn is_task_event(self) -> bool {
+        matches!(
+            self,
+            EventKind::TaskSwitchedIn
+                | EventKind::TaskSwitchedOut
+                | EventKind::TaskDelay
+                | EventKind::TaskDelayUntil
+        )
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            EventKind::TaskSwitchedIn => "switched in",
+            EventKind::TaskSwitchedOut => "switched out",
+            EventKind::QueueSend => "queue send",
+            EventKind::QueueSendFromIsr => "queue send from ISR",
+            EventKind::QueueReceive => "queue receive",
+            EventKind::QueueReceiveFromIsr => "queue receive from ISR",
+            EventKind::BlockingOnQueueSend => "blocking on queue send",
+            EventKind::BlockingOnQueueReceive => "blocking on queue receive",
+            EventKind::TaskDelay => "delay",
+            EventKind::TaskDelayUntil => "delay until",
+        }
+    }
+}
+
+/// A recorded event, read back with [`TraceBuffer::events()`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// Counts every event since the buffer was started, gaps are events that were
+    /// overwritten.
+    pub sequence: usize,
+    /// The time from the clock set with [`set_clock()`], the tick count by default.
+    pub timestamp: u32,
+    pub kind: EventKind,
+    /// The handle of the running task, 0 if there is none.
+    pub task: usize,
+    /// The handle of the queue, 0 for task events.
+    pub object: usize,
+}
+
+/// `seq` of a slot that is being written.
+const WRITING: usize = usize::MAX;
+
+/// One event, written and read like a sequence lock so readers can tell torn events apart.
+struct Slot {
+    /// 0 if the slot is empty, otherwise the sequence number of the event plus one.
+    seq: AtomicUsize,
+    timestamp: AtomicU32,
+    kind: AtomicU8,
+    task: AtomicUsize,
+    object: AtomicUsize,
+}
+
+impl Slot {
+    const fn new() -> Slot {
+        Slot {
+            seq: AtomicUsize::new(0),
+            timestamp: AtomicU32::new(0),
+            kind: AtomicU8::new(0),
+            task: AtomicUsize::new(0),
+            object: AtomicUsize::new(0),
+        }
+    }
+
+    fn write(&self, event: &TraceEvent) {
+        self.seq.store(WRITING, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.timestamp.store(event.timestamp, Ordering::Relaxed);
+        self.kind.store(event.kind as u8, Ordering::Relaxed);
+        self.task.store(event.task, Ordering::Relaxed);
+        self.object.store(event.object, Ordering::Relaxed);
+        self.seq.store(event.sequence + 1, Ordering::Release);
+    }
+
+    fn read(&self) -> Option<TraceEvent> {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq == 0 || seq == WRITING {
+            return None;
+        }
+
+        let timestamp = self.timestamp.load(Ordering::Relaxed);
+        let kind = self.kind.load(Ordering::Relaxed);
+        let task = self.task.load(Ordering::Relaxed);
+        let object = self.object.load(Ordering::Relaxed);
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) != seq {
+            return None;
+        }
+
+        Some(TraceEvent {
+            sequence: seq - 1,
+            timestamp,
+            kind: EventKind::from_u8(kind)?,
+            task,
+            object,
+        })
+    }
+}
+
+/// Ring buffer for the last `N` events. Recording never blocks or allocates, once the
+/// buffer is full the oldest events are overwritten.
+#[repr(C)]
+pub struct TraceBuffer<const N: usize> {
+    /// Lets the recorder find the slots without knowing `N`.
+    capacity: usize,
+    head: AtomicUsize,
+    slots: [Slot; N],
+}
+
+impl<const N: usize> TraceBuffer<N> {
+    pub const fn new() -> TraceBuffer<N> {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: Slot = Slot::new();
+        TraceBuffer {
+            capacity: N,
+            head: AtomicUsize::new(0),
+            slots: [EMPTY; N],
+        }
+    }
+
+    /// The events in the buffer, oldest first. Events written while this runs can be
+    /// missing, best call it after [`stop()`].
+    pub fn events(&self) -> Vec<TraceEvent> {
+        let mut events: Vec<_> = self.slots.iter().filter_map(Slot::read).collect();
+        events.sort_by_key(|e| e.sequence);
+        events
+    }
+
+    /// The number of events recorded so far, including overwritten ones.
+    pub fn recorded(&self) -> usize {
+        self.head.load(Ordering::Relaxed)
+    }
+}
+
+impl<const N: usize> Default for TraceBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The buffer events are recorded into, null while stopped. Points to a `TraceBuffer` of
+/// any size, only its `capacity` and `head` are accessed through the type.
+static BUFFER: AtomicPtr<TraceBuffer<0>> = AtomicPtr::new(ptr::null_mut());
+
+/// Start recording into `buffer`, replacing the buffer of a previous [`start()`].
+pub fn start<const N: usize>(buffer: &'static TraceBuffer<N>) {
+    let buffer = buffer as *const TraceBuffer<N> as *mut TraceBuffer<0>;
+    BUFFER.store(buffer, Ordering::Release);
+}
+
+/// Stop recording, the events stay in the buffer.
+pub fn stop() {
+    BUFFER.store(ptr::null_mut(), Ordering::Release);
+}
+
+/// The clock set with [`set_clock()`], 0 for the tick count.
+static CLOCK: AtomicUsize = AtomicUsize::new(0);
+
+/// Timestamp events with `clock` instead of the tick count, e.g. a free running hardware
+/// timer. It is called from interrupts and while the kernel switches tasks.
+pub fn set_clock(clock: fn() -> u32) {
+    CLOCK.store(clock as usize, Ordering::Release);
+}
+
+fn now() -> u32 {
+    match CLOCK.load(Ordering::Acquire) {
+        0 => unsafe { glue::task_get_tick_count_from_isr() as u32 },
+        clock => {
+            let clock: fn() -> u32 = unsafe { mem::transmute(clock) };
+            clock()
+        }
+    }
+}
+
+pub(crate) fn record(kind: EventKind, task: usize, object: usize, timestamp: u32) {
+    let buffer = BUFFER.load(Ordering::Acquire) as *const TraceBuffer<0>;
+    if buffer.is_null() {
+        return;
+    }
+
+    unsafe {
+        let capacity = (*buffer).capacity;
+        if capacity == 0 {
+            return;
+        }
+        // Derived from `buffer`, which covers all `N` slots.
+        let slots = ptr::addr_of!((*buffer).slots) as *const Slot;
+
+        let sequence = (*buffer).head.fetch_add(1, Ordering::Relaxed);
+        (*slots.add(sequence % capacity)).write(&TraceEvent {
+            sequence,
+            timestamp,
+            kind,
+            task,
+            object,
+        });
+    }
+}
+
+/// Called by the trace macros of the kernel.
+///
+/// # Safety
+///
+/// `object` has to be the task or queue the kernel passes for `event`.
+#[no_mangle]
+pub unsafe extern "C" fn freertos_rs_trace(event: u8, object: *mut c_void) {
+    let kind = match EventKind::from_u8(event) {
+        Some(kind) => kind,
+        None => return,
+    };
+
+    if kind.is_task_event() {
+        record(kind, object as usize, 0, now());
+    } else {
+        let task = glue::get_current_task().map_or(0, |t| t.as_ptr() as usize);
+        record(kind, task, object as usize, now());
+    }
+}
+
+/// Write `events` as Chrome trace event JSON, every task becomes a thread that is busy
+/// while it was switched in, everything else shows up as instant events on it.
+///
+/// `clock_hz` is the frequency of the clock the timestamps are from, the tick rate unless
+/// [`set_clock()`] was used. `task_names` maps task handles to names, tasks without one are
+/// named by their handle.
+pub fn write_chrome_trace<W: Write>(
+    out: &mut W,
+    events: &[TraceEvent],
+    clock_hz: u32,
+    task_names: &[(usize, &str)],
+) -> fmt::Result {
+    let name_of = |task: usize| {
+        task_names
+            .iter()
+            .find(|&&(t, _)| t == task)
+            .map(|&(_, name)| name)
+    };
+
+    out.write_str("{\"traceEvents\":[\n")?;
+    out.write_str("{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":1,\"args\":{\"name\":\"FreeRTOS\"}}")?;
+
+    let mut tasks: Vec<usize> = events.iter().map(|e| e.task).filter(|&t| t != 0).collect();
+    tasks.sort_unstable();
+    tasks.dedup();
+    for &task in &tasks {
+        write!(
+            out,
+            ",\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":",
+            task
+        )?;
+        match name_of(task) {
+            Some(name) => write_json_string(out, name)?,
+            None => write!(out, "\"task {:#x}\"", task)?,
+        }
+        out.write_str("}}")?;
+    }
+
+    // Timestamps wrap, they are unwrapped by adding up the differences. An event can be
+    // timestamped a little before the one recorded ahead of it, by another core or an
+    // interrupt in between, it is put at the time of that one instead of far ahead. So
+    // events must not be more than half the range of the clock apart.
+    let mut time = 0u64;
+    let mut previous = None;
+    // The tasks that are switched in, with the time they were.
+    let mut running = BTreeMap::new();
+    for event in events {
+        let timestamp = event.timestamp;
+        let delta = timestamp.wrapping_sub(previous.unwrap_or(timestamp)) as i32;
+        if delta > 0 || previous.is_none() {
+            time += delta as u64;
+            previous = Some(timestamp);
+        }
+
+        match event.kind {
+            EventKind::TaskSwitchedIn => {
+                running.insert(event.task, time);
+            }
+            EventKind::TaskSwitchedOut => {
+                if let Some(start) = running.remove(&event.task) {
+                    write_slice(out, event.task, name_of(event.task), start, time, clock_hz)?;
+                }
+            }
+            kind => {
+                out.write_str(",\n{\"name\":")?;
+                write_json_string(out, kind.name())?;
+                write!(out, ",\"ph\":\"i\",\"s\":\"t\",\"pid\":1,\"tid\":{},\"ts\":", event.task)?;
+                write_micros(out, time, clock_hz)?;
+                if event.object != 0 {
+                    write!(out, ",\"args\":{{\"queue\":\"{:#x}\"}}", event.object)?;
+                }
+                out.write_char('}')?;
+            }
+        }
+    }
+
+    // Still running when the trace ended.
+    for (task, start) in running {
+        write_slice(out, task, name_of(task), start, time, clock_hz)?;
+    }
+
+    out.write_str("\n]}\n")
+}
+
+/// A complete event for the time `task` was switched in.
+fn write_slice<W: Write>(
+    out: &mut W,
+    task: usize,
+    name: Option<&str>,
+    start: u64,
+    end: u64,
+    clock_hz: u32,
+) -> fmt::Result {
+    out.write_str(",\n{\"name\":")?;
+    write_json_string(out, name.unwrap_or("running"))?;
+    write!(out, ",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":", task)?;
+    write_micros(out, start, clock_hz)?;
+    out.write_str(",\"dur\":")?;
+    write_micros(out, end - start, clock_hz)?;
+    out.write_char('}')
+}
+
+/// Chrome traces are in microseconds, written with nanosecond precision.
+fn write_micros<W: Write>(out: &mut W, clock: u64, clock_hz: u32) -> fmt::Result {
+    let nanos = clock as u128 * 1_000_000_000 / clock_hz.max(1) as u128;
+    write!(out, "{}.{:03}", nanos / 1000, nanos % 1000)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::Kernel;
+    use crate::queue::Queue;
+    use crate::task::*;
+    use crate::units::*;
+
+    fn handle(task: &Task) -> usize {
+        task.clone().into_raw().as_ptr() as usize
+    }
+
+    #[test]
+    fn queue_operations_and_switches_are_recorded() {
+        static TRACE: TraceBuffer<64> = TraceBuffer::new();
+        let kernel = Kernel::new();
+        let queue = Arc::new(Queue::new(1).unwrap());
+
+        let (rx, tx) = (queue.clone(), queue.clone());
+        let receiver = Task::new()
+            .priority(TaskPriority(2))
+            .start(move || {
+                rx.receive(Ticks::infinite()).unwrap();
+            })
+            .unwrap();
+        let sender = Task::new()
+            .start(move || tx.send(1u8, Ticks::zero()).unwrap())
+            .unwrap();
+
+        start(&TRACE);
+        kernel.run();
+        stop();
+
+        let events = TRACE.events();
+        assert_eq!(events.len(), TRACE.recorded());
+        assert!(events.iter().enumerate().all(|(i, e)| e.sequence == i));
+
+        let queue_events: Vec<_> = events
+            .iter()
+            .filter(|e| e.object != 0)
+            .map(|e| (e.kind, e.task))
+            .collect();
+        assert_eq!(
+            queue_events,
+            [
+                (EventKind::QueueSend, handle(&sender)),
+                (EventKind::QueueReceive, handle(&receiver)),
+            ]
+        );
+        assert!(events
+            .iter()
+            .any(|e| e.kind == EventKind::TaskSwitchedIn && e.task == handle(&receiver)));
+    }
+
+    #[test]
+    fn full_buffer_keeps_the_latest_events() {
+        static TRACE: TraceBuffer<4> = TraceBuffer::new();
+        let kernel = Kernel::new();
+        let queue = Arc::new(Queue::new(8).unwrap());
+
+        let q = queue.clone();
+        Task::new()
+            .start(move || {
+                for i in 0..8u8 {
+                    q.send(i, Ticks::zero()).unwrap();
+                }
+            })
+            .unwrap();
+
+        start(&TRACE);
+        kernel.run();
+        stop();
+
+        let recorded = TRACE.recorded();
+        assert!(recorded > 8);
+        let sequences: Vec<_> = TRACE.events().iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, (recorded - 4..recorded).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn chrome_trace_of_a_switch_and_a_send() {
+        let event = |sequence, timestamp, kind, object| TraceEvent {
+            sequence,
+            timestamp,
+            kind,
+            task: 0x10,
+            object,
+        };
+        let events = [
+            event(0, u32::MAX, EventKind::TaskSwitchedIn, 0),
+            event(1, 1, EventKind::QueueSend, 0x20),
+            event(2, 3, EventKind::TaskSwitchedOut, 0),
+        ];
+
+        let mut json = String::new();
+        write_chrome_trace(&mut json, &events, 1000, &[(0x10, "tx\"1")]).unwrap();
+        assert_eq!(
+            json,
+            "{\"traceEvents\":[\n\
+             {\"name\":\"process_name\",\"ph\":\"M\",\"pid\":1,\"args\":{\"name\":\"FreeRTOS\"}},\n\
+             {\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":16,\"args\":{\"name\":\"tx\\\"1\"}},\n\
+             {\"name\":\"queue send\",\"ph\":\"i\",\"s\":\"t\",\"pid\":1,\"tid\":16,\"ts\":2000.000,\
+             \"args\":{\"queue\":\"0x20\"}},\n\
+             {\"name\":\"tx\\\"1\",\"ph\":\"X\",\"pid\":1,\"tid\":16,\"ts\":0.000,\"dur\":4000.000}\n\
+             ]}\n"
+        );
+    }
+
+    #[test]
+    fn chrome_trace_keeps_events_out_of_order_in_place() {
+        let event = |sequence, timestamp| TraceEvent {
+            sequence,
+            timestamp,
+            kind: EventKind::TaskDelay,
+            task: 0x10,
+            object: 0,
+        };
+        let events = [event(0, 10), event(1, 12), event(2, 11), event(3, 13)];
+
+        let mut json = String::new();
+        write_chrome_trace(&mut json, &events, 1_000_000, &[]).unwrap();
+        let times: Vec<_> = json
+            .split("\"ts\":")
+            .skip(1)
+            .map(|s| s.split(|c| c == ',' || c == '}').next().unwrap())
+            .collect();
+        assert_eq!(times, ["0.000", "2.000", "2.000", "3.000"]);
+    }
+}
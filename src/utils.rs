@@ -1,6 +1,26 @@
 use crate::base::*;
 use crate::glue;
 
+/// The thread local storage pointer the crate uses for something, set at build time with
+/// the environment variable that `value` was read from, or `default`.
+#[cfg(feature = "thread-local-storage")]
+pub(crate) const fn tls_index(value: Option<&'static str>, default: BaseType) -> BaseType {
+    let digits = match value {
+        Some(value) => value.as_bytes(),
+        None => return default,
+    };
+    assert!(!digits.is_empty(), "thread local storage index is empty");
+
+    let mut index: BaseType = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        assert!(digits[i].is_ascii_digit(), "thread local storage index isn't a number");
+        index = index * 10 + (digits[i] - b'0') as BaseType;
+        i += 1;
+    }
+    index
+}
+
 pub unsafe fn str_from_c_string(c_str: &*const c_char) -> &str {
     let len = glue::strlen(*c_str);
     core::str::from_utf8_unchecked(core::slice::from_raw_parts(*c_str as *const _, len))